@@ -1,17 +1,17 @@
 //! Please see the `inline-c` crate to learn more.
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Group, Literal, TokenStream, TokenTree};
 use quote::quote;
+use std::{env, fs::OpenOptions, io::Write};
 
 /// Execute a C program and return a `Result` of
 /// `inline_c::Assert`. See examples inside the `inline-c` crate.
 #[proc_macro]
 pub fn assert_c(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = TokenStream::from(input);
-    let input_as_string = reconstruct(input);
-
-    quote!(
-        inline_c::run(inline_c::Language::C, #input_as_string).map_err(|e| panic!("{}", e)).unwrap()
+    expand(
+        quote!(inline_c::Language::C),
+        TokenStream::from(input),
+        false,
     )
     .into()
 }
@@ -20,17 +20,375 @@ pub fn assert_c(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 /// `inline_c::Assert`. See examples inside the `inline-c` crate.
 #[proc_macro]
 pub fn assert_cxx(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = TokenStream::from(input);
-    let input_as_string = reconstruct(input);
+    expand(
+        quote!(inline_c::Language::Cxx),
+        TokenStream::from(input),
+        false,
+    )
+    .into()
+}
+
+/// Like `assert_c!`, but the program is compiled as a shared object
+/// and run inside the test process, in a forked child. See examples
+/// inside the `inline-c` crate.
+#[proc_macro]
+pub fn assert_c_inproc(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(
+        quote!(inline_c::Language::C),
+        TokenStream::from(input),
+        true,
+    )
+    .into()
+}
 
-    quote!(
-        inline_c::run(inline_c::Language::Cxx, #input_as_string).map_err(|e| panic!("{}", e)).unwrap()
+/// Like `assert_cxx!`, but the program is compiled as a shared object
+/// and run inside the test process, in a forked child. See examples
+/// inside the `inline-c` crate.
+#[proc_macro]
+pub fn assert_cxx_inproc(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand(
+        quote!(inline_c::Language::Cxx),
+        TokenStream::from(input),
+        true,
     )
     .into()
 }
 
-fn reconstruct(input: TokenStream) -> String {
-    use proc_macro2::{Delimiter, Spacing, TokenTree::*};
+/// Execute a CUDA program, compiled with `nvcc`, and return a
+/// `Result` of `inline_c::Assert`. If `nvcc` or a GPU is missing, the
+/// enclosing function returns early, i.e. the test is skipped. See
+/// examples inside the `inline-c` crate.
+#[cfg(feature = "cuda")]
+#[proc_macro]
+pub fn assert_cuda(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let expansion = expand(
+        quote!(inline_c::Language::Cuda),
+        TokenStream::from(input),
+        false,
+    );
+
+    quote!({
+        if !inline_c::cuda_available() {
+            eprintln!("CUDA is not available (no `nvcc` or no GPU), skipping the program");
+
+            return Default::default();
+        }
+
+        #expansion
+    })
+    .into()
+}
+
+/// The C source the other macros compile for the given tokens, as a
+/// string literal, e.g. to test that a snippet round-trips. See
+/// examples inside the `inline-c` crate.
+#[proc_macro]
+pub fn reconstruct(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let source = program_source(TokenStream::from(input));
+
+    quote!(#source).into()
+}
+
+fn expand(language: TokenStream, input: TokenStream, in_process: bool) -> TokenStream {
+    let (statements, input) = split_statements(input);
+
+    // A program given as a string literal, e.g. `r#"…"#`, is kept as
+    // is, with its comments.
+    let (input_as_string, debug) = match string_literal(&input) {
+        Some(literal) => (quote!(#literal), TokenStream::new()),
+        None => {
+            let source = program_source(input);
+            let debug = debug_reconstruction(&source);
+
+            (quote!(#source), debug)
+        }
+    };
+    let mut export_consts = None;
+    let mut callbacks = None;
+
+    for (name, arguments) in statements {
+        match name.as_str() {
+            "export_consts" => export_consts = Some(arguments),
+            _ => callbacks = Some(arguments),
+        }
+    }
+
+    // The Rust constants are evaluated at the call site, and prepended
+    // to the program as `#define`s.
+    let program = match export_consts {
+        Some(consts) => {
+            quote!(&format!("{}{}", inline_c::export_consts!(#consts), #input_as_string))
+        }
+        None => quote!(#input_as_string),
+    };
+
+    let callbacks = match callbacks {
+        Some(callbacks) => quote!(&inline_c::callbacks!(#callbacks)),
+        None => quote!(&[]),
+    };
+
+    let run = if in_process {
+        quote!(inline_c::run_in_process)
+    } else {
+        quote!(inline_c::run_with_callbacks)
+    };
+
+    quote!({
+        #debug
+
+        #run(
+            #language,
+            #program,
+            Some(
+                inline_c::Location::new(module_path!(), file!(), env!("CARGO_MANIFEST_DIR"))
+                    .out_dir(option_env!("OUT_DIR"))
+                    .debug_assertions(cfg!(debug_assertions)),
+            ),
+            #callbacks,
+        )
+        .map_err(|e| panic!("{}", e))
+        .unwrap()
+    })
+}
+
+/// Show the C source reconstructed from the tokens, to report the
+/// reconstruction bugs with the exact text. The
+/// `INLINE_C_RS_DEBUG_MACROS` environment variable, read when the
+/// macros are expanded, is either `1`, to emit the source as a
+/// compiler warning, or the path of a file the source is appended to.
+fn debug_reconstruction(source: &str) -> TokenStream {
+    emit_reconstruction(env::var("INLINE_C_RS_DEBUG_MACROS").ok(), source)
+}
+
+/// Emit `source` as configured by `value`, the value of the
+/// `INLINE_C_RS_DEBUG_MACROS` environment variable, if any.
+fn emit_reconstruction(value: Option<String>, source: &str) -> TokenStream {
+    let value = match value {
+        Some(value) if !value.is_empty() && value != "0" => value,
+        _ => return TokenStream::new(),
+    };
+
+    if value == "1" {
+        // There is no warning API for the procedural macros on stable,
+        // but the uses of deprecated items are warned with their note.
+        let note = format!("the reconstructed C source is:\n{}", source);
+
+        return quote!(
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const inline_c_reconstructed_source: () = ();
+            #[allow(clippy::let_unit_value)]
+            let _ = inline_c_reconstructed_source;
+        );
+    }
+
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&value)
+        .and_then(|mut file| writeln!(file, "// --- inline-c ---\n{}", source));
+
+    if let Err(error) = written {
+        panic!(
+            "Failed to write the reconstructed C source to `{}`: {}",
+            value, error
+        );
+    }
+
+    TokenStream::new()
+}
+
+/// Split the leading `export_consts!(…);` and `callbacks!(…);`
+/// statements from the program, and return their names and
+/// arguments.
+fn split_statements(input: TokenStream) -> (Vec<(String, TokenStream)>, TokenStream) {
+    use proc_macro2::TokenTree::*;
+
+    let tokens = input.into_iter().collect::<Vec<_>>();
+    let mut statements = Vec::new();
+    let mut rest = tokens.as_slice();
+
+    while let [Ident(ident), Punct(bang), Group(group), Punct(semicolon), next @ ..] = rest {
+        if (*ident != "export_consts" && *ident != "callbacks")
+            || bang.as_char() != '!'
+            || semicolon.as_char() != ';'
+        {
+            break;
+        }
+
+        statements.push((ident.to_string(), group.stream()));
+        rest = next;
+    }
+
+    (statements, rest.iter().cloned().collect())
+}
+
+/// The program, if it is a single string literal.
+fn string_literal(input: &TokenStream) -> Option<Literal> {
+    let mut tokens = input.clone().into_iter();
+
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None)
+            if literal.to_string().starts_with(['"', 'r']) =>
+        {
+            Some(literal)
+        }
+        _ => None,
+    }
+}
+
+/// The value of a string literal, e.g. of a doc comment.
+fn string_value(literal: &Literal) -> Option<String> {
+    let literal = literal.to_string();
+
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+
+        return raw
+            .get(hashes + 1..raw.len().checked_sub(hashes + 1)?)
+            .map(str::to_string);
+    }
+
+    let mut value = String::new();
+    let mut characters = literal.strip_prefix('"')?.strip_suffix('"')?.chars();
+
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            value.push(character);
+
+            continue;
+        }
+
+        let escaped = match characters.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'x' => {
+                let code = characters.by_ref().take(2).collect::<String>();
+
+                char::from(u8::from_str_radix(&code, 16).ok()?)
+            }
+            'u' => {
+                let code = characters
+                    .by_ref()
+                    .skip(1)
+                    .take_while(|character| *character != '}')
+                    .collect::<String>();
+
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            // Line continuation.
+            '\n' => {
+                characters = characters.as_str().trim_start().chars();
+
+                continue;
+            }
+            character => character,
+        };
+
+        value.push(escaped);
+    }
+
+    Some(value)
+}
+
+/// The C comment of a doc comment, i.e. of the `[doc = "…"]` group of
+/// the `#[doc = "…"]` attribute produced by the Rust lexer for `///`,
+/// `//!`, `/** … */` and `/*! … */`, one `//` comment per line.
+fn doc_comment(group: &Group) -> Option<String> {
+    if group.delimiter() != Delimiter::Bracket {
+        return None;
+    }
+
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+
+    match tokens.as_slice() {
+        [TokenTree::Ident(doc), TokenTree::Punct(equal), TokenTree::Literal(literal)]
+            if *doc == "doc" && equal.as_char() == '=' =>
+        {
+            let mut comment = String::from("\n");
+
+            for line in string_value(literal)?.lines() {
+                comment.push_str("//");
+                comment.push_str(line);
+                comment.push('\n');
+            }
+
+            Some(comment)
+        }
+        _ => None,
+    }
+}
+
+/// The C source of the tokens: as written on nightly when possible,
+/// reconstructed from the tokens otherwise.
+fn program_source(input: TokenStream) -> String {
+    #[cfg(nightly)]
+    {
+        source_text(&input).unwrap_or_else(|| reconstruct_tokens(input))
+    }
+
+    #[cfg(not(nightly))]
+    {
+        reconstruct_tokens(input)
+    }
+}
+
+/// Whether two adjacent characters form a digraph (`<:`, `:>`, `<%`,
+/// `%>` and `%:`), or start a trigraph (`??`).
+fn is_digraph(first: char, second: char) -> bool {
+    matches!(
+        (first, second),
+        ('<', ':') | (':', '>') | ('<', '%') | ('%', '>') | ('%', ':') | ('?', '?')
+    )
+}
+
+/// A literal, with the `??` of the string and character literals
+/// written `?\?`, so that they never form a trigraph, whatever the
+/// language standard. Their value does not change.
+fn literal_without_trigraphs(literal: &Literal) -> String {
+    let literal = literal.to_string();
+
+    if !literal.starts_with(['"', '\'']) {
+        return literal;
+    }
+
+    let mut output = String::with_capacity(literal.len());
+    let mut previous = None;
+
+    for character in literal.chars() {
+        if character == '?' && previous == Some('?') {
+            output.push('\\');
+        }
+
+        output.push(character);
+        previous = Some(character);
+    }
+
+    output
+}
+
+/// The source of the tokens as written, byte for byte, i.e. with its
+/// spacing, its line breaks, and its literals. The spans of the first
+/// and the last tokens can only be joined on nightly. It is `None` if
+/// the tokens do not come from a single source file, e.g. when they
+/// are produced by another macro.
+#[cfg(nightly)]
+fn source_text(input: &TokenStream) -> Option<String> {
+    let mut tokens = input.clone().into_iter();
+    let first = tokens.next()?.span();
+    let last = tokens.last().map_or(first, |token| token.span());
+    let mut source = first.join(last)?.source_text()?;
+
+    // The directives end with a line break.
+    source.push('\n');
+
+    Some(source)
+}
+
+fn reconstruct_tokens(input: TokenStream) -> String {
+    use proc_macro2::{Spacing, TokenTree::*};
 
     let mut output = String::new();
     let mut iterator = input.into_iter().peekable();
@@ -42,6 +400,26 @@ fn reconstruct(input: TokenStream) -> String {
 
                 match token_value {
                     '#' => {
+                        // Doc comments, i.e. `#[doc = "…"]` or
+                        // `#![doc = "…"]`.
+                        let mut lookahead = iterator.clone();
+
+                        if let Some(Punct(bang)) = lookahead.peek() {
+                            if bang.as_char() == '!' {
+                                lookahead.next();
+                            }
+                        }
+
+                        if let Some(comment) = match lookahead.next() {
+                            Some(Group(group)) => doc_comment(&group),
+                            _ => None,
+                        } {
+                            output.push_str(&comment);
+                            iterator = lookahead;
+
+                            continue;
+                        }
+
                         output.push('\n');
                         output.push(token_value);
 
@@ -115,14 +493,23 @@ fn reconstruct(input: TokenStream) -> String {
                                 #[cfg(nightly)]
                                 {
                                     let current_line = define.span().start().line;
+                                    let mut previous_end = define.span().end();
                                     iterator.next();
-                                    output.push_str("define ");
+                                    output.push_str("define");
 
                                     loop {
                                         match iterator.peek() {
                                             Some(item) => {
                                                 if item.span().start().line == current_line {
+                                                    // The spacing is kept, e.g. a
+                                                    // function-like macro has no
+                                                    // space before its parameters.
+                                                    if item.span().start() != previous_end {
+                                                        output.push(' ');
+                                                    }
+
                                                     output.push_str(&item.to_string());
+                                                    previous_end = item.span().end();
                                                     iterator.next();
                                                 } else {
                                                     output.push('\n');
@@ -148,8 +535,25 @@ fn reconstruct(input: TokenStream) -> String {
                     _ => {
                         output.push(token_value);
 
-                        if token.spacing() == Spacing::Alone {
-                            output.push(' ');
+                        match iterator.peek() {
+                            // A floating-point number, e.g. `.5`, is
+                            // split by the Rust lexer.
+                            Some(Literal(literal))
+                                if token_value == '.'
+                                    && literal
+                                        .to_string()
+                                        .starts_with(|c: char| c.is_ascii_digit()) => {}
+
+                            Some(Punct(next))
+                                if token.spacing() == Spacing::Joint
+                                    && is_digraph(token_value, next.as_char()) =>
+                            {
+                                output.push(' ')
+                            }
+
+                            _ if token.spacing() == Spacing::Alone => output.push(' '),
+
+                            _ => {}
                         }
                     }
                 }
@@ -158,10 +562,31 @@ fn reconstruct(input: TokenStream) -> String {
             Some(Ident(ident)) => {
                 output.push_str(&ident.to_string());
                 output.push(' ');
+
+                // A linkage specification, e.g. `extern "C" { … }` or
+                // `extern "C" int f();`: the language is written as is,
+                // and a block ends its line, so that the declarations
+                // and the directives after it are not glued to it.
+                if ident == "extern" {
+                    if let Some(Literal(language)) = iterator.peek() {
+                        output.push_str(&language.to_string());
+                        output.push(' ');
+                        iterator.next();
+
+                        if let Some(Group(block)) = iterator.peek() {
+                            if block.delimiter() == Delimiter::Brace {
+                                output.push_str("{\n");
+                                output.push_str(&reconstruct_tokens(block.stream()));
+                                output.push_str("\n}\n");
+                                iterator.next();
+                            }
+                        }
+                    }
+                }
             }
 
             Some(Group(group)) => {
-                let group_output = reconstruct(group.stream());
+                let group_output = reconstruct_tokens(group.stream());
 
                 match group.delimiter() {
                     Delimiter::Parenthesis => {
@@ -190,8 +615,12 @@ fn reconstruct(input: TokenStream) -> String {
                 }
             }
 
-            Some(token) => {
-                output.push_str(&token.to_string());
+            // A space follows the literals, so that a string literal
+            // followed by a macro, e.g. `"%" PRId64`, is not a C++
+            // user-defined literal.
+            Some(Literal(literal)) => {
+                output.push_str(&literal_without_trigraphs(&literal));
+                output.push(' ');
             }
 
             None => break,
@@ -200,3 +629,213 @@ fn reconstruct(input: TokenStream) -> String {
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconstruct(input: &str) -> String {
+        reconstruct_tokens(input.parse().unwrap())
+    }
+
+    #[test]
+    fn test_reconstruct_operators() {
+        for (input, expected) in [
+            ("int *p = &s->x;", "int * p = & s -> x ;\n"),
+            ("x <<= 2;", "x <<= 2 ;\n"),
+            ("y >>= 3;", "y >>= 3 ;\n"),
+            ("z ^= 1;", "z ^= 1 ;\n"),
+            (
+                "if (a && !b || c != d) { i++; --j; }",
+                "if (a && ! b || c != d ){\ni ++;\n-- j ;\n\n}",
+            ),
+            (
+                "int printf(const char *format, ...);",
+                "int printf (const char * format , ... );\n",
+            ),
+            ("x = a->*b + c.*d;", "x = a ->* b + c .* d ;\n"),
+            ("x = .5 + 1.5e-3;", "x = .5 + 1.5e-3 ;\n"),
+            (
+                "std::vector<::std::string> v;",
+                "std :: vector < :: std :: string > v ;\n",
+            ),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_without_digraphs_and_trigraphs() {
+        for (input, expected) in [
+            ("a<:0:> = b<%c%>;", "a < : 0 : > = b < % c % >;\n"),
+            ("%:", "% : "),
+            ("e = f ?? g;", "e = f ? ? g ;\n"),
+            (r#"puts("what??!");"#, "puts (\"what?\\?!\" );\n"),
+            (r#"puts("???=");"#, "puts (\"?\\?\\?=\" );\n"),
+            ("c = '?';", "c = '?' ;\n"),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_variadic() {
+        for (input, expected) in [
+            (
+                "int sum(int count, ...);",
+                "int sum (int count , ... );\n",
+            ),
+            (
+                "va_list arguments; va_start(arguments, count); x = va_arg(arguments, unsigned long);",
+                "va_list arguments ;\nva_start (arguments , count );\nx = va_arg (arguments , unsigned long );\n",
+            ),
+            (
+                r#"printf("%-*.*s|%%", 5, 2, s);"#,
+                "printf (\"%-*.*s|%%\" , 5 , 2 , s );\n",
+            ),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_extern() {
+        for (input, expected) in [
+            (
+                r#"extern "C" { int foo(void); } int main() { return foo(); }"#,
+                "extern \"C\" {\nint foo (void );\n\n}\nint main (){\nreturn foo ();\n\n}",
+            ),
+            (
+                r#"extern "C" int foo(void);"#,
+                "extern \"C\" int foo (void );\n",
+            ),
+            (r#"extern "C++" {}"#, "extern \"C++\" {\n\n}\n"),
+            ("extern int foo;", "extern int foo ;\n"),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_literals_and_comments() {
+        for (input, expected) in [
+            (
+                r#"printf("%" PRId64 "\n", x);"#,
+                "printf (\"%\" PRId64 \"\\n\" , x );\n",
+            ),
+            (
+                "#include <stdio.h>\nint main() { return 0; }",
+                "\n#include <stdio.h>\nint main (){\nreturn 0 ;\n\n}",
+            ),
+            (
+                "/// CHECK: \"foo\"\nint x;",
+                "\n// CHECK: \"foo\"\nint x ;\n",
+            ),
+            ("/** a\n b */ int x;", "\n// a\n// b \nint x ;\n"),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_emit_reconstruction() {
+        let source = "int main() { return 0; }";
+
+        assert!(emit_reconstruction(None, source).is_empty());
+        assert!(emit_reconstruction(Some(String::from("0")), source).is_empty());
+
+        // The source is in the note of a deprecated item.
+        let warning = emit_reconstruction(Some(String::from("1")), source).to_string();
+
+        assert!(warning.contains("deprecated"), "{}", warning);
+        assert!(
+            warning.contains("the reconstructed C source is:\\nint main() { return 0; }"),
+            "{}",
+            warning
+        );
+
+        // The source is appended to the file.
+        let path = env::temp_dir().join(format!(
+            "inline-c-rs-test-emit-reconstruction-{}.c",
+            std::process::id()
+        ));
+
+        for _ in 0..2 {
+            assert!(emit_reconstruction(Some(path.display().to_string()), source).is_empty());
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            written,
+            "// --- inline-c ---\nint main() { return 0; }\n".repeat(2)
+        );
+    }
+
+    #[cfg(nightly)]
+    #[test]
+    fn test_source_text() {
+        // The spacing, the line breaks and the literals are kept.
+        let input = "int  main(void) {\n    char c = '\\0';\n\n    return  c;\n}";
+
+        assert_eq!(
+            source_text(&input.parse().unwrap()).unwrap(),
+            format!("{}\n", input)
+        );
+        assert_eq!(source_text(&TokenStream::new()), None);
+    }
+
+    #[test]
+    fn test_reconstruct_doc_comments() {
+        for (input, expected) in [
+            ("/// a\nint x;", "\n// a\nint x ;\n"),
+            ("//! a\nint x;", "\n// a\nint x ;\n"),
+            // The doc comments are line comments, which a `*/` does not
+            // end.
+            ("/// a */ b\nint x;", "\n// a */ b\nint x ;\n"),
+            ("/** a */ int x;", "\n// a \nint x ;\n"),
+            ("#[doc = \"a */\\nb\"] int x;", "\n//a */\n//b\nint x ;\n"),
+            // The other comments are dropped by the Rust lexer.
+            ("// a\nint x; /* b */", "int x ;\n"),
+        ] {
+            assert_eq!(reconstruct(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_literal_program() {
+        for input in [
+            r###"r#"// a */ "b"
+int x;"#"###,
+            r#""// a */ \"b\"\nint x;""#,
+        ] {
+            let literal = string_literal(&input.parse().unwrap()).unwrap();
+
+            assert_eq!(
+                string_value(&literal).unwrap(),
+                "// a */ \"b\"\nint x;",
+                "{}",
+                input
+            );
+
+            // The program is given as is, with its comments.
+            let expanded = expand(quote!(inline_c::Language::C), input.parse().unwrap(), false);
+
+            assert!(
+                expanded.to_string().contains(&literal.to_string()),
+                "{}",
+                expanded
+            );
+        }
+
+        // Anything else is a program made of tokens.
+        for input in ["int x;", "\"a\" \"b\"", "'a'", "42"] {
+            assert!(
+                string_literal(&input.parse().unwrap()).is_none(),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! The files produced to run a program, which are removed when the
+//! [`Assert`](crate::Assert) is dropped, unless they are persisted.
+
+use crate::footprint;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The files produced to run a program, see
+/// [`Assert::artifacts`](crate::Assert::artifacts). Some of them may
+/// not exist, e.g. the output if the compilation has failed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArtifactSet {
+    sources: Vec<PathBuf>,
+    objects: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    provenance: Option<PathBuf>,
+}
+
+impl ArtifactSet {
+    pub(crate) fn new(sources: Vec<PathBuf>, objects: Vec<PathBuf>, output: PathBuf) -> Self {
+        Self {
+            sources,
+            objects,
+            output: Some(output),
+            provenance: None,
+        }
+    }
+
+    pub(crate) fn with_provenance(mut self, provenance: PathBuf) -> Self {
+        self.provenance = Some(provenance);
+
+        self
+    }
+
+    /// The source files: the program, and the generated sources
+    /// compiled with it, e.g. for the callbacks.
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// The intermediate object files, e.g. with MSVC.
+    pub fn objects(&self) -> &[PathBuf] {
+        &self.objects
+    }
+
+    /// The executable, or the shared object.
+    pub fn output(&self) -> Option<&Path> {
+        self.output.as_deref()
+    }
+
+    /// The provenance of the program, in JSON, see
+    /// [`Assert::provenance`](crate::Assert::provenance).
+    pub fn provenance(&self) -> Option<&Path> {
+        self.provenance.as_deref()
+    }
+
+    /// All the files, in the order of [`sources`](Self::sources),
+    /// [`objects`](Self::objects), [`output`](Self::output) and
+    /// [`provenance`](Self::provenance).
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.sources
+            .iter()
+            .chain(&self.objects)
+            .chain(&self.output)
+            .chain(&self.provenance)
+            .map(PathBuf::as_path)
+    }
+
+    /// Copy the existing files into `directory`, which is created if
+    /// needed, e.g. to sign or to package them. The copies are not
+    /// removed when the `Assert` is dropped. It returns the set of
+    /// the copies.
+    pub fn persist_to<P>(&self, directory: P) -> Result<Self, Box<dyn Error>>
+    where
+        P: AsRef<Path>,
+    {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+
+        let persist = |paths: &[PathBuf]| -> Result<Vec<PathBuf>, Box<dyn Error>> {
+            let mut persisted_paths = Vec::new();
+
+            for path in paths.iter().filter(|path| path.is_file()) {
+                let file_name = path
+                    .file_name()
+                    .ok_or_else(|| format!("`{}` has no file name", path.display()))?;
+                let persisted_path = directory.join(file_name);
+
+                fs::copy(path, &persisted_path).map_err(|error| {
+                    format!(
+                        "Failed to copy `{}` to `{}`: {}",
+                        path.display(),
+                        persisted_path.display(),
+                        error
+                    )
+                })?;
+
+                footprint::record_persisted(&persisted_path);
+                persisted_paths.push(persisted_path);
+            }
+
+            Ok(persisted_paths)
+        };
+
+        Ok(Self {
+            sources: persist(&self.sources)?,
+            objects: persist(&self.objects)?,
+            output: persist(self.output.as_slice())?.pop(),
+            provenance: persist(self.provenance.as_slice())?.pop(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persist_to() {
+        let build_dir = tempfile::tempdir().unwrap();
+        let source = build_dir.path().join("program.c");
+        fs::write(&source, "int main() { return 0; }\n").unwrap();
+
+        let artifacts = ArtifactSet::new(
+            vec![source],
+            vec![build_dir.path().join("program.obj")],
+            build_dir.path().join("program"),
+        );
+        let persist_dir = tempfile::tempdir().unwrap();
+        let persisted = artifacts
+            .persist_to(persist_dir.path().join("artifacts"))
+            .unwrap();
+
+        // The missing files are skipped.
+        assert_eq!(
+            persisted.iter().collect::<Vec<_>>(),
+            [persist_dir.path().join("artifacts").join("program.c")]
+        );
+        assert_eq!(persisted.output(), None);
+    }
+}
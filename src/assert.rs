@@ -1,22 +1,1590 @@
-use std::{fs, path::PathBuf, process::Command};
+#[cfg(unix)]
+use crate::forkserver::ForkServer;
+use crate::{
+    artifacts::ArtifactSet,
+    baseline::Baseline,
+    debugger::{self, Debugger},
+    dependencies,
+    diagnostics::Diagnostics,
+    evaluate::{Check, RunReport},
+    filecheck::FileCheck,
+    footprint, guard,
+    heap::HeapProfile,
+    inproc::{self, SharedObject},
+    journal::{Journal, JournalMode},
+    observer::{self, Event},
+    permissions,
+    provenance::Provenance,
+    pty,
+    redact::Redaction,
+    report::{Outcome, Reporter},
+    sanitizer::{self, Sanitizer, SanitizerFinding, Sanitizers},
+    spawn::Spawned,
+    stderr::StderrExpectation,
+    symbols, temp,
+    trace::Trace,
+};
+use predicates::Predicate;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs, mem, panic,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// Panic with the context of `$assert`, if any, see
+/// [`Assert::context`].
+macro_rules! fail {
+    ($assert:expr, $($arguments:tt)+) => {
+        panic!("{}{}", $assert.context_prefix(), format_args!($($arguments)+))
+    };
+}
 
 /// `Assert` is a wrapper around the [`assert_cmd::assert::Assert`]
 /// struct.
 pub struct Assert {
-    command: assert_cmd::Command,
+    command: Command,
     files_to_remove: Option<Vec<PathBuf>>,
+    // The program, whose `// CHECK:` directives are parsed by
+    // `file_check` only.
+    source: String,
+    args: Vec<OsString>,
+    stdin: Option<Vec<u8>>,
+    program_command: Option<Box<dyn Fn() -> Command>>,
+    // The compiled program, absent if the compilation has failed.
+    artifact_path: Option<PathBuf>,
+    // The output of the compiler, present if the compilation has
+    // failed.
+    compiler_output: Option<Output>,
+    // The diagnostics of the compiler, unknown if the run has been
+    // replayed.
+    diagnostics: Option<Diagnostics>,
+    // The toolchain which has compiled the program, unknown if the run
+    // has been replayed.
+    provenance: Option<Provenance>,
+    artifacts: ArtifactSet,
+    journal: Option<Journal>,
+    // Removed when `Assert` is dropped, after `files_to_remove`.
+    build_dir: Option<TempDir>,
+    backtrace: bool,
+    debugger: Option<Debugger>,
+    heap_profile: Option<HeapProfile>,
+    trace: Option<Trace>,
+    sanitizers: Sanitizers,
+    // The directory of the reports of the sanitizers.
+    sanitizer_logs: Option<TempDir>,
+    stderr_expectation: StderrExpectation,
+    // The output of the last run of the program.
+    last_output: Option<Output>,
+    // The execution time of the last run of the program, unknown if
+    // the run has been replayed.
+    last_run_time: Option<Duration>,
+    reporter: Option<Reporter>,
+    baseline: Option<Baseline>,
+    shared_object: Option<SharedObject>,
+    fork_server: bool,
+    pty: bool,
+    tee: bool,
+    redaction: Redaction,
+    kill_on_drop: bool,
+    verbose: bool,
+    // The label of the program in the test.
+    context: Option<String>,
+    // The headers included by the program, if they are recorded.
+    included_headers: Option<Vec<PathBuf>>,
+    #[cfg(unix)]
+    running_fork_server: Option<ForkServer>,
 }
 
 impl Assert {
     pub(crate) fn new(command: Command, files_to_remove: Option<Vec<PathBuf>>) -> Self {
         Self {
-            command: assert_cmd::Command::from_std(command),
+            command,
             files_to_remove,
+            source: String::new(),
+            args: Vec::new(),
+            stdin: None,
+            program_command: None,
+            artifact_path: None,
+            compiler_output: None,
+            diagnostics: None,
+            provenance: None,
+            artifacts: ArtifactSet::default(),
+            journal: None,
+            build_dir: None,
+            backtrace: false,
+            debugger: None,
+            heap_profile: None,
+            trace: None,
+            sanitizers: Sanitizers::default(),
+            sanitizer_logs: None,
+            stderr_expectation: StderrExpectation::default(),
+            last_output: None,
+            last_run_time: None,
+            reporter: None,
+            baseline: None,
+            shared_object: None,
+            fork_server: false,
+            pty: false,
+            tee: false,
+            redaction: Redaction::default(),
+            kill_on_drop: true,
+            verbose: false,
+            context: None,
+            included_headers: None,
+            #[cfg(unix)]
+            running_fork_server: None,
         }
     }
 
+    pub(crate) fn with_source(mut self, source: String) -> Self {
+        self.source = source;
+
+        self
+    }
+
+    /// Set the factory of commands running the compiled program. It
+    /// is absent if the compilation has failed.
+    pub(crate) fn with_program_command<F>(mut self, program_command: F) -> Self
+    where
+        F: Fn() -> Command + 'static,
+    {
+        self.program_command = Some(Box::new(program_command));
+
+        self
+    }
+
+    pub(crate) fn with_artifact_path(mut self, artifact_path: PathBuf) -> Self {
+        self.artifact_path = Some(artifact_path);
+
+        self
+    }
+
+    pub(crate) fn with_artifacts(mut self, artifacts: ArtifactSet) -> Self {
+        self.artifacts = artifacts;
+
+        self
+    }
+
+    pub(crate) fn with_compiler_output(mut self, compiler_output: Output) -> Self {
+        self.compiler_output = Some(compiler_output);
+
+        self
+    }
+
+    pub(crate) fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+
+        self
+    }
+
+    pub(crate) fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+
+        self
+    }
+
+    /// Whether the program has compiled.
+    pub(crate) fn has_compiled(&self) -> bool {
+        self.artifact_path.is_some()
+    }
+
+    pub(crate) fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+
+        self
+    }
+
+    pub(crate) fn with_build_dir(mut self, build_dir: Option<TempDir>) -> Self {
+        self.build_dir = build_dir;
+
+        self
+    }
+
+    pub(crate) fn with_backtrace(mut self, backtrace: bool) -> Self {
+        self.backtrace = backtrace;
+
+        self
+    }
+
+    pub(crate) fn with_debugger(mut self, debugger: Option<Debugger>) -> Self {
+        self.debugger = debugger;
+
+        self
+    }
+
+    pub(crate) fn with_heap_profile(mut self, heap_profile: Option<HeapProfile>) -> Self {
+        self.heap_profile = heap_profile;
+
+        self
+    }
+
+    pub(crate) fn with_trace(mut self, trace: Option<Trace>) -> Self {
+        self.trace = trace;
+
+        self
+    }
+
+    pub(crate) fn with_sanitizers(mut self, sanitizers: Sanitizers) -> Self {
+        self.sanitizers = sanitizers;
+
+        self
+    }
+
+    pub(crate) fn with_sanitizer_logs(mut self, sanitizer_logs: Option<TempDir>) -> Self {
+        self.sanitizer_logs = sanitizer_logs;
+
+        self
+    }
+
+    pub(crate) fn with_stderr_expectation(mut self, stderr_expectation: StderrExpectation) -> Self {
+        self.stderr_expectation = stderr_expectation;
+
+        self
+    }
+
+    pub(crate) fn with_reporter(mut self, reporter: Option<Reporter>) -> Self {
+        self.reporter = reporter;
+
+        self
+    }
+
+    pub(crate) fn with_baseline(mut self, baseline: Option<Baseline>) -> Self {
+        self.baseline = baseline;
+
+        self
+    }
+
+    pub(crate) fn with_shared_object(mut self, shared_object: SharedObject) -> Self {
+        self.shared_object = Some(shared_object);
+
+        self
+    }
+
+    pub(crate) fn with_fork_server(mut self, fork_server: bool) -> Self {
+        self.fork_server = fork_server;
+
+        self
+    }
+
+    pub(crate) fn with_pty(mut self, pty: bool) -> Self {
+        self.pty = pty;
+
+        self
+    }
+
+    pub(crate) fn with_stdin(mut self, stdin: Option<Vec<u8>>) -> Self {
+        self.stdin = stdin;
+
+        self
+    }
+
+    pub(crate) fn with_tee(mut self, tee: bool) -> Self {
+        self.tee = tee;
+
+        self
+    }
+
+    pub(crate) fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+
+        self
+    }
+
+    pub(crate) fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+
+        self
+    }
+
+    pub(crate) fn with_included_headers(mut self, included_headers: Option<Vec<PathBuf>>) -> Self {
+        self.included_headers = included_headers;
+
+        self
+    }
+
+    /// Create a fresh command running the compiled program, without
+    /// the arguments or the standard input given to `self`.
+    pub(crate) fn program_command(&self) -> Option<Command> {
+        self.program_command
+            .as_ref()
+            .map(|program_command| program_command())
+    }
+
+    /// Add an argument to pass to the program.
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self.command.arg(arg);
+
+        self
+    }
+
+    /// Add multiple arguments to pass to the program.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+
+        self
+    }
+
+    /// Write `buffer` to the standard input of the program.
+    pub fn stdin<B: Into<Vec<u8>>>(&mut self, buffer: B) -> &mut Self {
+        self.stdin = Some(buffer.into());
+
+        self
+    }
+
     pub fn assert(&mut self) -> assert_cmd::assert::Assert {
-        self.command.assert()
+        // The assertions are on the output of the compiler, so that the
+        // diagnostics are in the failure messages.
+        if let Some(compiler_output) = &self.compiler_output {
+            let assert = assert_cmd::assert::Assert::new(compiler_output.clone())
+                .append_context("compilation", "failed, see the diagnostics of the compiler");
+            let assert = match self.source_excerpt() {
+                Some(excerpt) => assert.append_context("source", excerpt),
+                None => assert,
+            };
+
+            return match &self.context {
+                Some(context) => assert.append_context("context", context.clone()),
+                None => assert,
+            };
+        }
+
+        let assert = self.run_program();
+
+        if let Err(error) = self
+            .stderr_expectation
+            .check(&String::from_utf8_lossy(&assert.get_output().stderr))
+        {
+            fail!(self, "{}", error);
+        }
+
+        match &self.context {
+            Some(context) => assert.append_context("context", context.clone()),
+            None => assert,
+        }
+    }
+
+    /// Run the compiled program, or replay it, without asserting
+    /// anything.
+    fn run_program(&mut self) -> assert_cmd::assert::Assert {
+        if let Some(debugger) = self.debugger {
+            self.debug(debugger);
+        }
+
+        let assert = match self.journal.clone() {
+            Some(journal) if journal.mode() == JournalMode::Replay => {
+                self.last_run_time = None;
+
+                let output = journal
+                    .replay(&self.args, self.stdin.as_deref())
+                    .unwrap_or_else(|error| panic!("{}", error));
+
+                assert_cmd::assert::Assert::new(output)
+            }
+
+            journal => {
+                if self.verbose {
+                    self.print_run_command();
+                }
+
+                observer::notify(Event::RunStarted { args: &self.args });
+
+                let run_start = Instant::now();
+                let mut assert = if let Some(shared_object) = &self.shared_object {
+                    assert_cmd::assert::Assert::new(
+                        shared_object
+                            .run(&self.args, self.stdin.as_deref())
+                            .unwrap_or_else(|error| panic!("{}", error)),
+                    )
+                } else if self.fork_server {
+                    assert_cmd::assert::Assert::new(
+                        self.run_in_fork_server()
+                            .unwrap_or_else(|error| panic!("{}", error)),
+                    )
+                } else if self.pty {
+                    let program_command = self
+                        .program_command()
+                        .expect("The program has failed to compile");
+
+                    assert_cmd::assert::Assert::new(
+                        pty::run(program_command, &self.args, self.stdin.as_deref())
+                            .unwrap_or_else(|error| panic!("{}", error)),
+                    )
+                } else {
+                    let output = guard::output(&mut self.command, self.stdin.as_deref(), self.tee)
+                        .unwrap_or_else(|error| {
+                            panic!(
+                                "Failed to run `{}`: {}",
+                                self.redaction.command(&self.command),
+                                error
+                            )
+                        });
+                    let assert = assert_cmd::assert::Assert::new(output);
+
+                    match &self.stdin {
+                        _ if self.redaction.is_quiet() => assert,
+                        Some(stdin) => assert
+                            .append_context("command", self.redaction.command(&self.command))
+                            .append_context("stdin", String::from_utf8_lossy(stdin).into_owned()),
+                        None => {
+                            assert.append_context("command", self.redaction.command(&self.command))
+                        }
+                    }
+                };
+                let run_time = run_start.elapsed();
+                self.last_run_time = Some(run_time);
+
+                // The reports of the sanitizers are appended to the
+                // standard error, as if they were written there.
+                let sanitizer_logs = self.take_sanitizer_logs();
+
+                if !sanitizer_logs.is_empty() {
+                    let mut output = assert.get_output().clone();
+                    output.stderr.extend(sanitizer_logs.into_bytes());
+                    assert = assert_cmd::assert::Assert::new(output);
+                }
+
+                observer::notify(Event::RunFinished {
+                    output: assert.get_output(),
+                    duration: run_time,
+                });
+
+                if let Some(reporter) = &self.reporter {
+                    reporter.record(Some(run_time), Outcome::from_output(assert.get_output()));
+                }
+
+                if let Some(journal) = &journal {
+                    journal
+                        .record(&self.args, self.stdin.as_deref(), assert.get_output())
+                        .unwrap_or_else(|error| panic!("Failed to record the journal: {}", error));
+                }
+
+                if self.backtrace && debugger::has_crashed(&assert.get_output().status) {
+                    assert = assert.append_context("backtrace", self.crash_backtrace());
+                }
+
+                assert
+            }
+        };
+
+        self.last_output = Some(assert.get_output().clone());
+
+        assert
+    }
+
+    /// Run the program, and evaluate all the `checks` without
+    /// panicking, so that a custom harness can collect all the
+    /// failures of a program, instead of stopping at the first one.
+    /// The `expect_stderr` directive is also evaluated. It panics only
+    /// if the program cannot run at all.
+    ///
+    /// ```rust
+    /// use inline_c::{assert_c, Check};
+    ///
+    /// fn test_evaluate() {
+    ///     let report = (assert_c! {
+    ///         #include <stdio.h>
+    ///
+    ///         int main() {
+    ///             printf("Hello, World!\n");
+    ///
+    ///             return 1;
+    ///         }
+    ///     })
+    ///     .evaluate(vec![
+    ///         Check::Success,
+    ///         Check::Stdout(String::from("Hello, World!\n")),
+    ///         Check::StderrContains(String::from("warning")),
+    ///     ]);
+    ///
+    ///     assert_eq!(report.findings().len(), 2);
+    ///     assert_eq!(report.findings()[0].check, Some(Check::Success));
+    /// }
+    ///
+    /// # fn main() { test_evaluate() }
+    /// ```
+    pub fn evaluate<I>(&mut self, checks: I) -> RunReport
+    where
+        I: IntoIterator<Item = Check>,
+    {
+        if let Some(compiler_output) = &self.compiler_output {
+            return RunReport::compile_failure(format!(
+                "The program has failed to compile:\n\n{}{}",
+                String::from_utf8_lossy(&compiler_output.stderr),
+                self.source_excerpt()
+                    .map(|excerpt| format!("\n{}", excerpt))
+                    .unwrap_or_default()
+            ));
+        }
+
+        let output = self.run_program().get_output().clone();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+        RunReport::new(
+            output,
+            checks,
+            self.stderr_expectation.check(&stderr),
+            (!self.sanitizers.is_empty()).then(|| sanitizer::findings(&stderr)),
+        )
+    }
+
+    /// Whether the program spawned by [`Assert::spawn`] is killed,
+    /// with the processes it has spawned, when [`Spawned`] is
+    /// dropped, e.g. when the test panics. It is on by default; turn
+    /// it off to let the program outlive the test, e.g. a server
+    /// shared by several tests. The programs run by the assertions
+    /// are always killed if the test panics while they run.
+    pub fn kill_on_drop(&mut self, kill_on_drop: bool) -> &mut Self {
+        self.kill_on_drop = kill_on_drop;
+
+        self
+    }
+
+    /// Spawn the program, to assert its output incrementally while it
+    /// runs, see [`Spawned::expect_line_within`]. The arguments and
+    /// the standard input given to `self` are given to the program.
+    pub fn spawn(&mut self) -> Spawned {
+        let mut program_command = self
+            .program_command()
+            .expect("The program has failed to compile");
+        program_command.args(&self.args);
+
+        Spawned::new(
+            program_command,
+            self.stdin.as_deref(),
+            self.kill_on_drop,
+            self.sanitizer_logs
+                .as_ref()
+                .map(|sanitizer_logs| sanitizer_logs.path().to_path_buf()),
+        )
+        .unwrap_or_else(|error| panic!("Failed to spawn the program: {}", error))
+    }
+
+    /// Read, and remove, the reports of the sanitizers written since
+    /// the last run, so that they are not reported twice.
+    fn take_sanitizer_logs(&self) -> String {
+        self.sanitizer_logs
+            .as_ref()
+            .map(|sanitizer_logs| sanitizer::take_logs(sanitizer_logs.path()))
+            .unwrap_or_default()
+    }
+
+    fn print_run_command(&self) {
+        if self.shared_object.is_some() {
+            eprintln!(
+                "inline-c: run in-process {:?} with {:?}",
+                self.artifact_path.as_deref().unwrap_or_else(|| "".as_ref()),
+                self.args
+            );
+        } else if let Some(mut program_command) = self.program_command() {
+            program_command.args(&self.args);
+
+            eprintln!(
+                "inline-c: run{} {}",
+                if self.fork_server {
+                    " in the fork server"
+                } else if self.pty {
+                    " in a pseudo-terminal"
+                } else {
+                    ""
+                },
+                self.redaction.command(&program_command)
+            );
+        }
+    }
+
+    /// Run the program in the fork server, started on the first run.
+    #[cfg(unix)]
+    fn run_in_fork_server(&mut self) -> Result<Output, Box<dyn Error>> {
+        if self.running_fork_server.is_none() {
+            let program_command = self
+                .program_command()
+                .ok_or("The program has failed to compile")?;
+
+            self.running_fork_server = Some(ForkServer::start(program_command)?);
+        }
+
+        self.running_fork_server
+            .as_mut()
+            .unwrap()
+            .run(&self.args, self.stdin.as_deref())
+    }
+
+    #[cfg(not(unix))]
+    fn run_in_fork_server(&mut self) -> Result<Output, Box<dyn Error>> {
+        Err("The fork server is only supported on Unix".into())
+    }
+
+    /// Run the program under `debugger` interactively, and skip the
+    /// assertions.
+    fn debug(&self, debugger: Debugger) -> ! {
+        let mut program_command = self
+            .program_command()
+            .expect("The program has failed to compile, there is nothing to debug");
+        program_command.args(&self.args);
+
+        let status = debugger
+            .debug(&program_command)
+            .unwrap_or_else(|error| panic!("Failed to run `{}`: {}", debugger.name(), error));
+
+        fail!(
+            self,
+            "The program has been run under `{}` (exited with {}), assertions are skipped",
+            debugger.name(),
+            status
+        );
+    }
+
+    /// Assert the program like [`Assert::assert`], but with `args` and
+    /// `stdin` instead of the ones given to this `Assert`, which are
+    /// restored afterwards, e.g. for each case of property testing.
+    pub(crate) fn assert_with(
+        &mut self,
+        args: Vec<OsString>,
+        stdin: Option<Vec<u8>>,
+    ) -> assert_cmd::assert::Assert {
+        let command = self.program_command().map(|mut command| {
+            command.args(&args);
+
+            mem::replace(&mut self.command, command)
+        });
+        let args = mem::replace(&mut self.args, args);
+        let stdin = mem::replace(&mut self.stdin, stdin);
+
+        // The assertion may panic, e.g. with a strict standard error,
+        // and the panic may be caught, e.g. to shrink the input.
+        let assert = panic::catch_unwind(panic::AssertUnwindSafe(|| self.assert()));
+
+        self.args = args;
+        self.stdin = stdin;
+
+        if let Some(command) = command {
+            self.command = command;
+        }
+
+        assert.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// Run the program once more with `args` and `stdin`, without
+    /// asserting anything.
+    fn output_with(
+        &self,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+    ) -> Result<Output, Box<dyn Error>> {
+        if let Some(shared_object) = &self.shared_object {
+            return shared_object.run(args, stdin);
+        }
+
+        let mut program_command = self
+            .program_command()
+            .ok_or("The program has failed to compile")?;
+        program_command.args(args);
+
+        Ok(guard::output(&mut program_command, stdin, false)?)
+    }
+
+    fn crash_backtrace(&self) -> String {
+        let mut program_command = match self.program_command() {
+            Some(program_command) => program_command,
+            None => return String::from("The program has failed to compile"),
+        };
+        program_command.args(&self.args);
+
+        match Debugger::find() {
+            Some(debugger) => debugger.backtrace(&program_command, self.stdin.as_deref()),
+            None => String::from("No debugger found, please install `gdb` or `lldb`"),
+        }
+    }
+
+    /// The peak heap consumption of the program, in bytes, read from
+    /// the report of the heap profiler. The program must have run
+    /// before, e.g. with [`Assert::success`], with the special
+    /// `heap_profiler` directive set.
+    pub fn peak_heap(&self) -> u64 {
+        self.heap_profile
+            .as_ref()
+            .expect("No heap profiler, please set the special `heap_profiler` directive")
+            .peak_heap()
+            .unwrap_or_else(|error| panic!("Failed to read the heap profile: {}", error))
+    }
+
+    /// The path to the report of the heap profiler, if the program
+    /// has run with the special `heap_profiler` directive set.
+    pub fn heap_profile_path(&self) -> Option<PathBuf> {
+        self.heap_profile
+            .as_ref()
+            .and_then(|heap_profile| heap_profile.report_path())
+    }
+
+    /// Run the program, and assert that its peak heap consumption is
+    /// below `limit` bytes.
+    pub fn peak_heap_below(&mut self, limit: u64) -> assert_cmd::assert::Assert {
+        let assert = self.assert();
+        let peak_heap = self.peak_heap();
+
+        if peak_heap >= limit {
+            fail!(
+                self,
+                "The peak heap consumption is {} bytes, expected below {} bytes",
+                peak_heap,
+                limit
+            );
+        }
+
+        assert
+    }
+
+    /// The output of the tracer (`strace` or `ltrace`), i.e. the
+    /// system or library calls of the program. The program must have
+    /// run before, e.g. with [`Assert::success`], with the special
+    /// `tracer` directive set.
+    pub fn trace_output(&self) -> String {
+        let trace = self
+            .trace
+            .as_ref()
+            .expect("No tracer, please set the special `tracer` directive");
+
+        fs::read_to_string(trace.path()).unwrap_or_else(|error| {
+            panic!("Failed to read the trace, has the program run? {}", error)
+        })
+    }
+
+    /// Run the program, and assert that ThreadSanitizer has reported no
+    /// data race. It requires the special `sanitizer` directive to
+    /// contain `thread`.
+    pub fn no_data_races(&mut self) -> assert_cmd::assert::Assert {
+        if !self.sanitizers.contains(Sanitizer::Thread) {
+            panic!("Data races are detected with `sanitizer` set to `thread` only");
+        }
+
+        let assert = self.assert();
+        let data_races =
+            sanitizer::data_races(&String::from_utf8_lossy(&assert.get_output().stderr));
+
+        if !data_races.is_empty() {
+            fail!(
+                self,
+                "ThreadSanitizer has reported {} data race(s):\n\n{}",
+                data_races.len(),
+                data_races.join("\n\n")
+            );
+        }
+
+        assert
+    }
+
+    /// The findings of the sanitizers (see the special `sanitizer`
+    /// directive) in the last run of the program, e.g. to assert on the
+    /// kind of a memory error. The program runs if it has not run yet.
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_sanitizer_findings() {
+    ///     let findings = (assert_c! {
+    ///         #inline_c_rs sanitizer: "undefined"
+    ///         #include <limits.h>
+    ///
+    ///         int main(int argc, char** argv) {
+    ///             (void) argv;
+    ///             int x = INT_MAX;
+    ///
+    ///             return x + argc;
+    ///         }
+    ///     })
+    ///     .sanitizer_findings();
+    ///
+    ///     assert_eq!(findings[0].kind, "signed integer overflow");
+    /// }
+    ///
+    /// # fn main() { test_sanitizer_findings() }
+    /// ```
+    pub fn sanitizer_findings(&mut self) -> Vec<SanitizerFinding> {
+        if self.last_output.is_none() {
+            self.assert();
+        }
+
+        sanitizer::findings(&String::from_utf8_lossy(
+            &self.last_output.as_ref().unwrap().stderr,
+        ))
+    }
+
+    /// Run the program, and assert that it does not leak memory, except
+    /// for the leaks matched by the `suppressions` file, e.g. the known
+    /// benign leaks of a C runtime.
+    ///
+    /// With the special `sanitizer` directive containing `address` or
+    /// `leak`, the file is given to LeakSanitizer through
+    /// `LSAN_OPTIONS` (e.g. `leak:function_name` lines); otherwise, the
+    /// program runs under Valgrind (`valgrind --leak-check=full`), and
+    /// the file is in the format of Valgrind.
+    pub fn no_leaks_with_suppressions<P>(&mut self, suppressions: P) -> assert_cmd::assert::Assert
+    where
+        P: AsRef<Path>,
+    {
+        let suppressions = suppressions.as_ref();
+        let suppressions = fs::canonicalize(suppressions).unwrap_or_else(|error| {
+            panic!(
+                "Failed to read the suppressions file `{}`: {}",
+                suppressions.display(),
+                error
+            )
+        });
+
+        let assert = if self.sanitizers.contains(Sanitizer::Address)
+            || self.sanitizers.contains(Sanitizer::Leak)
+        {
+            if self.fork_server || self.pty {
+                panic!("The leaks are not checked with the fork server, or a pseudo-terminal");
+            }
+
+            let options = self
+                .program_command()
+                .and_then(|command| {
+                    command
+                        .get_envs()
+                        .find(|(name, _)| *name == "LSAN_OPTIONS")
+                        .and_then(|(_, value)| {
+                            value.map(|value| value.to_string_lossy().into_owned())
+                        })
+                })
+                .or_else(|| env::var("LSAN_OPTIONS").ok());
+
+            self.command.env(
+                "LSAN_OPTIONS",
+                sanitizer::lsan_options(options.as_deref(), &suppressions),
+            );
+
+            self.assert()
+        } else {
+            self.run_in_valgrind(&suppressions)
+        };
+
+        if let Some(report) =
+            sanitizer::leak_report(&String::from_utf8_lossy(&assert.get_output().stderr))
+        {
+            fail!(self, "The program leaks memory:\n\n{}", report);
+        }
+
+        assert
+    }
+
+    fn run_in_valgrind(&mut self, suppressions: &Path) -> assert_cmd::assert::Assert {
+        let program_command = self
+            .program_command()
+            .expect("The program has failed to compile");
+
+        let mut command = Command::new("valgrind");
+        command
+            .args(["--leak-check=full", "--show-leak-kinds=definite,indirect"])
+            .arg(format!("--suppressions={}", suppressions.display()))
+            .arg(program_command.get_program())
+            .args(program_command.get_args())
+            .args(&self.args);
+
+        for (name, value) in program_command.get_envs() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+
+        let mut command = assert_cmd::Command::from_std(command);
+
+        if let Some(stdin) = &self.stdin {
+            command.write_stdin(stdin.clone());
+        }
+
+        let output = command
+            .output()
+            .unwrap_or_else(|error| panic!("Failed to run `valgrind`, is it installed? {}", error));
+        self.last_output = Some(output.clone());
+
+        assert_cmd::assert::Assert::new(output)
+    }
+
+    /// Extract values from the standard output of the program with
+    /// the regular expression `regex`: for each match, the first
+    /// capture group (or the whole match if there is no group) is
+    /// collected. The program runs if it has not run yet.
+    ///
+    /// It is useful to pull numbers computed by the C program back
+    /// into Rust, for custom assertions:
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_capture() {
+    ///     let durations = (assert_c! {
+    ///         #include <stdio.h>
+    ///
+    ///         int main() {
+    ///             printf("took 12 ms\ntook 7 ms\n");
+    ///
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .capture(r"took (\d+) ms");
+    ///
+    ///     assert_eq!(durations, ["12", "7"]);
+    /// }
+    ///
+    /// # fn main() { test_capture() }
+    /// ```
+    pub fn capture(&mut self, regex: &str) -> Vec<String> {
+        let regex = Regex::new(regex)
+            .unwrap_or_else(|error| panic!("Invalid regular expression: {}", error));
+
+        if self.last_output.is_none() {
+            self.assert();
+        }
+
+        let stdout =
+            String::from_utf8_lossy(&self.last_output.as_ref().unwrap().stdout).into_owned();
+
+        regex
+            .captures_iter(&stdout)
+            .filter_map(|captures| captures.get(1).or_else(|| captures.get(0)))
+            .map(|capture| capture.as_str().to_string())
+            .collect()
+    }
+
+    /// The environment variables given to the program by `inline-c`:
+    /// the meta environment variables, without their `INLINE_C_RS_`
+    /// prefix, and the variables of the directives, once merged, e.g.
+    /// to find why a program does not see a variable. The program also
+    /// inherits the environment of the test, which is not included.
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_program_env() {
+    ///     let env = (assert_c! {
+    ///         #inline_c_rs FOO: "bar"
+    ///
+    ///         #include <stdlib.h>
+    ///
+    ///         int main() {
+    ///             return getenv("FOO") == NULL;
+    ///         }
+    ///     })
+    ///     .program_env();
+    ///
+    ///     assert_eq!(env["FOO"], "bar");
+    /// }
+    ///
+    /// # fn main() { test_program_env() }
+    /// ```
+    pub fn program_env(&self) -> HashMap<String, String> {
+        self.program_command()
+            .expect("The environment of the program is unknown, it has failed to compile, or it is replayed or run in-process")
+            .get_envs()
+            .filter_map(|(name, value)| {
+                Some((
+                    name.to_string_lossy().into_owned(),
+                    value?.to_string_lossy().into_owned(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Assert that the environment variable `name` is given to the
+    /// program, see [`Assert::program_env`], and that its value
+    /// satisfies `predicate`. The program is not run.
+    pub fn has_env_var<P>(&mut self, name: &str, predicate: P) -> &mut Self
+    where
+        P: Predicate<str>,
+    {
+        let env = self.program_env();
+
+        match env.get(name) {
+            Some(value) if predicate.eval(value) => (),
+            Some(value) => fail!(
+                self,
+                "The environment variable `{}` of the program is `{}`, it does not satisfy `{}`",
+                name,
+                if self.redaction.is_redacted(name) {
+                    "<redacted>"
+                } else {
+                    value
+                },
+                predicate
+            ),
+            None => {
+                let mut names = env.keys().map(String::as_str).collect::<Vec<_>>();
+                names.sort_unstable();
+
+                fail!(
+                    self,
+                    "The environment variable `{}` is not given to the program, the variables are: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        }
+
+        self
+    }
+
+    /// Assert that the program has created the file at `path`, and
+    /// that it satisfies `predicate`, e.g.
+    /// [`has_mode`](crate::predicates::has_mode) for its permissions.
+    /// A relative path is relative to the working directory of the
+    /// program, see the `cwd` directive. The program runs if it has
+    /// not run yet.
+    pub fn created_file<P, Q>(&mut self, path: P, predicate: Q) -> &mut Self
+    where
+        P: AsRef<Path>,
+        Q: Predicate<Path>,
+    {
+        if self.last_output.is_none() {
+            self.assert();
+        }
+
+        let path = match self
+            .program_command()
+            .as_ref()
+            .and_then(Command::get_current_dir)
+        {
+            Some(working_dir) => working_dir.join(path),
+            None => path.as_ref().to_path_buf(),
+        };
+
+        if !path.exists() {
+            fail!(
+                self,
+                "The program has not created the file `{}`",
+                path.display()
+            );
+        }
+
+        if !predicate.eval(&path) {
+            fail!(
+                self,
+                "The file `{}` does not satisfy `{}`{}",
+                path.display(),
+                predicate,
+                permissions::mode(&path)
+                    .map(|mode| format!(", its mode is {:#o}", mode))
+                    .unwrap_or_default()
+            );
+        }
+
+        self
+    }
+
+    /// Run the program, and assert that it finishes within `limit`.
+    /// Only the execution of the program is measured, not its
+    /// compilation.
+    ///
+    /// It is a lightweight guard against performance regressions,
+    /// e.g. of the hot paths of a C API.
+    pub fn finishes_within(&mut self, limit: Duration) -> assert_cmd::assert::Assert {
+        let assert = self.assert();
+        let run_time = self
+            .last_run_time
+            .expect("The execution time of a replayed program is unknown");
+
+        if run_time > limit {
+            fail!(
+                self,
+                "The program has run in {:?}, expected within {:?}",
+                run_time,
+                limit
+            );
+        }
+
+        assert
+    }
+
+    /// Run the program, and assert that it is not slower than the
+    /// baseline of the benchmark `name`, plus `tolerance` (e.g. `0.1`
+    /// for 10%). It requires the special `baseline` directive, see [the
+    /// documentation of the crate](crate#performance-baseline).
+    pub fn not_slower_than_baseline(
+        &mut self,
+        name: &str,
+        tolerance: f64,
+    ) -> assert_cmd::assert::Assert {
+        let baseline = self
+            .baseline
+            .clone()
+            .expect("No baseline, please set the special `baseline` directive");
+
+        let assert = self.assert();
+        let run_time = self
+            .last_run_time
+            .expect("The execution time of a replayed program is unknown");
+
+        if let Err(error) = baseline.check(name, run_time, tolerance) {
+            fail!(self, "{}", error);
+        }
+
+        assert
+    }
+
+    /// The toolchain which has compiled the program: the compiler, its
+    /// kind and its version, its flags, and the host and target
+    /// triples, e.g. for the bug reports or the cache keys. It is
+    /// also written in JSON next to the program, see
+    /// [`ArtifactSet::provenance`].
+    pub fn provenance(&self) -> &Provenance {
+        self.provenance
+            .as_ref()
+            .expect("The provenance is unknown, the run has been replayed")
+    }
+
+    /// The files produced to run the program: the sources, the
+    /// intermediate objects, the executable and the provenance. They
+    /// are removed when
+    /// `Assert` is dropped, see [`ArtifactSet::persist_to`] to keep
+    /// them.
+    pub fn artifacts(&self) -> &ArtifactSet {
+        &self.artifacts
+    }
+
+    /// Copy the compiled executable, or shared object, to `path`, e.g.
+    /// for the later tests or for external tools. If `path` is a
+    /// directory, the copy keeps the file name of the binary inside it,
+    /// see the special `output_name` directive. Unlike the artifacts,
+    /// the copy is not removed when `Assert` is dropped. The assertions
+    /// panic, so the binary is only copied once the previous ones have
+    /// passed.
+    pub fn persist_binary_to<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let binary = self
+            .artifacts
+            .output()
+            .filter(|output| output.is_file())
+            .expect("The program has failed to compile")
+            .to_path_buf();
+        let mut path = path.as_ref().to_path_buf();
+
+        if path.is_dir() {
+            path.push(binary.file_name().unwrap_or_default());
+        }
+
+        let copied = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::copy(&binary, &path));
+
+        if let Err(error) = copied {
+            fail!(
+                self,
+                "Failed to copy `{}` to `{}`: {}",
+                binary.display(),
+                path.display(),
+                error
+            );
+        }
+
+        footprint::record_persisted(&path);
+
+        self
+    }
+
+    /// The shared libraries the compiled program links against, read
+    /// with `ldd` on Linux (including the indirect dependencies),
+    /// `otool -L` on macOS, or `dumpbin /DEPENDENTS` on Windows. The
+    /// program is not run.
+    pub fn dynamic_dependencies(&self) -> Vec<String> {
+        let artifact_path = self
+            .artifact_path
+            .as_ref()
+            .expect("The program has failed to compile");
+
+        dependencies::dynamic_dependencies(artifact_path)
+            .unwrap_or_else(|error| panic!("Failed to list the dynamic dependencies: {}", error))
+    }
+
+    /// Assert that the compiled program links against `library`, i.e.
+    /// that the file name of one of its [dynamic
+    /// dependencies](Assert::dynamic_dependencies) starts with
+    /// `library`, e.g. `libfoo.so` matches `libfoo.so.1`.
+    pub fn links_against(&mut self, library: &str) -> &mut Self {
+        let dependencies = self.dynamic_dependencies();
+
+        if !dependencies
+            .iter()
+            .any(|dependency| dependencies::matches(dependency, library))
+        {
+            fail!(
+                self,
+                "The program does not link against `{}`, its dynamic dependencies are:\n{}",
+                library,
+                dependencies.join("\n")
+            );
+        }
+
+        self
+    }
+
+    /// The symbols exported by the compiled program, sorted, e.g. the
+    /// functions of a shared object (see
+    /// [`OutputKind::SharedObject`](crate::OutputKind::SharedObject)),
+    /// with `nm` on Linux and macOS, or `dumpbin /EXPORTS` on Windows.
+    /// The symbols of the linker and of `inline-c` are ignored. The
+    /// program is not run.
+    pub fn exported_symbols(&self) -> Vec<String> {
+        let artifact_path = self
+            .artifact_path
+            .as_ref()
+            .expect("The program has failed to compile");
+
+        symbols::exported_symbols(artifact_path)
+            .unwrap_or_else(|error| panic!("Failed to list the exported symbols: {}", error))
+    }
+
+    /// Assert that the compiled program exports exactly `symbols`, in
+    /// any order, e.g. to check a version script or the visibility
+    /// attributes of a C API, see [`Assert::exported_symbols`].
+    pub fn exports_exactly<I, S>(&mut self, symbols: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut expected = symbols
+            .into_iter()
+            .map(|symbol| symbol.as_ref().to_string())
+            .collect::<Vec<_>>();
+        expected.sort();
+        expected.dedup();
+
+        let exported = self.exported_symbols();
+
+        if exported != expected {
+            let missing = expected
+                .iter()
+                .filter(|symbol| !exported.contains(symbol))
+                .cloned()
+                .collect::<Vec<_>>();
+            let unexpected = exported
+                .iter()
+                .filter(|symbol| !expected.contains(symbol))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            fail!(
+                self,
+                "The program does not export exactly the expected symbols, missing: [{}], \
+                 unexpected: [{}]",
+                missing.join(", "),
+                unexpected.join(", ")
+            );
+        }
+
+        self
+    }
+
+    /// Assert that the compiled shared object (see
+    /// [`OutputKind::SharedObject`](crate::OutputKind::SharedObject))
+    /// loads cleanly, i.e. that `dlopen` with `RTLD_NOW` (or
+    /// `LoadLibrary` on Windows) resolves all its symbols and
+    /// libraries. The failure message names what is missing. The
+    /// program is not run.
+    pub fn loads_cleanly(&mut self) -> &mut Self {
+        let artifact_path = self
+            .artifact_path
+            .as_ref()
+            .expect("The program has failed to compile");
+
+        if let Err(error) = inproc::load(artifact_path) {
+            fail!(self, "The shared object does not load cleanly: {}", error);
+        }
+
+        self
+    }
+
+    /// Assert that the compiled program is statically linked, i.e. that
+    /// it has no [dynamic dependencies](Assert::dynamic_dependencies),
+    /// e.g. when compiled with the special `static` directive, to run in
+    /// minimal containers. It is checked on Linux only.
+    pub fn is_statically_linked(&mut self) -> &mut Self {
+        if !cfg!(target_os = "linux") {
+            panic!("Static linking is checked on Linux only");
+        }
+
+        let dependencies = self.dynamic_dependencies();
+
+        if !dependencies.is_empty() {
+            fail!(
+                self,
+                "The program is not statically linked, its dynamic dependencies are:\n{}",
+                dependencies.join("\n")
+            );
+        }
+
+        self
+    }
+
+    /// Assert that the compiled program does not link against
+    /// `library`, see [`Assert::links_against`].
+    pub fn does_not_link_against(&mut self, library: &str) -> &mut Self {
+        if let Some(dependency) = self
+            .dynamic_dependencies()
+            .iter()
+            .find(|dependency| dependencies::matches(dependency, library))
+        {
+            fail!(
+                self,
+                "The program links against `{}`, through `{}`",
+                library,
+                dependency
+            );
+        }
+
+        self
+    }
+
+    /// The headers the program includes, directly or not, when the
+    /// `record_headers` directive is set to `1`. The program is not
+    /// run.
+    pub fn included_headers(&self) -> &[PathBuf] {
+        self.included_headers.as_deref().expect(
+            "The included headers are not recorded, set the special `record_headers` directive to `1`",
+        )
+    }
+
+    /// Assert that the program includes `header`, directly or not,
+    /// i.e. that one of its [included headers](Assert::included_headers)
+    /// ends with `header`, e.g. `foo/bar.h` matches
+    /// `/usr/include/foo/bar.h`.
+    pub fn includes_header(&mut self, header: &str) -> &mut Self {
+        let headers = self.included_headers();
+
+        if !headers.iter().any(|path| path.ends_with(header)) {
+            fail!(
+                self,
+                "The program does not include `{}`, its included headers are:\n{}",
+                header,
+                headers
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+
+        self
+    }
+
+    /// Assert that the program does not include `header`, directly or
+    /// not, see [`Assert::includes_header`]. It checks, for example,
+    /// that a public header does not drag in private headers.
+    pub fn does_not_include_header(&mut self, header: &str) -> &mut Self {
+        if let Some(path) = self
+            .included_headers()
+            .iter()
+            .find(|path| path.ends_with(header))
+        {
+            fail!(
+                self,
+                "The program includes `{}`, at `{}`",
+                header,
+                path.display()
+            );
+        }
+
+        self
+    }
+
+    /// Whether the compilation has failed. In this case, the
+    /// assertions are on the output of the compiler, see
+    /// [`Assert::compile_failure`], and their failure messages show the
+    /// compiled source around the errors, with line numbers and carets.
+    pub fn compilation_failed(&self) -> bool {
+        self.compiler_output.is_some()
+    }
+
+    /// The lines of the compiled source around the errors of the
+    /// compiler, numbered, with carets under the error locations.
+    fn source_excerpt(&self) -> Option<String> {
+        let diagnostics = self.diagnostics.as_ref()?;
+
+        self.artifacts
+            .sources()
+            .first()
+            .and_then(|source| diagnostics.excerpt(source))
+            .map(|excerpt| format!("\n{}", excerpt))
+    }
+
+    /// Assert that the compilation has failed, and return an assertion
+    /// on the output of the compiler, e.g. to check its diagnostics.
+    ///
+    /// ```rust
+    /// use inline_c::{assert_c, predicates::*};
+    ///
+    /// fn test_compile_failure() {
+    ///     (assert_c! {
+    ///         int main() {
+    ///             return undefined;
+    ///         }
+    ///     })
+    ///     .compile_failure()
+    ///     .stderr(predicate::str::contains("undefined"));
+    /// }
+    ///
+    /// # fn main() { test_compile_failure() }
+    /// ```
+    pub fn compile_failure(&mut self) -> assert_cmd::assert::Assert {
+        if !self.compilation_failed() {
+            fail!(self, "The compilation has succeeded, expected a failure");
+        }
+
+        self.assert().failure()
+    }
+
+    /// Assert that the compiler has reported `expected` errors, in the
+    /// formats of GCC, Clang and MSVC. Unlike the wording of the
+    /// messages, the count does not change across the compilers and
+    /// their versions.
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_errors() {
+    ///     (assert_c! {
+    ///         int main() {
+    ///             return undefined;
+    ///         }
+    ///     })
+    ///     .errors(1)
+    ///     .warnings(0);
+    /// }
+    ///
+    /// # fn main() { test_errors() }
+    /// ```
+    pub fn errors(&mut self, expected: usize) -> &mut Self {
+        let errors = self.diagnostics().errors;
+
+        if errors != expected {
+            fail!(
+                self,
+                "The compiler has reported {} error(s), expected {}:\n{}",
+                errors,
+                expected,
+                self.diagnostics().output
+            );
+        }
+
+        self
+    }
+
+    /// Assert that the compiler has reported `expected` warnings, see
+    /// [`Assert::errors`].
+    pub fn warnings(&mut self, expected: usize) -> &mut Self {
+        let warnings = self.diagnostics().warnings;
+
+        if warnings != expected {
+            fail!(
+                self,
+                "The compiler has reported {} warning(s), expected {}:\n{}",
+                warnings,
+                expected,
+                self.diagnostics().output
+            );
+        }
+
+        self
+    }
+
+    fn diagnostics(&self) -> &Diagnostics {
+        self.diagnostics
+            .as_ref()
+            .expect("The diagnostics of the compiler are unknown, the run has been replayed")
+    }
+
+    /// Assert that the program has been terminated by an uncaught C++
+    /// exception, whose type or message contains `pattern`, e.g.
+    /// `std::out_of_range`. The message of the C++ runtime is
+    /// recognized for libstdc++ and libc++.
+    ///
+    /// ```rust,no_run
+    /// use inline_c::assert_cxx;
+    ///
+    /// fn test_uncaught_exception() {
+    ///     (assert_cxx! {
+    ///         #include <vector>
+    ///
+    ///         int main() {
+    ///             std::vector<int> numbers;
+    ///
+    ///             return numbers.at(1);
+    ///         }
+    ///     })
+    ///     .uncaught_exception_containing("std::out_of_range");
+    /// }
+    ///
+    /// # fn main() { test_uncaught_exception() }
+    /// ```
+    pub fn uncaught_exception_containing(&mut self, pattern: &str) -> assert_cmd::assert::Assert {
+        let assert = self.assert();
+
+        match Outcome::from_output(assert.get_output()) {
+            Outcome::UncaughtException(exception) if exception.contains(pattern) => assert,
+            Outcome::UncaughtException(exception) => fail!(
+                self,
+                "The program has been terminated by the uncaught exception `{}`, expected one containing `{}`",
+                exception,
+                pattern
+            ),
+            outcome => fail!(
+                self,
+                "The program has not been terminated by an uncaught exception, it has {}:\n{}",
+                outcome,
+                String::from_utf8_lossy(&assert.get_output().stderr)
+            ),
+        }
+    }
+
+    /// Label the program, e.g. when a test runs several programs. The
+    /// label is added to the failure messages, and to the report (see
+    /// the special `report` directive).
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_context() {
+    ///     (assert_c! {
+    ///         int main() {
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .context("empty program")
+    ///     .success();
+    /// }
+    ///
+    /// # fn main() { test_context() }
+    /// ```
+    pub fn context(&mut self, label: &str) -> &mut Self {
+        self.context = Some(label.to_string());
+
+        if let Some(reporter) = &mut self.reporter {
+            reporter.set_context(label);
+        }
+
+        self
+    }
+
+    fn context_prefix(&self) -> String {
+        self.context
+            .as_ref()
+            .map_or_else(String::new, |context| format!("[{}] ", context))
     }
 
     /// Shortcut to `self.assert().success()`.
@@ -28,15 +1596,227 @@ impl Assert {
     pub fn failure(&mut self) -> assert_cmd::assert::Assert {
         self.assert().failure()
     }
+
+    /// Match the `// CHECK:` and `// CHECK-NEXT:` comments of the
+    /// program against its standard output, [LLVM
+    /// FileCheck](https://llvm.org/docs/CommandGuide/FileCheck.html)
+    /// style. It panics if the program has no such comment, or if one
+    /// of them is invalid or does not match.
+    ///
+    /// Note that the Rust tokenizer drops the regular comments on
+    /// stable, so the macros need doc comments, e.g. `/// CHECK: …`,
+    /// or a program given as a string literal (see the [crate
+    /// documentation](crate#comments)).
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_file_check() {
+    ///     (assert_c! {
+    ///         #include <stdio.h>
+    ///
+    ///         int main() {
+    ///             /// CHECK: foo
+    ///             /// CHECK-NEXT: bar
+    ///             printf("foo\nbar\n");
+    ///
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .file_check();
+    /// }
+    ///
+    /// # fn main() { test_file_check() }
+    /// ```
+    pub fn file_check(&mut self) -> assert_cmd::assert::Assert {
+        let file_check = match FileCheck::parse(&self.source) {
+            Ok(file_check) => file_check,
+            Err(error) => fail!(self, "Invalid file check: {}", error),
+        };
+
+        if file_check.is_empty() {
+            fail!(self, "The program has no `// CHECK:` directive");
+        }
+
+        let assert = self.assert();
+        let stdout = String::from_utf8_lossy(&assert.get_output().stdout);
+
+        if let Err(error) = file_check.matches(&stdout) {
+            fail!(self, "File check failed: {}\n\nstdout:\n{}", error, stdout);
+        }
+
+        assert
+    }
+
+    /// Run `instances` instances of the program at the same time, with
+    /// the same arguments and standard input, and assert that they all
+    /// succeed. It is a simple smoke test of the thread and process
+    /// safety of the global state of a C API, e.g. of files or locks
+    /// shared between the processes. The instances share the working
+    /// directory of the program, see
+    /// [`Assert::run_concurrently_in_distinct_dirs`] otherwise.
+    ///
+    /// ```rust
+    /// use inline_c::assert_c;
+    ///
+    /// fn test_run_concurrently() {
+    ///     (assert_c! {
+    ///         int main() {
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .run_concurrently(8);
+    /// }
+    ///
+    /// # fn main() { test_run_concurrently() }
+    /// ```
+    pub fn run_concurrently(&mut self, instances: usize) -> &mut Self {
+        self.run_instances(instances, false)
+    }
+
+    /// Like [`Assert::run_concurrently`], but each instance runs in a
+    /// working directory of its own, empty and removed afterwards, so
+    /// that only the state outside of the working directory is shared.
+    pub fn run_concurrently_in_distinct_dirs(&mut self, instances: usize) -> &mut Self {
+        self.run_instances(instances, true)
+    }
+
+    fn run_instances(&mut self, instances: usize, distinct_dirs: bool) -> &mut Self {
+        let working_dirs = (0..instances)
+            .map(|_| {
+                distinct_dirs
+                    .then(|| {
+                        tempfile::Builder::new()
+                            .prefix("inline-c-rs-instance-")
+                            .tempdir_in(temp::temp_dir())
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|error| panic!("Failed to create a working directory: {}", error));
+
+        // All the instances are spawned before any is waited for.
+        let outputs = working_dirs
+            .iter()
+            .map(|working_dir| {
+                let mut command = self
+                    .program_command()
+                    .expect("The program has failed to compile");
+                command.args(&self.args);
+
+                if let Some(working_dir) = working_dir {
+                    command.current_dir(working_dir.path());
+                }
+
+                let stdin = self.stdin.clone();
+
+                thread::spawn(move || guard::output(&mut command, stdin.as_deref(), false))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|instance| instance.join().expect("The instance runner has panicked"))
+            .collect::<Vec<_>>();
+
+        // The reports of all the instances are in the same directory,
+        // one file per process.
+        let sanitizer_logs = self.take_sanitizer_logs();
+
+        for (index, output) in outputs.into_iter().enumerate() {
+            match output {
+                Ok(output) if output.status.success() => (),
+                Ok(output) if !sanitizer_logs.is_empty() => fail!(
+                    self,
+                    "The instance {} of {} has failed with {}\n\nstdout:\n{}\n\nstderr:\n{}\n\n\
+                     sanitizers:\n{}",
+                    index + 1,
+                    instances,
+                    output.status,
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr),
+                    sanitizer_logs,
+                ),
+                Ok(output) => fail!(
+                    self,
+                    "The instance {} of {} has failed with {}\n\nstdout:\n{}\n\nstderr:\n{}",
+                    index + 1,
+                    instances,
+                    output.status,
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr),
+                ),
+                Err(error) => fail!(
+                    self,
+                    "Failed to run the instance {} of {}: {}",
+                    index + 1,
+                    instances,
+                    error
+                ),
+            }
+        }
+
+        // The programs may keep running after a finding, e.g. with
+        // `halt_on_error=0`.
+        let sanitizer_findings = sanitizer::findings(&sanitizer_logs);
+
+        if !sanitizer_findings.is_empty() {
+            fail!(
+                self,
+                "The sanitizers have reported {} finding(s) in the instances:\n\n{}",
+                sanitizer_findings.len(),
+                sanitizer_logs
+            );
+        }
+
+        self
+    }
+
+    /// Run the program, and the `reference` program with the same
+    /// arguments and standard input, and assert that they exit the
+    /// same way, i.e. with the same code or signal, and print the same
+    /// standard output. `reference` is left untouched, so that it can
+    /// be compared to several programs.
+    pub fn same_as(&mut self, reference: &Assert) -> assert_cmd::assert::Assert {
+        let reference_output = reference
+            .output_with(&self.args, self.stdin.as_deref())
+            .unwrap_or_else(|error| fail!(self, "Failed to run the reference program: {}", error));
+
+        let assert = self.assert();
+        let output = assert.get_output();
+
+        // `ExitStatus` holds the signal, if any, on Unix.
+        if output.status != reference_output.status {
+            fail!(
+                self,
+                "Exit statuses differ: got `{}`, expected `{}` from the reference program",
+                output.status,
+                reference_output.status,
+            );
+        }
+
+        if output.stdout != reference_output.stdout {
+            fail!(
+                self,
+                "Standard outputs differ.\n\ngot:\n{}\n\nexpected from the reference program:\n{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&reference_output.stdout),
+            );
+        }
+
+        assert
+    }
 }
 
 impl Drop for Assert {
     fn drop(&mut self) {
         if let Some(files_to_remove) = &self.files_to_remove {
             for file in files_to_remove.iter() {
-                if fs::metadata(file).is_ok() {
+                if let Ok(metadata) = fs::metadata(file) {
                     fs::remove_file(file)
                         .unwrap_or_else(|_| panic!("Failed to remove `{:?}`", file));
+
+                    if self.artifacts.iter().any(|artifact| artifact == file) {
+                        footprint::record_removed(metadata.len());
+                    }
                 }
             }
         }
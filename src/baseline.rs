@@ -0,0 +1,222 @@
+//! Compare the run time of programs against a baseline file, to catch
+//! large performance regressions.
+//!
+//! The baseline is configured with the special `baseline` directive
+//! (the path to the baseline file) and the special `baseline_mode`
+//! directive (`compare` or `update`).
+//!
+//! The baseline is a JSON object, mapping the name of a benchmark to
+//! its run time in seconds, e.g. `{"parse": 0.0125}`. It is meant to
+//! be committed in the repository. A benchmark missing from the
+//! baseline is added to it.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+lazy_static! {
+    // Tests run in parallel, let's not lose the updates.
+    static ref BASELINE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BaselineMode {
+    Compare,
+    Update,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Baseline {
+    path: PathBuf,
+    mode: BaselineMode,
+}
+
+impl Baseline {
+    /// Read the baseline configuration from the variables, if any.
+    pub(crate) fn from_variables(
+        variables: &HashMap<String, String>,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = match variables.get("baseline") {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(None),
+        };
+
+        let mode = match variables.get("baseline_mode").map(String::as_str) {
+            Some("compare") | None => BaselineMode::Compare,
+            Some("update") => BaselineMode::Update,
+            Some(mode) => {
+                return Err(format!(
+                    "Invalid `baseline_mode` value `{}`, expected `compare` or `update`",
+                    mode
+                )
+                .into())
+            }
+        };
+
+        Ok(Some(Self { path, mode }))
+    }
+
+    /// Compare `run_time` against the baseline of `name`, with a
+    /// `tolerance` relative to the baseline (e.g. `0.1` for 10%). The
+    /// baseline is written if it is missing, or in `update` mode.
+    pub(crate) fn check(
+        &self,
+        name: &str,
+        run_time: Duration,
+        tolerance: f64,
+    ) -> Result<(), Box<dyn Error>> {
+        if name.is_empty() || !name.chars().all(is_name_character) {
+            return Err(format!(
+                "Invalid benchmark name `{}`, expected alphanumeric characters, `_`, `-`, \
+                 `.` or `:`",
+                name
+            )
+            .into());
+        }
+
+        let _lock = BASELINE_LOCK
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        let mut baselines = match fs::read_to_string(&self.path) {
+            Ok(content) => parse(&content)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        match baselines.get(name) {
+            Some(&baseline) if self.mode == BaselineMode::Compare => {
+                let limit = baseline * (1.0 + tolerance);
+
+                if run_time.as_secs_f64() > limit {
+                    return Err(format!(
+                        "The benchmark `{}` has run in {:?}, expected within {:?} \
+                         (baseline of {:?}, plus {}%)",
+                        name,
+                        run_time,
+                        Duration::from_secs_f64(limit),
+                        Duration::from_secs_f64(baseline),
+                        tolerance * 100.0,
+                    )
+                    .into());
+                }
+
+                Ok(())
+            }
+
+            _ => {
+                baselines.insert(name.to_string(), run_time.as_secs_f64());
+                fs::write(&self.path, to_json(&baselines))?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+fn is_name_character(character: char) -> bool {
+    character.is_ascii_alphanumeric() || matches!(character, '_' | '-' | '.' | ':')
+}
+
+fn parse(content: &str) -> Result<BTreeMap<String, f64>, Box<dyn Error>> {
+    lazy_static! {
+        static ref ENTRY: Regex =
+            Regex::new(r#""(?P<name>[^"]+)"\s*:\s*(?P<time>[0-9.eE+-]+)"#).unwrap();
+    }
+
+    ENTRY
+        .captures_iter(content)
+        .map(|captures| {
+            let time = captures["time"].parse::<f64>().map_err(|_| {
+                format!(
+                    "Invalid run time `{}` for the benchmark `{}` in the baseline",
+                    &captures["time"], &captures["name"]
+                )
+            })?;
+
+            Ok((captures["name"].to_string(), time))
+        })
+        .collect()
+}
+
+fn to_json(baselines: &BTreeMap<String, f64>) -> String {
+    let mut json = String::from("{\n");
+
+    for (index, (name, time)) in baselines.iter().enumerate() {
+        let _ = writeln!(
+            json,
+            "  \"{}\": {}{}",
+            name,
+            time,
+            if index + 1 < baselines.len() { "," } else { "" }
+        );
+    }
+
+    json.push('}');
+    json.push('\n');
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_to_json() {
+        let mut baselines = BTreeMap::new();
+        baselines.insert("parse".to_string(), 0.0125);
+        baselines.insert("api::render".to_string(), 1.5);
+
+        let json = to_json(&baselines);
+
+        assert_eq!(json, "{\n  \"api::render\": 1.5,\n  \"parse\": 0.0125\n}\n");
+        assert_eq!(parse(&json).unwrap(), baselines);
+    }
+
+    #[test]
+    fn test_check() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        fs::remove_file(&path).unwrap();
+
+        let baseline = Baseline {
+            path: path.to_path_buf(),
+            mode: BaselineMode::Compare,
+        };
+
+        // Missing, so recorded.
+        baseline
+            .check("bench", Duration::from_millis(100), 0.1)
+            .unwrap();
+        baseline
+            .check("bench", Duration::from_millis(105), 0.1)
+            .unwrap();
+        assert!(baseline
+            .check("bench", Duration::from_millis(200), 0.1)
+            .is_err());
+        assert!(baseline
+            .check("bench with spaces", Duration::from_millis(1), 0.1)
+            .is_err());
+
+        let baseline = Baseline {
+            mode: BaselineMode::Update,
+            ..baseline
+        };
+        baseline
+            .check("bench", Duration::from_millis(200), 0.1)
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{\n  \"bench\": 0.2\n}\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Export Rust constants into C programs as `#define`s.
+
+use std::convert::TryFrom;
+
+/// Format a Rust value as a C literal. It is used by
+/// [`export_consts!`](crate::export_consts).
+#[doc(hidden)]
+pub trait CLiteral {
+    fn to_c_literal(&self) -> String;
+}
+
+/// The suffix of the integer literals of each type, so that the
+/// literal has a C type as wide as the Rust type, e.g. `u64::MAX` does
+/// not fit in a `long` on Windows.
+macro_rules! impl_c_literal_for_integers {
+    ($($ty:ty => $suffix:literal),* $(,)?) => {
+        $(
+            impl CLiteral for $ty {
+                fn to_c_literal(&self) -> String {
+                    integer(
+                        i128::try_from(*self).ok(),
+                        <$ty>::MIN == 0,
+                        *self == <$ty>::MIN,
+                        $suffix,
+                    )
+                    .unwrap_or_else(|| {
+                        panic!("`{}` does not fit in a C integer literal", self)
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_c_literal_for_integers!(
+    i8 => "",
+    i16 => "",
+    i32 => "",
+    i64 => "LL",
+    i128 => "LL",
+    isize => "LL",
+    u8 => "",
+    u16 => "",
+    u32 => "U",
+    u64 => "ULL",
+    u128 => "ULL",
+    usize => "ULL",
+);
+
+/// Format an integer with its `suffix`. A negative integer is
+/// parenthesized, e.g. `(-1)`, and the minimum of a signed type is
+/// written `(-MAX - 1)`, since `MAX + 1` does not fit in the type.
+fn integer(value: Option<i128>, unsigned: bool, is_min: bool, suffix: &str) -> Option<String> {
+    let value = value?;
+    let (min, max) = if unsigned {
+        (0, i128::from(u64::MAX))
+    } else {
+        (i128::from(i64::MIN), i128::from(i64::MAX))
+    };
+
+    if value < min || value > max {
+        return None;
+    }
+
+    Some(if value >= 0 {
+        format!("{}{}", value, suffix)
+    } else if is_min {
+        format!("(-{}{} - 1)", -(value + 1), suffix)
+    } else {
+        format!("({}{})", value, suffix)
+    })
+}
+
+/// Format a float with its `suffix`. NaN and the infinities are
+/// written with the `NAN` and `INFINITY` macros of `<math.h>`.
+fn float(value: f64, debug: String, suffix: &str) -> String {
+    let literal = if value.is_nan() {
+        String::from("NAN")
+    } else if value.is_infinite() {
+        String::from("INFINITY")
+    } else {
+        // `Debug` always writes a decimal point, e.g. `1.0`.
+        format!("{}{}", debug.trim_start_matches('-'), suffix)
+    };
+
+    if value.is_sign_negative() && !value.is_nan() {
+        format!("(-{})", literal)
+    } else {
+        literal
+    }
+}
+
+impl CLiteral for f32 {
+    fn to_c_literal(&self) -> String {
+        float(f64::from(*self), format!("{:?}", self), "f")
+    }
+}
+
+impl CLiteral for f64 {
+    fn to_c_literal(&self) -> String {
+        float(*self, format!("{:?}", self), "")
+    }
+}
+
+impl CLiteral for bool {
+    fn to_c_literal(&self) -> String {
+        String::from(if *self { "1" } else { "0" })
+    }
+}
+
+impl CLiteral for char {
+    fn to_c_literal(&self) -> String {
+        format!("'{}'", escape(&self.to_string()))
+    }
+}
+
+impl CLiteral for str {
+    fn to_c_literal(&self) -> String {
+        format!("\"{}\"", escape(self))
+    }
+}
+
+impl CLiteral for String {
+    fn to_c_literal(&self) -> String {
+        self.as_str().to_c_literal()
+    }
+}
+
+impl<T: CLiteral + ?Sized> CLiteral for &T {
+    fn to_c_literal(&self) -> String {
+        (**self).to_c_literal()
+    }
+}
+
+/// Escape a string for a C string or character literal. Non-ASCII
+/// characters are written as octal escapes of their UTF-8 bytes.
+fn escape(string: &str) -> String {
+    let mut escaped = String::new();
+
+    for byte in string.bytes() {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    escaped
+}
+
+/// Generate the `#define`s of Rust constants, to keep magic numbers
+/// in one place. Integers, floats, booleans (`1` or `0`), characters
+/// and strings are supported. The integers have the suffix of their
+/// width, e.g. `ULL` for `u64`, and must fit in 64 bits. NaN and the
+/// infinities need `<math.h>`.
+///
+/// It is usually used as the first statement of `assert_c!` or
+/// `assert_cxx!`:
+///
+/// ```rust
+/// use inline_c::assert_c;
+///
+/// const MAX_LEN: i32 = 8;
+/// const VERSION: &str = "1.2.3";
+///
+/// fn test_export_consts() {
+///     (assert_c! {
+///         export_consts!(MAX_LEN, VERSION);
+///
+///         #include <stdio.h>
+///
+///         int main() {
+///             printf("%d %s", MAX_LEN, VERSION);
+///
+///             return 0;
+///         }
+///     })
+///     .success()
+///     .stdout("8 1.2.3");
+/// }
+///
+/// # fn main() { test_export_consts() }
+/// ```
+#[macro_export]
+macro_rules! export_consts {
+    ($($name:ident),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut defines = String::new();
+
+        $(
+            defines.push_str(&format!(
+                "#define {} {}\n",
+                stringify!($name),
+                $crate::CLiteral::to_c_literal(&$name),
+            ));
+        )*
+
+        defines
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_export_consts() {
+        const ANSWER: i32 = -42;
+        const RATIO: f64 = 2.0;
+        const ENABLED: bool = true;
+        const NAME: &str = "a \"quoted\"\nname";
+        const LETTER: char = '\'';
+
+        assert_eq!(
+            export_consts!(ANSWER, RATIO, ENABLED, NAME, LETTER),
+            "#define ANSWER (-42)\n\
+             #define RATIO 2.0\n\
+             #define ENABLED 1\n\
+             #define NAME \"a \\\"quoted\\\"\\nname\"\n\
+             #define LETTER '\\''\n"
+        );
+        assert_eq!(export_consts!(), "");
+    }
+
+    #[test]
+    fn test_integers() {
+        use crate::CLiteral;
+
+        assert_eq!(42u8.to_c_literal(), "42");
+        assert_eq!(i32::MIN.to_c_literal(), "(-2147483647 - 1)");
+        assert_eq!(u32::MAX.to_c_literal(), "4294967295U");
+        assert_eq!(u64::MAX.to_c_literal(), "18446744073709551615ULL");
+        assert_eq!(i64::MIN.to_c_literal(), "(-9223372036854775807LL - 1)");
+        assert_eq!((-1i64).to_c_literal(), "(-1LL)");
+        assert_eq!(7usize.to_c_literal(), "7ULL");
+        assert_eq!(1i128.to_c_literal(), "1LL");
+        assert!(std::panic::catch_unwind(|| u128::MAX.to_c_literal()).is_err());
+        assert!(std::panic::catch_unwind(|| i128::MIN.to_c_literal()).is_err());
+    }
+
+    #[test]
+    fn test_floats() {
+        use crate::CLiteral;
+
+        assert_eq!(1.5f32.to_c_literal(), "1.5f");
+        assert_eq!((-1.5f64).to_c_literal(), "(-1.5)");
+        assert_eq!(f32::NAN.to_c_literal(), "NAN");
+        assert_eq!(f64::INFINITY.to_c_literal(), "INFINITY");
+        assert_eq!(f32::NEG_INFINITY.to_c_literal(), "(-INFINITY)");
+    }
+}
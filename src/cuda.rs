@@ -0,0 +1,24 @@
+//! Support of CUDA programs, compiled with `nvcc` and run on the host.
+
+use lazy_static::lazy_static;
+use std::{env, process::Command};
+
+/// Whether `nvcc` (or the `NVCC` environment variable) and a GPU are
+/// available, so that CUDA programs can be compiled and run. It is
+/// used by `assert_cuda!` to skip the programs otherwise.
+#[doc(hidden)]
+pub fn cuda_available() -> bool {
+    lazy_static! {
+        static ref CUDA_AVAILABLE: bool = {
+            let nvcc = env::var_os("NVCC").unwrap_or_else(|| "nvcc".into());
+            let succeeds = |command: &mut Command| {
+                command.output().is_ok_and(|output| output.status.success())
+            };
+
+            succeeds(Command::new(nvcc).arg("--version"))
+                && succeeds(Command::new("nvidia-smi").arg("-L"))
+        };
+    }
+
+    *CUDA_AVAILABLE
+}
@@ -0,0 +1,186 @@
+//! Run programs under a debugger (`gdb` or `lldb`).
+
+use lazy_static::lazy_static;
+use std::{
+    io::{self, prelude::*},
+    process::{Command, ExitStatus, Stdio},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Debugger {
+    Gdb,
+    Lldb,
+}
+
+impl Debugger {
+    /// Find the first available debugger, `gdb` being preferred
+    /// except on macOS.
+    pub(crate) fn find() -> Option<Self> {
+        lazy_static! {
+            static ref DEBUGGER: Option<Debugger> = {
+                let candidates = if cfg!(target_os = "macos") {
+                    [Debugger::Lldb, Debugger::Gdb]
+                } else {
+                    [Debugger::Gdb, Debugger::Lldb]
+                };
+
+                candidates
+                    .iter()
+                    .copied()
+                    .find(|debugger| debugger.is_available())
+            };
+        }
+
+        *DEBUGGER
+    }
+
+    /// Parse the name of a debugger, `auto` (or `1`) meaning the
+    /// first available one.
+    pub(crate) fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "gdb" => Ok(Self::Gdb),
+            "lldb" => Ok(Self::Lldb),
+            "auto" | "1" => Self::find()
+                .ok_or_else(|| "No debugger found, please install `gdb` or `lldb`".into()),
+            name => Err(format!(
+                "Invalid debugger `{}`, expected `gdb`, `lldb` or `auto`",
+                name
+            )),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Gdb => "gdb",
+            Self::Lldb => "lldb",
+        }
+    }
+
+    fn is_available(&self) -> bool {
+        Command::new(self.name())
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    }
+
+    /// Create a command running `program` under the debugger, with
+    /// the debugger commands `commands`, executed after the program
+    /// has stopped.
+    fn command(&self, program: &Command, commands: &[&str]) -> Command {
+        let mut command = Command::new(self.name());
+
+        match self {
+            Self::Gdb => {
+                command.args(["--batch", "-ex", "run"]);
+
+                for debugger_command in commands {
+                    command.args(["-ex", debugger_command]);
+                }
+
+                command.arg("--args");
+            }
+
+            Self::Lldb => {
+                command.args(["--batch", "-o", "run"]);
+
+                for debugger_command in commands {
+                    command.args(["-k", debugger_command]);
+                }
+
+                command.arg("--");
+            }
+        }
+
+        add_program(&mut command, program);
+
+        command
+    }
+
+    /// Run `program` under the debugger, interactively: the debugger
+    /// inherits the standard input and outputs.
+    pub(crate) fn debug(&self, program: &Command) -> io::Result<ExitStatus> {
+        let mut command = Command::new(self.name());
+
+        match self {
+            Self::Gdb => command.args(["--quiet", "--args"]),
+            Self::Lldb => command.arg("--"),
+        };
+
+        add_program(&mut command, program);
+
+        command.status()
+    }
+
+    /// Re-run `program` under the debugger, and collect the backtrace
+    /// of the crash.
+    pub(crate) fn backtrace(&self, program: &Command, stdin: Option<&[u8]>) -> String {
+        let mut command = self.command(program, &["bt"]);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let output = command.spawn().and_then(|mut child| {
+            if let (Some(stdin), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+                // The program may not read its standard input.
+                let _ = child_stdin.write_all(stdin);
+            }
+
+            child.wait_with_output()
+        });
+
+        let output = match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+            Err(error) => return format!("Failed to run `{}`: {}", self.name(), error),
+        };
+
+        // Keep the frames only, i.e. `#0 …` with `gdb`, `frame #0: …`
+        // with `lldb`.
+        let frames = output
+            .lines()
+            .filter(|line| {
+                let line = line.trim_start().trim_start_matches("* ");
+
+                line.starts_with('#') || line.starts_with("frame #")
+            })
+            .collect::<Vec<_>>();
+
+        if frames.is_empty() {
+            output
+        } else {
+            frames.join("\n")
+        }
+    }
+}
+
+/// Add `program`, its arguments and its environment to the debugger
+/// `command`.
+fn add_program(command: &mut Command, program: &Command) {
+    command.arg(program.get_program()).args(program.get_args());
+
+    for (name, value) in program.get_envs() {
+        match value {
+            Some(value) => command.env(name, value),
+            None => command.env_remove(name),
+        };
+    }
+}
+
+/// Whether the program has crashed, i.e. was killed by a signal on
+/// Unix, or has exited with an exception code on Windows.
+pub(crate) fn has_crashed(status: &ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        status.signal().is_some()
+    }
+
+    #[cfg(not(unix))]
+    {
+        matches!(status.code(), Some(code) if code as u32 >= 0xC000_0000)
+    }
+}
@@ -0,0 +1,144 @@
+//! List the shared libraries the compiled program links against, with
+//! `ldd` on Linux, `otool -L` on macOS, and `dumpbin /DEPENDENTS` on
+//! Windows.
+
+use std::{error::Error, path::Path, process::Command};
+
+/// The shared libraries `path` links against. On Linux, the indirect
+/// dependencies are listed too.
+pub(crate) fn dynamic_dependencies(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let (tool, output) = if cfg!(target_os = "macos") {
+        ("otool", Command::new("otool").arg("-L").arg(path).output())
+    } else if cfg!(target_os = "windows") {
+        (
+            "dumpbin",
+            Command::new("dumpbin")
+                .arg("/DEPENDENTS")
+                .arg(path)
+                .output(),
+        )
+    } else {
+        ("ldd", Command::new("ldd").arg(path).output())
+    };
+
+    let output = output.map_err(|error| format!("Failed to run `{}`: {}", tool, error))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // `ldd` fails on static executables.
+    if !output.status.success()
+        && !stdout.contains("not a dynamic executable")
+        && !stderr.contains("not a dynamic executable")
+    {
+        return Err(format!("`{}` has failed: {}", tool, stderr).into());
+    }
+
+    Ok(if cfg!(target_os = "macos") {
+        parse_otool(&stdout)
+    } else if cfg!(target_os = "windows") {
+        parse_dumpbin(&stdout)
+    } else {
+        parse_ldd(&stdout)
+    })
+}
+
+/// Whether `dependency` matches `library`, i.e. whether its file name
+/// starts with `library`, e.g. `libssl.so.3` matches `libssl`.
+pub(crate) fn matches(dependency: &str, library: &str) -> bool {
+    Path::new(dependency)
+        .file_name()
+        .is_some_and(|file_name| file_name.to_string_lossy().starts_with(library))
+}
+
+fn parse_ldd(output: &str) -> Vec<String> {
+    // Static executables have no dependencies, e.g. with
+    // `-static-pie`.
+    output
+        .lines()
+        .filter(|line| line.starts_with('\t'))
+        .filter(|line| {
+            let line = line.trim();
+
+            line != "statically linked" && line != "not a dynamic executable"
+        })
+        .filter_map(|line| line.split_whitespace().next())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn parse_otool(output: &str) -> Vec<String> {
+    // The first line is the path of the program.
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.trim().split(" (").next())
+        .filter(|line| !line.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn parse_dumpbin(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.contains("has the following dependencies"))
+        .skip(1)
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_ldd(
+                "\tlinux-vdso.so.1 (0x00007ffd)\n\
+                 \tlibfoo.so => not found\n\
+                 \tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007fa8)\n\
+                 \t/lib64/ld-linux-x86-64.so.2 (0x00007fa8)\n"
+            ),
+            [
+                "linux-vdso.so.1",
+                "libfoo.so",
+                "libc.so.6",
+                "/lib64/ld-linux-x86-64.so.2"
+            ]
+        );
+        assert!(parse_ldd("\tstatically linked\n").is_empty());
+        assert_eq!(
+            parse_otool(
+                "/tmp/inline-c-rs-abc:\n\
+                 \t@rpath/libfoo.dylib (compatibility version 0.0.0, current version 0.0.0)\n\
+                 \t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1311.0.0)\n"
+            ),
+            ["@rpath/libfoo.dylib", "/usr/lib/libSystem.B.dylib"]
+        );
+        assert_eq!(
+            parse_dumpbin(
+                "Dump of file inline-c-rs.exe\n\
+                 \n\
+                 File Type: EXECUTABLE IMAGE\n\
+                 \n\
+                 \x20 Image has the following dependencies:\n\
+                 \n\
+                 \x20   foo.dll\n\
+                 \x20   KERNEL32.dll\n\
+                 \n\
+                 \x20 Summary\n"
+            ),
+            ["foo.dll", "KERNEL32.dll"]
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("libssl.so.3", "libssl"));
+        assert!(matches("/usr/lib/libSystem.B.dylib", "libSystem"));
+        assert!(!matches("libcrypto.so.3", "libssl"));
+    }
+}
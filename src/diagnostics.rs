@@ -0,0 +1,270 @@
+//! Count the errors and the warnings of the compiler, in the formats of
+//! GCC and Clang (`file:line:column: error: …`) and of MSVC
+//! (`file(line): error C1234: …`), and excerpt the source around the
+//! errors.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::BTreeMap, fs, path::Path, process::Output};
+
+/// The diagnostics of the compiler for a program.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Diagnostics {
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+    // The locations of the errors, in the order of the output.
+    pub(crate) error_locations: Vec<Location>,
+    // The output of the compiler, for the failure messages.
+    pub(crate) output: String,
+}
+
+impl Diagnostics {
+    /// Parse the diagnostics of the compiler output. MSVC writes them
+    /// on the standard output, the others on the standard error.
+    pub(crate) fn parse(output: &Output) -> Self {
+        let output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let mut diagnostics = Self::default();
+
+        for line in output.lines() {
+            match severity(line) {
+                Some((Severity::Error, location)) => {
+                    diagnostics.errors += 1;
+                    diagnostics.error_locations.push(location);
+                }
+                Some((Severity::Warning, _)) => diagnostics.warnings += 1,
+                None => (),
+            }
+        }
+
+        diagnostics.output = output;
+
+        diagnostics
+    }
+
+    /// The lines of the source file at `path` around the errors located
+    /// in it, numbered, with a caret under the column of each error,
+    /// e.g. to show the source generated by the macros. It is `None`
+    /// if no error is located in this file.
+    pub(crate) fn excerpt(&self, path: &Path) -> Option<String> {
+        const CONTEXT: usize = 2;
+
+        let file_name = path.file_name()?.to_string_lossy();
+        let mut columns = BTreeMap::<usize, Vec<Option<usize>>>::new();
+
+        for location in &self.error_locations {
+            if Path::new(&location.file)
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy() == file_name)
+            {
+                columns
+                    .entry(location.line)
+                    .or_default()
+                    .push(location.column);
+            }
+        }
+
+        if columns.is_empty() {
+            return None;
+        }
+
+        let source = fs::read_to_string(path).ok()?;
+        let lines = source.lines().collect::<Vec<_>>();
+        let width = lines.len().to_string().len();
+        let mut excerpt = String::new();
+        let mut last_printed = 0;
+
+        for line_number in 1..=lines.len() {
+            if columns
+                .range(line_number.saturating_sub(CONTEXT)..=line_number + CONTEXT)
+                .next()
+                .is_none()
+            {
+                continue;
+            }
+
+            if last_printed != 0 && line_number > last_printed + 1 {
+                excerpt.push_str(&format!("{:>width$} |\n", "...", width = width));
+            }
+
+            let line = lines[line_number - 1];
+            excerpt.push_str(&format!(
+                "{:>width$} | {}\n",
+                line_number,
+                line,
+                width = width
+            ));
+            last_printed = line_number;
+
+            if let Some(columns) = columns.get(&line_number) {
+                excerpt.push_str(&format!(
+                    "{:>width$} | {}\n",
+                    "",
+                    carets(line, columns),
+                    width = width
+                ));
+            }
+        }
+
+        Some(excerpt)
+    }
+}
+
+/// Where a diagnostic is reported, with a 1-based line and column.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Location {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+    pub(crate) column: Option<usize>,
+}
+
+/// The carets under the `columns` of `line`, or under its first
+/// character if the columns are unknown. The tabs of `line` are kept
+/// to align the carets.
+fn carets(line: &str, columns: &[Option<usize>]) -> String {
+    let first_character = line.chars().take_while(|c| c.is_whitespace()).count() + 1;
+    let columns = columns
+        .iter()
+        .map(|column| column.unwrap_or(first_character))
+        .collect::<Vec<_>>();
+    let last_column = columns.iter().copied().max().unwrap_or(1);
+
+    line.chars()
+        .chain(std::iter::repeat(' '))
+        .take(last_column)
+        .enumerate()
+        .map(|(index, c)| {
+            if columns.contains(&(index + 1)) {
+                '^'
+            } else if c == '\t' {
+                '\t'
+            } else {
+                ' '
+            }
+        })
+        .collect()
+}
+
+enum Severity {
+    Error,
+    Warning,
+}
+
+fn severity(line: &str) -> Option<(Severity, Location)> {
+    lazy_static! {
+        // The colors of the diagnostics, see `diagnostics_color`.
+        static ref ANSI_ESCAPE: Regex = Regex::new(r"\x1b\[[0-9;]*[mK]").unwrap();
+        static ref GNU: Regex =
+            Regex::new(r"^(?P<file>.+?):(?P<line>\d+):(?:(?P<column>\d+):)?\s*(?P<severity>fatal error|error|warning):").unwrap();
+        static ref MSVC: Regex = Regex::new(
+            r"^(?P<file>.+?)\((?P<line>\d+)(?:,(?P<column>\d+))?\)\s*:\s*(?P<severity>fatal error|error|warning) [A-Z]+\d+:"
+        )
+        .unwrap();
+    }
+
+    let line = ANSI_ESCAPE.replace_all(line, "");
+    let captures = GNU.captures(&line).or_else(|| MSVC.captures(&line))?;
+
+    let severity = match &captures["severity"] {
+        "warning" => Severity::Warning,
+        _ => Severity::Error,
+    };
+    let location = Location {
+        file: captures["file"].to_string(),
+        line: captures["line"].parse().ok()?,
+        column: captures
+            .name("column")
+            .and_then(|column| column.as_str().parse().ok()),
+    };
+
+    Some((severity, location))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = Output {
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: b"foo.c(3): warning C4101: 'x': unreferenced local variable\n\
+                      foo.c(4,5): error C2065: 'y': undeclared identifier\n"
+                .to_vec(),
+            stderr: b"/tmp/foo.c:3:9: warning: unused variable 'x' [-Wunused-variable]\n\
+                      /tmp/foo.c:4:12: \x1b[01;31m\x1b[Kerror: \x1b[m\x1b[K'y' undeclared\n\
+                      /tmp/foo.c:1:10: fatal error: bar.h: No such file or directory\n\
+                      /tmp/foo.c:4:12: note: each undeclared identifier is reported only once\n\
+                      2 errors generated.\n"
+                .to_vec(),
+        };
+
+        let diagnostics = Diagnostics::parse(&output);
+
+        assert_eq!(diagnostics.errors, 3);
+        assert_eq!(diagnostics.warnings, 2);
+        assert_eq!(
+            diagnostics.error_locations[0],
+            Location {
+                file: String::from("foo.c"),
+                line: 4,
+                column: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn test_excerpt() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("foo.c");
+        fs::write(
+            &path,
+            "#include <stdio.h>\n\nint main() {\n\treturn y;\n}\n\n\n\nint x = z;\n",
+        )
+        .unwrap();
+
+        let diagnostics = Diagnostics {
+            errors: 3,
+            error_locations: vec![
+                Location {
+                    file: path.display().to_string(),
+                    line: 4,
+                    column: Some(9),
+                },
+                Location {
+                    file: String::from("foo.c"),
+                    line: 9,
+                    column: None,
+                },
+                Location {
+                    file: String::from("bar.h"),
+                    line: 1,
+                    column: Some(1),
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            diagnostics.excerpt(&path).unwrap(),
+            "2 | \n\
+             3 | int main() {\n\
+             4 | \treturn y;\n\
+             \x20 | \t       ^\n\
+             5 | }\n\
+             6 | \n\
+             7 | \n\
+             8 | \n\
+             9 | int x = z;\n\
+             \x20 | ^\n"
+        );
+        assert_eq!(diagnostics.excerpt(Path::new("baz.c")), None);
+    }
+}
@@ -0,0 +1,367 @@
+//! Strict checks of the `#inline_c_rs` directives, so that a malformed
+//! directive is reported, instead of being left in the C source where
+//! it breaks the compilation cryptically, and the custom directives
+//! registered with [`register_directive`].
+
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    process::Command,
+    sync::{Arc, RwLock},
+};
+
+lazy_static! {
+    static ref HANDLERS: RwLock<HashMap<String, Arc<dyn DirectiveHandler>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// The special directives of `inline-c`, which configure how the
+/// program is compiled and run. They are neither given to the compiler
+/// nor to the program. A name close to one of them is likely a typo,
+/// e.g. `timzone`, unless it is allowed with the special
+/// `allowed_directives` directive.
+pub(crate) const DIRECTIVES: &[&str] = &[
+    "allowed_directives",
+    "auto_include",
+    "auto_link",
+    "backtrace",
+    "baseline",
+    "baseline_mode",
+    "compile_retries",
+    "compile_timeout",
+    "compiler",
+    "compiler_env",
+    "cpu_affinity",
+    "cwd",
+    "debugger",
+    "deterministic",
+    "diagnostics_color",
+    "entry_point",
+    "expect_stderr",
+    "faketime",
+    "faketime_library",
+    "fork_server",
+    "gcc_toolchain",
+    "heap_profiler",
+    "host",
+    "journal",
+    "journal_mode",
+    "locale",
+    "merge_stderr",
+    "min_compiler",
+    "network",
+    "nice",
+    "openmp",
+    "output_name",
+    "preload",
+    "profile",
+    "pty",
+    "quiet",
+    "record_headers",
+    "redact",
+    "report",
+    "runner",
+    "sanitizer",
+    "scheduler",
+    "source_extension",
+    "stack_size",
+    "standard",
+    "static",
+    "staticlib",
+    "stdin_file",
+    "strict_stderr",
+    "sysroot",
+    "system_include_dirs",
+    "target",
+    "tee",
+    "timezone",
+    "toolchain_prefixes",
+    "tracer",
+    "translate_paths",
+    "umask",
+    "use_out_dir",
+    "verbose",
+    "version_script",
+];
+
+/// An invalid `#inline_c_rs` directive, returned by [`run`](crate::run)
+/// and its variants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectiveError {
+    /// The line of the directive in the program, starting at 1.
+    pub line: usize,
+    /// The directive, as written.
+    pub directive: String,
+    /// Why the directive is invalid.
+    pub reason: String,
+}
+
+impl fmt::Display for DirectiveError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Invalid directive at line {}, `{}`: {}",
+            self.line, self.directive, self.reason
+        )
+    }
+}
+
+impl Error for DirectiveError {}
+
+/// A handler of a custom directive, see [`register_directive`].
+pub trait DirectiveHandler: Send + Sync {
+    /// Handle the directive with the value `value`, e.g. by adding
+    /// flags to the compiler, or variables to the program.
+    fn handle(&self, value: &str, context: &mut DirectiveContext) -> Result<(), Box<dyn Error>>;
+}
+
+/// What a [`DirectiveHandler`] can change: the commands compiling and
+/// running the program. It is given once the compiler command is
+/// complete, just before the compilation, so that the handler has the
+/// last word, e.g. the libraries it adds come after the source file.
+pub struct DirectiveContext<'a> {
+    variables: &'a HashMap<String, String>,
+    compile_command: &'a mut Command,
+    program_command: &'a mut Command,
+}
+
+impl<'a> DirectiveContext<'a> {
+    pub(crate) fn new(
+        variables: &'a HashMap<String, String>,
+        compile_command: &'a mut Command,
+        program_command: &'a mut Command,
+    ) -> Self {
+        Self {
+            variables,
+            compile_command,
+            program_command,
+        }
+    }
+
+    /// The variables of the program, e.g. `CFLAGS`, or `target`.
+    pub fn variables(&self) -> &HashMap<String, String> {
+        self.variables
+    }
+
+    /// The command compiling the program, to add arguments or
+    /// environment variables to the compiler.
+    pub fn compile_command(&mut self) -> &mut Command {
+        self.compile_command
+    }
+
+    /// The command running the program. Only its arguments, its
+    /// environment variables and its working directory are used: they
+    /// are added to the ones of the program, before the arguments
+    /// given with [`Assert::arg`](crate::Assert::arg).
+    pub fn program_command(&mut self) -> &mut Command {
+        self.program_command
+    }
+}
+
+/// Register a handler for the custom directive `name`, e.g.
+/// `#inline_c_rs artifactory_lib: "zlib"`, for all the programs. It
+/// replaces the previous handler of `name`, if any. The directive is
+/// not given to the program as a variable; it can also be set with
+/// an `INLINE_C_RS_` environment variable, like the other variables.
+///
+/// # Panics
+///
+/// Panics if `name` is not a valid name, or if it is the name of a
+/// special directive, e.g. `timezone`.
+///
+/// # Example
+///
+/// ```rust
+/// use inline_c::{assert_c, register_directive, DirectiveContext, DirectiveHandler};
+/// use std::error::Error;
+///
+/// struct Greeting;
+///
+/// impl DirectiveHandler for Greeting {
+///     fn handle(&self, value: &str, context: &mut DirectiveContext) -> Result<(), Box<dyn Error>> {
+///         context
+///             .compile_command()
+///             .arg(format!("-DGREETING=\"{}\"", value));
+///
+///         Ok(())
+///     }
+/// }
+///
+/// fn test_directive() {
+///     register_directive("greeting", Box::new(Greeting));
+///
+///     (assert_c! {
+///         #inline_c_rs greeting: "Hello"
+///
+///         #include <stdio.h>
+///
+///         int main() {
+///             printf(GREETING);
+///
+///             return 0;
+///         }
+///     })
+///     .success()
+///     .stdout("Hello");
+/// }
+///
+/// # fn main() { test_directive() }
+/// ```
+pub fn register_directive(name: &str, handler: Box<dyn DirectiveHandler>) {
+    assert!(
+        is_identifier(name),
+        "`{}` is not a valid name for a directive",
+        name
+    );
+    assert!(
+        !DIRECTIVES.contains(&name),
+        "`{}` is a special directive of `inline-c`",
+        name
+    );
+
+    HANDLERS
+        .write()
+        .unwrap_or_else(|error| error.into_inner())
+        .insert(name.to_string(), Arc::from(handler));
+}
+
+fn is_registered(name: &str) -> bool {
+    HANDLERS
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .contains_key(name)
+}
+
+/// Remove the custom directives from `variables`, with their handler.
+pub(crate) fn take_registered(
+    variables: &mut HashMap<String, String>,
+) -> Vec<(String, Arc<dyn DirectiveHandler>, String)> {
+    let handlers = HANDLERS.read().unwrap_or_else(|error| error.into_inner());
+    let mut registered = handlers
+        .iter()
+        .filter_map(|(name, handler)| {
+            variables
+                .remove(name)
+                .map(|value| (name.clone(), handler.clone(), value))
+        })
+        .collect::<Vec<_>>();
+
+    // The handlers run in a stable order.
+    registered.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+    registered
+}
+
+/// Whether `name` is the name of a special directive, see
+/// [`DIRECTIVES`].
+pub(crate) fn is_special(name: &str) -> bool {
+    DIRECTIVES.contains(&name)
+}
+
+/// Check the name of the directive `name`, without its step (e.g.
+/// `_run`): a variable, e.g. `CFLAGS` or `FOO`, a macro (`define
+/// NAME`, without a step), or a special directive.
+pub(crate) fn check_name(name: &str, has_step: bool) -> Result<(), String> {
+    if let (Some(macro_name), false) = (name.strip_prefix("define "), has_step) {
+        return if macro_name.trim().is_empty() {
+            Err(String::from("the name of the macro is missing"))
+        } else {
+            Ok(())
+        };
+    }
+
+    if !is_identifier(name) {
+        return Err(format!(
+            "`{}` is not a valid name, expected e.g. `FOO` or `stack_size`",
+            name
+        ));
+    }
+
+    // A special directive is consumed by `inline-c`, it is never given
+    // to the compiler or to the program.
+    if has_step && is_special(name) {
+        return Err(format!(
+            "`{}` is a special directive, it cannot be scoped to the compiler or the program",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+/// The special directive `name` is probably a typo of, e.g. `timezone`
+/// for `timzone`, unless `name` is in `allowed`. The names are case
+/// sensitive, so that e.g. `HOST` is not mistaken for `host`.
+pub(crate) fn near_directive(name: &str, allowed: &[&str]) -> Option<&'static str> {
+    if is_special(name) || is_registered(name) || allowed.contains(&name) {
+        return None;
+    }
+
+    // A short name is close to too many others.
+    let max_distance = if name.len() <= 4 { 1 } else { 2 };
+
+    DIRECTIVES
+        .iter()
+        .copied()
+        .find(|directive| edit_distance(name, directive) <= max_distance)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_character) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_character) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_character != *b_character);
+
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_name() {
+        assert_eq!(check_name("CFLAGS", false), Ok(()));
+        assert_eq!(check_name("stack_size", false), Ok(()));
+        assert_eq!(check_name("INCLUDE", true), Ok(()));
+        assert!(check_name("stack_size", true).is_err());
+        assert_eq!(check_name("http_proxy", false), Ok(()));
+        assert_eq!(check_name("define ANSWER", false), Ok(()));
+        assert!(check_name("define ANSWER", true).is_err());
+        assert!(check_name("FOO BAR", false).is_err());
+        assert!(check_name("define ", false).is_err());
+    }
+
+    #[test]
+    fn test_near_directive() {
+        assert_eq!(near_directive("timzone", &[]), Some("timezone"));
+        assert_eq!(near_directive("stacksize", &[]), Some("stack_size"));
+        assert_eq!(near_directive("timzone", &["timzone"]), None);
+        assert_eq!(near_directive("timezone", &[]), None);
+        assert_eq!(near_directive("http_proxy", &[]), None);
+        assert_eq!(near_directive("HOST", &[]), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,237 @@
+//! Evaluate checks on a run of a program without panicking, so that a
+//! custom harness can collect all the failures of a program instead
+//! of stopping at the first one, see
+//! [`Assert::evaluate`](crate::Assert::evaluate).
+
+use crate::sanitizer::SanitizerFinding;
+use std::{fmt, process::Output};
+
+/// A check on a run of a program, for
+/// [`Assert::evaluate`](crate::Assert::evaluate).
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Check {
+    /// The program exits successfully.
+    Success,
+    /// The program fails, i.e. it exits with an error code, or it is
+    /// killed.
+    Failure,
+    /// The program exits with this code.
+    Code(i32),
+    /// The standard output is exactly this text, once the `\r\n` end
+    /// of lines are normalized into `\n`.
+    Stdout(String),
+    /// The standard output contains this text.
+    StdoutContains(String),
+    /// The standard error is exactly this text, once the `\r\n` end of
+    /// lines are normalized into `\n`.
+    Stderr(String),
+    /// The standard error contains this text.
+    StderrContains(String),
+    /// The sanitizers report nothing, see the special `sanitizer`
+    /// directive. It fails if no sanitizer is enabled.
+    NoSanitizerFindings,
+}
+
+impl Check {
+    /// Why `output` fails the check, if it does.
+    /// `sanitizer_findings` is `None` if no sanitizer is enabled.
+    fn evaluate(
+        &self,
+        output: &Output,
+        sanitizer_findings: Option<&[SanitizerFinding]>,
+    ) -> Option<String> {
+        let stdout = String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n");
+        let stderr = String::from_utf8_lossy(&output.stderr).replace("\r\n", "\n");
+
+        match self {
+            Self::Success if !output.status.success() => Some(format!(
+                "The program was expected to succeed, but it has exited with {}",
+                output.status
+            )),
+            Self::Failure if output.status.success() => Some(String::from(
+                "The program was expected to fail, but it has succeeded",
+            )),
+            Self::Code(code) if output.status.code() != Some(*code) => Some(format!(
+                "The program was expected to exit with the code {}, but it has exited with {}",
+                code, output.status
+            )),
+            Self::Stdout(expected) if stdout != *expected => {
+                Some(difference("standard output", expected, &stdout))
+            }
+            Self::StdoutContains(expected) if !stdout.contains(expected.as_str()) => Some(format!(
+                "The standard output does not contain `{}`:\n{}",
+                expected, stdout
+            )),
+            Self::Stderr(expected) if stderr != *expected => {
+                Some(difference("standard error", expected, &stderr))
+            }
+            Self::StderrContains(expected) if !stderr.contains(expected.as_str()) => Some(format!(
+                "The standard error does not contain `{}`:\n{}",
+                expected, stderr
+            )),
+            Self::NoSanitizerFindings if sanitizer_findings.is_none() => Some(String::from(
+                "No sanitizer is enabled, see the special `sanitizer` directive",
+            )),
+            Self::NoSanitizerFindings if !sanitizer_findings.unwrap_or_default().is_empty() => {
+                let sanitizer_findings = sanitizer_findings.unwrap_or_default();
+
+                Some(format!(
+                    "The sanitizers have reported {} finding(s):\n\n{}",
+                    sanitizer_findings.len(),
+                    sanitizer_findings
+                        .iter()
+                        .map(|finding| finding.report.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n\n")
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The first line that differs between `expected` and `got`, then both
+/// texts.
+fn difference(stream: &str, expected: &str, got: &str) -> String {
+    let mut expected_lines = expected.split('\n');
+    let mut got_lines = got.split('\n');
+    let mut line = 1;
+
+    let (expected_line, got_line) = loop {
+        match (expected_lines.next(), got_lines.next()) {
+            (Some(expected_line), Some(got_line)) if expected_line == got_line => line += 1,
+            (expected_line, got_line) => break (expected_line, got_line),
+        }
+    };
+
+    format!(
+        "The {} differs at line {}: expected {}, got {}\n\nexpected:\n{}\n\ngot:\n{}",
+        stream,
+        line,
+        expected_line.map_or_else(|| String::from("nothing"), |line| format!("`{}`", line)),
+        got_line.map_or_else(|| String::from("nothing"), |line| format!("`{}`", line)),
+        expected,
+        got
+    )
+}
+
+/// A failed check of a [`RunReport`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Finding {
+    /// The failed check, or `None` if the program has failed to
+    /// compile, or if its standard error does not meet the
+    /// `expect_stderr` directive.
+    pub check: Option<Check>,
+    /// Why the check has failed.
+    pub message: String,
+}
+
+/// The result of [`Assert::evaluate`](crate::Assert::evaluate): the
+/// output of the program, and all the failed checks.
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    output: Option<Output>,
+    findings: Vec<Finding>,
+    sanitizer_findings: Vec<SanitizerFinding>,
+}
+
+impl RunReport {
+    pub(crate) fn compile_failure(message: String) -> Self {
+        Self {
+            output: None,
+            findings: vec![Finding {
+                check: None,
+                message,
+            }],
+            sanitizer_findings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn new<I>(
+        output: Output,
+        checks: I,
+        stderr_expectation: Result<(), String>,
+        sanitizer_findings: Option<Vec<SanitizerFinding>>,
+    ) -> Self
+    where
+        I: IntoIterator<Item = Check>,
+    {
+        let findings = stderr_expectation
+            .err()
+            .map(|message| Finding {
+                check: None,
+                message,
+            })
+            .into_iter()
+            .chain(checks.into_iter().filter_map(|check| {
+                check
+                    .evaluate(&output, sanitizer_findings.as_deref())
+                    .map(|message| Finding {
+                        check: Some(check),
+                        message,
+                    })
+            }))
+            .collect();
+
+        Self {
+            output: Some(output),
+            findings,
+            sanitizer_findings: sanitizer_findings.unwrap_or_default(),
+        }
+    }
+
+    /// Whether all the checks have passed.
+    pub fn is_success(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// The failed checks.
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// The output of the program, absent if it has failed to compile.
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+
+    /// The findings of the sanitizers, see
+    /// [`Assert::sanitizer_findings`](crate::Assert::sanitizer_findings).
+    pub fn sanitizer_findings(&self) -> &[SanitizerFinding] {
+        &self.sanitizer_findings
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.findings.is_empty() {
+            return write!(formatter, "All the checks have passed");
+        }
+
+        write!(formatter, "{} check(s) have failed:", self.findings.len())?;
+
+        for finding in &self.findings {
+            write!(formatter, "\n\n- {}", finding.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difference() {
+        assert_eq!(
+            difference("standard output", "a\nb\n", "a\nc\n"),
+            "The standard output differs at line 2: expected `b`, got `c`\n\n\
+             expected:\na\nb\n\n\ngot:\na\nc\n"
+        );
+        assert!(difference("standard output", "a", "a\nb")
+            .starts_with("The standard output differs at line 2: expected nothing, got `b`"));
+    }
+}
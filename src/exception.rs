@@ -0,0 +1,94 @@
+//! Classify the runs terminated by an uncaught C++ exception, from the
+//! message printed by the C++ runtime before it aborts the program.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::process::{ExitStatus, Output};
+
+/// Whether the program has aborted, i.e. was killed by `SIGABRT` on
+/// Unix, or has exited with the code of `abort` on Windows.
+pub(crate) fn has_aborted(status: &ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        status.signal() == Some(libc::SIGABRT)
+    }
+
+    #[cfg(not(unix))]
+    {
+        status.code() == Some(3)
+    }
+}
+
+/// The uncaught exception which has terminated the program, e.g.
+/// `std::out_of_range: vector::_M_range_check`, i.e. its type followed
+/// by its message, if any.
+pub(crate) fn uncaught_exception(output: &Output) -> Option<String> {
+    lazy_static! {
+        // libstdc++, then libc++.
+        static ref LIBSTDCXX: Regex = Regex::new(
+            r"terminate called after throwing an instance of '(?P<type>[^']+)'(?:\r?\n\s*what\(\):\s*(?P<what>[^\r\n]*))?"
+        )
+        .unwrap();
+        static ref LIBCXX: Regex = Regex::new(
+            r"libc\+\+abi: terminating (?:with|due to) uncaught exception of type (?P<type>[^:\r\n]+(?:::[^:\r\n]+)*)(?::\s*(?P<what>[^\r\n]*))?"
+        )
+        .unwrap();
+    }
+
+    if !has_aborted(&output.status) {
+        return None;
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let captures = LIBSTDCXX
+        .captures(&stderr)
+        .or_else(|| LIBCXX.captures(&stderr))?;
+
+    Some(match captures.name("what") {
+        Some(what) => format!("{}: {}", &captures["type"], what.as_str()),
+        None => captures["type"].to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_uncaught_exception() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = |stderr: &str| Output {
+            status: ExitStatus::from_raw(libc::SIGABRT),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+
+        assert_eq!(
+            uncaught_exception(&output(
+                "terminate called after throwing an instance of 'std::out_of_range'\n  \
+                 what():  vector::_M_range_check: __n (which is 1) >= this->size() (which is 0)\n"
+            ))
+            .as_deref(),
+            Some("std::out_of_range: vector::_M_range_check: __n (which is 1) >= this->size() (which is 0)")
+        );
+        assert_eq!(
+            uncaught_exception(&output(
+                "libc++abi: terminating due to uncaught exception of type std::out_of_range: vector\n"
+            ))
+            .as_deref(),
+            Some("std::out_of_range: vector")
+        );
+        assert_eq!(
+            uncaught_exception(&output(
+                "terminate called after throwing an instance of 'int'\n"
+            ))
+            .as_deref(),
+            Some("int")
+        );
+        assert_eq!(uncaught_exception(&output("Assertion failed\n")), None);
+    }
+}
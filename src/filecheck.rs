@@ -0,0 +1,186 @@
+//! A tiny subset of [LLVM
+//! FileCheck](https://llvm.org/docs/CommandGuide/FileCheck.html).
+//!
+//! Patterns are written as C comments inside the program:
+//!
+//! * `// CHECK: <pattern>` matches the first line containing
+//!   `<pattern>`, after the line matched by the previous directive,
+//! * `// CHECK-NEXT: <pattern>` matches the line immediately following
+//!   the line matched by the previous directive.
+//!
+//! A pattern is a literal string, except for `{{…}}` blocks which
+//! are regular expressions.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CheckKind {
+    Check,
+    CheckNext,
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Check => write!(formatter, "CHECK"),
+            Self::CheckNext => write!(formatter, "CHECK-NEXT"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Check {
+    kind: CheckKind,
+    pattern: String,
+    regex: Regex,
+}
+
+/// A set of `CHECK` directives collected from a program.
+#[derive(Debug, Default)]
+pub(crate) struct FileCheck {
+    checks: Vec<Check>,
+}
+
+impl FileCheck {
+    /// Collect the `// CHECK:` and `// CHECK-NEXT:` directives from
+    /// `program`.
+    pub(crate) fn parse(program: &str) -> Result<Self, String> {
+        lazy_static! {
+            static ref REGEX: Regex =
+                Regex::new(r"//\s*(?P<kind>CHECK(?:-NEXT)?):\s*(?P<pattern>.*?)\s*$").unwrap();
+        }
+
+        let mut checks = Vec::new();
+
+        for line in program.lines() {
+            if let Some(captures) = REGEX.captures(line) {
+                let kind = match &captures["kind"] {
+                    "CHECK-NEXT" => CheckKind::CheckNext,
+                    _ => CheckKind::Check,
+                };
+
+                if kind == CheckKind::CheckNext && checks.is_empty() {
+                    return Err(String::from(
+                        "Found a `CHECK-NEXT` directive without a previous `CHECK` directive",
+                    ));
+                }
+
+                let pattern = captures["pattern"].to_string();
+                let regex = pattern_to_regex(&pattern)?;
+
+                checks.push(Check {
+                    kind,
+                    pattern,
+                    regex,
+                });
+            }
+        }
+
+        Ok(Self { checks })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    /// Match all the directives against `input`, in order.
+    pub(crate) fn matches(&self, input: &str) -> Result<(), String> {
+        let lines = input.lines().collect::<Vec<_>>();
+        // Index of the next line to look at.
+        let mut position = 0;
+
+        for check in &self.checks {
+            match check.kind {
+                CheckKind::Check => {
+                    match lines
+                        .iter()
+                        .skip(position)
+                        .position(|line| check.regex.is_match(line))
+                    {
+                        Some(offset) => position += offset + 1,
+                        None => return Err(check.failure(lines.get(position).copied())),
+                    }
+                }
+
+                CheckKind::CheckNext => match lines.get(position) {
+                    Some(line) if check.regex.is_match(line) => position += 1,
+                    line => return Err(check.failure(line.copied())),
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Check {
+    fn failure(&self, line: Option<&str>) -> String {
+        match line {
+            Some(line) => format!(
+                "`{kind}: {pattern}` does not match, scanning from line `{line}`",
+                kind = self.kind,
+                pattern = self.pattern,
+                line = line,
+            ),
+            None => format!(
+                "`{kind}: {pattern}` does not match, end of input reached",
+                kind = self.kind,
+                pattern = self.pattern,
+            ),
+        }
+    }
+}
+
+fn pattern_to_regex(pattern: &str) -> Result<Regex, String> {
+    let mut regex = String::new();
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| format!("Unterminated `{{{{` in CHECK pattern `{}`", pattern))?;
+
+        regex.push_str(&regex::escape(&rest[..start]));
+        regex.push('(');
+        regex.push_str(&rest[start + 2..start + end]);
+        regex.push(')');
+
+        rest = &rest[start + end + 2..];
+    }
+
+    regex.push_str(&regex::escape(rest));
+
+    Regex::new(&regex).map_err(|error| format!("Invalid CHECK pattern `{}`: {}", pattern, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filecheck() {
+        let file_check = FileCheck::parse(
+            r#"
+                // CHECK: Hello
+                // CHECK-NEXT: x = {{[0-9]+}}
+                // CHECK: done
+            "#,
+        )
+        .unwrap();
+
+        assert!(file_check
+            .matches("Hello, World!\nx = 42\nblah\ndone\n")
+            .is_ok());
+        assert!(file_check
+            .matches("Hello, World!\nblah\nx = 42\ndone\n")
+            .is_err());
+        assert!(file_check.matches("Hello, World!\nx = 42\n").is_err());
+    }
+
+    #[test]
+    fn test_filecheck_next_without_check() {
+        assert!(FileCheck::parse("// CHECK-NEXT: foo").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+//! The disk space used by the programs of the process, across all the
+//! threads, to keep the footprint of large test suites under control.
+
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static PROGRAMS: AtomicU64 = AtomicU64::new(0);
+static FILES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+static FILES_REMOVED: AtomicU64 = AtomicU64::new(0);
+static BYTES_REMOVED: AtomicU64 = AtomicU64::new(0);
+static FILES_PERSISTED: AtomicU64 = AtomicU64::new(0);
+static BYTES_PERSISTED: AtomicU64 = AtomicU64::new(0);
+
+/// The disk space used by the programs compiled by the process so
+/// far, see [`report`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Footprint {
+    /// The number of compiled programs.
+    pub programs: u64,
+    /// The number of artifacts written, i.e. the sources, the objects
+    /// and the outputs.
+    pub files_written: u64,
+    /// The size of the artifacts written, in bytes.
+    pub bytes_written: u64,
+    /// The number of artifacts still on the disk: the ones of the
+    /// live (or leaked) [`Assert`](crate::Assert)s, and the persisted
+    /// ones.
+    pub files_retained: u64,
+    /// The size of the artifacts still on the disk, in bytes.
+    pub bytes_retained: u64,
+}
+
+impl fmt::Display for Footprint {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} program(s) compiled, {} file(s) written ({}), {} file(s) retained ({})",
+            self.programs,
+            self.files_written,
+            Size(self.bytes_written),
+            self.files_retained,
+            Size(self.bytes_retained)
+        )
+    }
+}
+
+struct Size(u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+        let mut size = self.0 as f64;
+        let mut unit = 0;
+
+        while size >= 1024. && unit < UNITS.len() - 1 {
+            size /= 1024.;
+            unit += 1;
+        }
+
+        if unit == 0 {
+            write!(formatter, "{} B", self.0)
+        } else {
+            write!(formatter, "{:.1} {}", size, UNITS[unit])
+        }
+    }
+}
+
+/// The footprint of the process so far.
+pub fn footprint() -> Footprint {
+    let files_written = FILES_WRITTEN.load(Ordering::Relaxed);
+    let bytes_written = BYTES_WRITTEN.load(Ordering::Relaxed);
+
+    Footprint {
+        programs: PROGRAMS.load(Ordering::Relaxed),
+        files_written,
+        bytes_written,
+        files_retained: files_written.saturating_sub(FILES_REMOVED.load(Ordering::Relaxed))
+            + FILES_PERSISTED.load(Ordering::Relaxed),
+        bytes_retained: bytes_written.saturating_sub(BYTES_REMOVED.load(Ordering::Relaxed))
+            + BYTES_PERSISTED.load(Ordering::Relaxed),
+    }
+}
+
+/// Print the [`footprint`] of the process on the standard error, and
+/// return it. It is meant to be called at the end of a test suite,
+/// e.g. from its last test.
+pub fn report() -> Footprint {
+    let footprint = footprint();
+    eprintln!("inline-c: {}", footprint);
+
+    footprint
+}
+
+fn size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.len())
+}
+
+/// Record a compiled program and its existing artifacts.
+pub(crate) fn record_program<'p>(artifacts: impl Iterator<Item = &'p Path>) {
+    PROGRAMS.fetch_add(1, Ordering::Relaxed);
+
+    for size in artifacts.filter_map(size) {
+        FILES_WRITTEN.fetch_add(1, Ordering::Relaxed);
+        BYTES_WRITTEN.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+/// Record the removal of an artifact of `size` bytes.
+pub(crate) fn record_removed(size: u64) {
+    FILES_REMOVED.fetch_add(1, Ordering::Relaxed);
+    BYTES_REMOVED.fetch_add(size, Ordering::Relaxed);
+}
+
+/// Record a persisted copy of an artifact.
+pub(crate) fn record_persisted(path: &Path) {
+    if let Some(size) = size(path) {
+        FILES_PERSISTED.fetch_add(1, Ordering::Relaxed);
+        BYTES_PERSISTED.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Footprint {
+                programs: 2,
+                files_written: 4,
+                bytes_written: 3 * 1024 * 1024 / 2,
+                files_retained: 1,
+                bytes_retained: 512,
+            }
+            .to_string(),
+            "2 program(s) compiled, 4 file(s) written (1.5 MiB), 1 file(s) retained (512 B)"
+        );
+    }
+}
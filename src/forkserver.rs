@@ -0,0 +1,263 @@
+//! An [AFL](https://lcamtuf.coredump.cx/afl/)-style fork server, so
+//! that repeated runs of the same program are a `fork` each, instead
+//! of a full `exec`.
+//!
+//! The `main` function of the program is renamed into
+//! `inline_c_rs_main`, and a new `main` function, from
+//! [`SOURCE`], is linked in. When the `INLINE_C_RS_FORK_SERVER`
+//! environment variable is set, it reads requests from its standard
+//! input, and for each of them, forks a child running the original
+//! `main` function. Otherwise, it calls the original `main` function
+//! directly.
+//!
+//! A request is a sequence of NUL-terminated strings: the number of
+//! arguments, the arguments (including the program name), and the
+//! paths to the standard input, standard output and standard error of
+//! the child. The response is the wait status of the child, in
+//! decimal, followed by a new line.
+
+#[cfg(unix)]
+pub(crate) use unix::ForkServer;
+
+/// The compiler flag renaming the `main` function of the program.
+pub(crate) const RENAME_MAIN_FLAG: &str = "-Dmain=inline_c_rs_main";
+
+/// The source of the fork server. It is valid C and C++. In C, the
+/// original `main` function is declared with its arguments, since
+/// `int f();` declares a function without parameters in C23.
+pub(crate) const SOURCE: &str = r#"
+#undef main
+
+// For strict standards, e.g. `-std=c11`.
+#define _POSIX_C_SOURCE 200809L
+
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifdef __cplusplus
+__attribute__((weak)) int inline_c_rs_main();
+__attribute__((weak)) int inline_c_rs_main(int, char **);
+#else
+int inline_c_rs_main(int, char **);
+#endif
+
+static int inline_c_rs_call_main(int argc, char **argv) {
+#ifdef __cplusplus
+    int (*with_arguments)(int, char **) = inline_c_rs_main;
+    int (*without_arguments)() = inline_c_rs_main;
+
+    if (with_arguments) {
+        return with_arguments(argc, argv);
+    }
+
+    return without_arguments();
+#else
+    return inline_c_rs_main(argc, argv);
+#endif
+}
+
+static char *inline_c_rs_read_string(void) {
+    size_t length = 0;
+    size_t capacity = 64;
+    char *string = (char *) malloc(capacity);
+    char byte;
+
+    while (read(0, &byte, 1) == 1) {
+        if (length == capacity) {
+            capacity *= 2;
+            string = (char *) realloc(string, capacity);
+        }
+
+        string[length++] = byte;
+
+        if (byte == '\0') {
+            return string;
+        }
+    }
+
+    free(string);
+
+    return NULL;
+}
+
+static void inline_c_rs_redirect(const char *path, int flags, int fd) {
+    int file = open(path, flags);
+
+    if (file < 0) {
+        _exit(126);
+    }
+
+    dup2(file, fd);
+    close(file);
+}
+
+int main(int argc, char **argv) {
+    if (getenv("INLINE_C_RS_FORK_SERVER") == NULL) {
+        return inline_c_rs_call_main(argc, argv);
+    }
+
+    unsetenv("INLINE_C_RS_FORK_SERVER");
+
+    for (;;) {
+        char *count = inline_c_rs_read_string();
+
+        if (count == NULL) {
+            return 0;
+        }
+
+        int request_argc = atoi(count);
+        char **request_argv = (char **) calloc(request_argc + 1, sizeof(char *));
+        char *paths[3];
+        int index;
+
+        free(count);
+
+        for (index = 0; index < request_argc; ++index) {
+            if ((request_argv[index] = inline_c_rs_read_string()) == NULL) {
+                return 1;
+            }
+        }
+
+        for (index = 0; index < 3; ++index) {
+            if ((paths[index] = inline_c_rs_read_string()) == NULL) {
+                return 1;
+            }
+        }
+
+        pid_t pid = fork();
+
+        if (pid == 0) {
+            inline_c_rs_redirect(paths[0], O_RDONLY, 0);
+            inline_c_rs_redirect(paths[1], O_WRONLY | O_TRUNC, 1);
+            inline_c_rs_redirect(paths[2], O_WRONLY | O_TRUNC, 2);
+
+            exit(inline_c_rs_call_main(request_argc, request_argv));
+        }
+
+        int status = 0;
+
+        if (pid < 0 || waitpid(pid, &status, 0) < 0) {
+            return 1;
+        }
+
+        char response[32];
+        int length = snprintf(response, sizeof(response), "%d\n", status);
+
+        if (write(1, response, length) != length) {
+            return 1;
+        }
+
+        for (index = 0; index < request_argc; ++index) {
+            free(request_argv[index]);
+        }
+
+        for (index = 0; index < 3; ++index) {
+            free(paths[index]);
+        }
+
+        free(request_argv);
+    }
+}
+"#;
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        error::Error,
+        ffi::OsString,
+        fs::{self, File},
+        io::{prelude::*, BufReader},
+        os::unix::{ffi::OsStrExt, process::ExitStatusExt},
+        process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Output, Stdio},
+    };
+    use tempfile::TempDir;
+
+    /// A running fork server, killed when dropped.
+    pub(crate) struct ForkServer {
+        server: Child,
+        requests: ChildStdin,
+        responses: BufReader<ChildStdout>,
+        program: OsString,
+        // Holds the standard streams of the children.
+        directory: TempDir,
+    }
+
+    impl ForkServer {
+        /// Start the fork server with `command`, which runs the compiled
+        /// program.
+        pub(crate) fn start(mut command: Command) -> Result<Self, Box<dyn Error>> {
+            let program = command.get_program().to_os_string();
+            let mut server = command
+                .env("INLINE_C_RS_FORK_SERVER", "1")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()?;
+
+            let requests = server.stdin.take().expect("The server has no stdin");
+            let responses = BufReader::new(server.stdout.take().expect("The server has no stdout"));
+
+            Ok(Self {
+                server,
+                requests,
+                responses,
+                program,
+                directory: tempfile::Builder::new()
+                    .prefix("inline-c-rs-fork-server-")
+                    .tempdir()?,
+            })
+        }
+
+        pub(crate) fn run(
+            &mut self,
+            args: &[OsString],
+            stdin: Option<&[u8]>,
+        ) -> Result<Output, Box<dyn Error>> {
+            let paths = ["stdin", "stdout", "stderr"].map(|name| self.directory.path().join(name));
+
+            fs::write(&paths[0], stdin.unwrap_or_default())?;
+            File::create(&paths[1])?;
+            File::create(&paths[2])?;
+
+            let mut request = format!("{}\0", args.len() + 1).into_bytes();
+
+            for string in std::iter::once(&self.program)
+                .chain(args)
+                .map(|arg| arg.as_bytes())
+                .chain(paths.iter().map(|path| path.as_os_str().as_bytes()))
+            {
+                if string.contains(&0) {
+                    return Err("An argument of the program contains a NUL byte".into());
+                }
+
+                request.extend_from_slice(string);
+                request.push(0);
+            }
+
+            self.requests.write_all(&request)?;
+            self.requests.flush()?;
+
+            let mut response = String::new();
+
+            if self.responses.read_line(&mut response)? == 0 {
+                return Err("The fork server has stopped unexpectedly".into());
+            }
+
+            Ok(Output {
+                status: ExitStatus::from_raw(response.trim().parse()?),
+                stdout: fs::read(&paths[1])?,
+                stderr: fs::read(&paths[2])?,
+            })
+        }
+    }
+
+    impl Drop for ForkServer {
+        fn drop(&mut self) {
+            let _ = self.server.kill();
+            let _ = self.server.wait();
+        }
+    }
+}
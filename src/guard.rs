@@ -0,0 +1,362 @@
+//! Kill the running program, and the processes it has spawned, when
+//! its guard is dropped, e.g. when the test panics while the program
+//! runs, so that no program lingers after the tests.
+//!
+//! The program runs in a process group of its own on Unix, or in a
+//! Job Object on Windows, so that the whole tree of processes is torn
+//! down, also once the program has exited.
+
+use std::{
+    io::{self, prelude::*, BufReader},
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    thread::{self, JoinHandle},
+};
+
+/// A running program, killed with its process group when dropped,
+/// unless it has been spawned with `kill_on_drop` off.
+pub(crate) struct ProcessGuard {
+    child: Child,
+    kill_on_drop: bool,
+    #[cfg(windows)]
+    job: Option<windows::Job>,
+    // Whether the program has been waited for, after which its
+    // process ID may be reused.
+    reaped: bool,
+}
+
+impl ProcessGuard {
+    /// Spawn `command`. With `kill_on_drop`, the program runs in a
+    /// process group of its own, or in a Job Object, so that the
+    /// processes it spawns are killed with it. On Linux, it is also killed if the test harness
+    /// dies, e.g. when it is interrupted, because it no longer
+    /// receives the signals of the terminal.
+    pub(crate) fn spawn(command: &mut Command, kill_on_drop: bool) -> io::Result<Self> {
+        if kill_on_drop {
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+
+                command.process_group(0);
+            }
+
+            #[cfg(target_os = "linux")]
+            unsafe {
+                use std::os::unix::process::CommandExt;
+
+                command.pre_exec(|| {
+                    // The signal is sent when the thread which has
+                    // spawned the program exits.
+                    if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+
+                    Ok(())
+                });
+            }
+        }
+
+        let child = command.spawn()?;
+
+        Ok(Self {
+            // Without a Job Object, e.g. if the jobs cannot be nested,
+            // only the program is killed.
+            #[cfg(windows)]
+            job: kill_on_drop
+                .then(|| windows::Job::assign(&child).ok())
+                .flatten(),
+            child,
+            kill_on_drop,
+            reaped: false,
+        })
+    }
+
+    pub(crate) fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Kill the program, and its process group if it has one. The
+    /// program may already have exited.
+    pub(crate) fn kill(&mut self) {
+        if self.reaped {
+            return;
+        }
+
+        self.kill_tree();
+        let _ = self.child.kill();
+    }
+
+    /// Wait for the program to exit, then kill the processes it has
+    /// left behind, if it has been spawned with `kill_on_drop`.
+    pub(crate) fn wait(&mut self) -> io::Result<ExitStatus> {
+        if self.kill_on_drop && !self.reaped {
+            // The program is not reaped yet, so that the ID of its
+            // process group cannot be reused before it is killed.
+            #[cfg(unix)]
+            loop {
+                let mut information = std::mem::MaybeUninit::<libc::siginfo_t>::zeroed();
+
+                if unsafe {
+                    libc::waitid(
+                        libc::P_PID,
+                        self.child.id() as libc::id_t,
+                        information.as_mut_ptr(),
+                        libc::WEXITED | libc::WNOWAIT,
+                    )
+                } == 0
+                {
+                    break;
+                }
+
+                let error = io::Error::last_os_error();
+
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+
+            #[cfg(windows)]
+            self.child.wait()?;
+
+            self.kill_tree();
+        }
+
+        let status = self.child.wait()?;
+        self.reaped = true;
+
+        Ok(status)
+    }
+
+    fn kill_tree(&self) {
+        if !self.kill_on_drop {
+            return;
+        }
+
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-(self.child.id() as libc::pid_t), libc::SIGKILL);
+        }
+
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+    }
+}
+
+impl Drop for ProcessGuard {
+    fn drop(&mut self) {
+        if self.kill_on_drop && !self.reaped {
+            self.kill();
+            let _ = self.wait();
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{ffi::c_void, io, mem, os::windows::io::AsRawHandle, process::Child, ptr};
+
+    const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION: i32 = 9;
+    const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x2000;
+
+    /// `JOBOBJECT_EXTENDED_LIMIT_INFORMATION`.
+    #[repr(C)]
+    #[derive(Default)]
+    struct ExtendedLimitInformation {
+        per_process_user_time_limit: i64,
+        per_job_user_time_limit: i64,
+        limit_flags: u32,
+        minimum_working_set_size: usize,
+        maximum_working_set_size: usize,
+        active_process_limit: u32,
+        affinity: usize,
+        priority_class: u32,
+        scheduling_class: u32,
+        io_counters: [u64; 6],
+        process_memory_limit: usize,
+        job_memory_limit: usize,
+        peak_process_memory_used: usize,
+        peak_job_memory_used: usize,
+    }
+
+    extern "system" {
+        fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> *mut c_void;
+        fn SetInformationJobObject(
+            job: *mut c_void,
+            class: i32,
+            information: *mut c_void,
+            length: u32,
+        ) -> i32;
+        fn AssignProcessToJobObject(job: *mut c_void, process: *mut c_void) -> i32;
+        fn TerminateJobObject(job: *mut c_void, exit_code: u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    /// A Job Object, whose processes are killed when it is closed.
+    // The handle is stored as an integer, so that the guard is `Send`.
+    pub(super) struct Job(isize);
+
+    impl Job {
+        pub(super) fn assign(child: &Child) -> io::Result<Self> {
+            let handle = unsafe { CreateJobObjectW(ptr::null_mut(), ptr::null()) };
+
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let job = Job(handle as isize);
+            let mut information = ExtendedLimitInformation {
+                limit_flags: JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+                ..Default::default()
+            };
+
+            let assigned = unsafe {
+                SetInformationJobObject(
+                    handle,
+                    JOB_OBJECT_EXTENDED_LIMIT_INFORMATION,
+                    &mut information as *mut _ as *mut c_void,
+                    mem::size_of::<ExtendedLimitInformation>() as u32,
+                ) != 0
+                    && AssignProcessToJobObject(handle, child.as_raw_handle() as *mut c_void) != 0
+            };
+
+            if !assigned {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(job)
+        }
+
+        pub(super) fn terminate(&self) {
+            unsafe { TerminateJobObject(self.0 as *mut c_void, 1) };
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            unsafe { CloseHandle(self.0 as *mut c_void) };
+        }
+    }
+}
+
+/// Run `command` with a guard, write `stdin` to its standard input,
+/// and wait for its output. The processes left behind by the program
+/// are killed once it has exited, so that they do not keep its output
+/// open. With `tee`, the output is also streamed to the standard error
+/// of the test while it is captured.
+pub(crate) fn output(command: &mut Command, stdin: Option<&[u8]>, tee: bool) -> io::Result<Output> {
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut guard = ProcessGuard::spawn(command, true)?;
+    let child = guard.child();
+
+    let mut child_stdin = child.stdin.take().expect("The child has no stdin");
+    let stdin = stdin.unwrap_or_default().to_vec();
+    let stdin_thread = thread::spawn(move || {
+        // The program may exit without reading its input.
+        let _ = child_stdin.write_all(&stdin);
+    });
+
+    let stdout_thread = capture(child.stdout.take().expect("The child has no stdout"), tee);
+    let stderr_thread = capture(child.stderr.take().expect("The child has no stderr"), tee);
+
+    let status = guard.wait()?;
+    let stdout = stdout_thread
+        .join()
+        .expect("The stdout reader has panicked")?;
+    let stderr = stderr_thread
+        .join()
+        .expect("The stderr reader has panicked")?;
+    let _ = stdin_thread.join();
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Read `reader` to its end in a thread. With `tee`, each line is
+/// also written to the standard error of the test as soon as it is
+/// read, with `eprint!` so that the test harness still captures it
+/// unless `--nocapture` is given.
+fn capture<R>(reader: R, tee: bool) -> JoinHandle<io::Result<Vec<u8>>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut output = Vec::new();
+
+        if !tee {
+            reader.read_to_end(&mut output)?;
+
+            return Ok(output);
+        }
+
+        loop {
+            let start = output.len();
+
+            if reader.read_until(b'\n', &mut output)? == 0 {
+                return Ok(output);
+            }
+
+            eprint!("{}", String::from_utf8_lossy(&output[start..]));
+        }
+    })
+}
+
+/// Assert that the process `pid` exits soon, e.g. once it has been
+/// killed with the tree of processes of a program.
+#[cfg(all(test, target_os = "linux"))]
+pub(crate) fn assert_exits(pid: u32) {
+    use std::{fs, time::Duration};
+
+    // A killed process is a zombie until it is reaped by its new
+    // parent.
+    let is_running = || {
+        fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(|stat| !stat.contains(") Z "))
+    };
+
+    for _ in 0..500 {
+        if !is_running() {
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("The process spawned by the program is still running");
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::{fs, thread, time::Duration};
+
+    #[test]
+    fn test_kill_on_drop() {
+        let pid_file = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("sleep 60 & echo $! > {}; wait", pid_file.display()));
+
+        let guard = ProcessGuard::spawn(&mut command, true).unwrap();
+
+        let grandchild = loop {
+            if let Ok(pid) = fs::read_to_string(&pid_file).unwrap().trim().parse::<u32>() {
+                break pid;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        drop(guard);
+
+        assert_exits(grandchild);
+    }
+}
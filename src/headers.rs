@@ -0,0 +1,108 @@
+//! Record the headers included by the program, with `-M` (GCC and
+//! Clang) or `/showIncludes` (MSVC), in a preprocessing pass.
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Run `command`, the compiler with the flags of the program, to list
+/// the headers included by the program at `input_path`, directly or
+/// not.
+pub(crate) fn included_headers(
+    mut command: Command,
+    input_path: &Path,
+    msvc: bool,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if msvc {
+        command.args(["/showIncludes", "/Zs"]);
+    } else {
+        command.arg("-M");
+    }
+
+    let output = command
+        .arg(input_path)
+        .output()
+        .map_err(|error| format!("Failed to list the included headers: {}", error))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to list the included headers: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    Ok(if msvc {
+        parse_show_includes(&stdout)
+    } else {
+        parse_make_rule(&stdout)
+    })
+}
+
+/// Parse the `Note: including file: <path>` lines. The note is
+/// localized, but it always ends with a colon, followed by the path,
+/// indented by its depth.
+fn parse_show_includes(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| line.rsplit_once(": "))
+        .map(|(_, path)| path.trim())
+        .filter(|path| {
+            let bytes = path.as_bytes();
+
+            path.starts_with('\\')
+                || (bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && &bytes[1..3] == b":\\")
+        })
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Parse a Make rule, e.g. `out.o: in.c foo.h \` then ` bar.h`. The
+/// first prerequisite is the program itself.
+fn parse_make_rule(output: &str) -> Vec<PathBuf> {
+    let rule = output.replace("\\\n", " ").replace("\\\r\n", " ");
+    let prerequisites = rule
+        .split_once(": ")
+        .map_or("", |(_, prerequisites)| prerequisites);
+
+    prerequisites
+        .replace("\\ ", "\0")
+        .split_whitespace()
+        .skip(1)
+        .map(|path| PathBuf::from(path.replace('\0', " ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_make_rule(
+                "inline-c-rs-abc.o: /tmp/inline-c-rs-abc.c /usr/include/stdio.h \\\n \
+                 /tmp/my\\ dir/foo.h\n"
+            ),
+            [
+                PathBuf::from("/usr/include/stdio.h"),
+                PathBuf::from("/tmp/my dir/foo.h")
+            ]
+        );
+        assert_eq!(
+            parse_show_includes(
+                "inline-c-rs-abc.c\n\
+                 Note: including file: C:\\include\\foo.h\n\
+                 Note: including file:  C:\\include\\bar.h\n"
+            ),
+            [
+                PathBuf::from("C:\\include\\foo.h"),
+                PathBuf::from("C:\\include\\bar.h")
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Heap profiling with Valgrind's Massif or with Heaptrack.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HeapProfiler {
+    Massif,
+    Heaptrack,
+}
+
+/// A heap profile, written by a heap profiler when the program runs.
+#[derive(Debug, Clone)]
+pub(crate) struct HeapProfile {
+    profiler: HeapProfiler,
+    path: PathBuf,
+}
+
+impl HeapProfile {
+    /// Create a heap profile for the program `program_path`. The
+    /// report is written next to the program.
+    pub(crate) fn new(profiler: &str, program_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let profiler = match profiler {
+            "massif" => HeapProfiler::Massif,
+            "heaptrack" => HeapProfiler::Heaptrack,
+            profiler => {
+                return Err(format!(
+                    "Invalid heap profiler `{}`, expected `massif` or `heaptrack`",
+                    profiler
+                )
+                .into())
+            }
+        };
+
+        let (tool, extension) = match profiler {
+            HeapProfiler::Massif => ("valgrind", "massif"),
+            HeapProfiler::Heaptrack => ("heaptrack", "heaptrack"),
+        };
+
+        if Command::new(tool).arg("--version").output().is_err() {
+            return Err(format!("The heap profiler requires `{}` to be installed", tool).into());
+        }
+
+        let mut path = program_path.as_os_str().to_os_string();
+        path.push(".");
+        path.push(extension);
+
+        Ok(Self {
+            profiler,
+            path: PathBuf::from(path),
+        })
+    }
+
+    /// The command and arguments that run the program under the heap
+    /// profiler.
+    pub(crate) fn launcher(&self) -> Vec<OsString> {
+        match self.profiler {
+            HeapProfiler::Massif => {
+                let mut out_file = OsString::from("--massif-out-file=");
+                out_file.push(&self.path);
+
+                vec![
+                    OsString::from("valgrind"),
+                    OsString::from("--tool=massif"),
+                    OsString::from("--quiet"),
+                    out_file,
+                    OsString::from("--"),
+                ]
+            }
+
+            HeapProfiler::Heaptrack => vec![
+                OsString::from("heaptrack"),
+                OsString::from("--output"),
+                self.path.clone().into_os_string(),
+            ],
+        }
+    }
+
+    /// The files that may be written by the heap profiler.
+    pub(crate) fn files(&self) -> Vec<PathBuf> {
+        match self.profiler {
+            HeapProfiler::Massif => vec![self.path.clone()],
+            HeapProfiler::Heaptrack => [".zst", ".gz"]
+                .iter()
+                .map(|extension| {
+                    let mut path = self.path.clone().into_os_string();
+                    path.push(extension);
+
+                    PathBuf::from(path)
+                })
+                .collect(),
+        }
+    }
+
+    /// The path to the report, once the program has run.
+    pub(crate) fn report_path(&self) -> Option<PathBuf> {
+        self.files().into_iter().find(|path| path.exists())
+    }
+
+    /// Read the peak heap consumption, in bytes, from the report.
+    pub(crate) fn peak_heap(&self) -> Result<u64, Box<dyn Error>> {
+        let report_path = self
+            .report_path()
+            .ok_or("No heap profile has been found, has the program run?")?;
+
+        match self.profiler {
+            HeapProfiler::Massif => parse_massif(&fs::read_to_string(report_path)?),
+            HeapProfiler::Heaptrack => {
+                let output = Command::new("heaptrack_print")
+                    .arg("--print-peaks")
+                    .arg("0")
+                    .arg(&report_path)
+                    .output()?;
+
+                parse_heaptrack(&String::from_utf8_lossy(&output.stdout))
+            }
+        }
+    }
+}
+
+/// Parse a Massif report, and return the biggest `mem_heap_B`
+/// snapshot.
+fn parse_massif(report: &str) -> Result<u64, Box<dyn Error>> {
+    report
+        .lines()
+        .filter_map(|line| line.strip_prefix("mem_heap_B="))
+        .map(|bytes| bytes.trim().parse::<u64>())
+        .try_fold(None, |peak: Option<u64>, bytes| {
+            Ok(Some(peak.unwrap_or(0).max(bytes?)))
+        })
+        .and_then(|peak: Option<u64>| peak.ok_or_else(|| "Empty Massif report".into()))
+}
+
+/// Parse the output of `heaptrack_print`, e.g. `peak heap memory
+/// consumption: 4.10K`.
+fn parse_heaptrack(report: &str) -> Result<u64, Box<dyn Error>> {
+    let value = report
+        .lines()
+        .find_map(|line| line.strip_prefix("peak heap memory consumption:"))
+        .ok_or("No peak heap memory consumption in the Heaptrack report")?
+        .trim();
+
+    let (number, multiplier) = match value.chars().last() {
+        Some('K') => (&value[..value.len() - 1], 1e3),
+        Some('M') => (&value[..value.len() - 1], 1e6),
+        Some('G') => (&value[..value.len() - 1], 1e9),
+        Some('B') => (&value[..value.len() - 1], 1.),
+        _ => (value, 1.),
+    };
+
+    Ok((number.parse::<f64>()? * multiplier) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_massif() {
+        let report = "desc: (none)\n\
+                      cmd: ./program\n\
+                      time_unit: i\n\
+                      #-----------\n\
+                      snapshot=0\n\
+                      #-----------\n\
+                      time=0\n\
+                      mem_heap_B=0\n\
+                      mem_heap_extra_B=0\n\
+                      mem_stacks_B=0\n\
+                      heap_tree=empty\n\
+                      #-----------\n\
+                      snapshot=1\n\
+                      #-----------\n\
+                      time=1\n\
+                      mem_heap_B=1024\n\
+                      mem_heap_extra_B=8\n\
+                      mem_stacks_B=0\n\
+                      heap_tree=peak\n";
+
+        assert_eq!(parse_massif(report).unwrap(), 1024);
+        assert!(parse_massif("").is_err());
+    }
+
+    #[test]
+    fn test_parse_heaptrack() {
+        assert_eq!(
+            parse_heaptrack("total runtime: 0.01s.\npeak heap memory consumption: 4.10K\n")
+                .unwrap(),
+            4100
+        );
+    }
+}
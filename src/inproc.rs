@@ -0,0 +1,531 @@
+//! Run a program compiled as a shared object inside the test process,
+//! so that it can call Rust functions of the test binary.
+//!
+//! Each callback `name` is rewritten by the preprocessor into a call
+//! through the `inline_c_rs_callback_<name>` function pointer, which
+//! is set to the address of the Rust function once the shared object
+//! is loaded. The entry point (`main` by default, or the
+//! `entry_point` directive) is then called in a forked child, whose
+//! standard output and standard error are captured, so that a crash
+//! does not take the test harness down.
+//!
+//! The `main` function is renamed into `inline_c_rs_main`, so that
+//! the shared object does not export a `main` function, and it is
+//! called by the `inline_c_rs_entry` function of [`ENTRY_SOURCE`],
+//! whose name is not mangled in C++.
+
+use std::{error::Error, ffi::OsString, path::Path, process::Output};
+
+/// A Rust function exposed to the program. See
+/// [`callbacks!`](crate::callbacks).
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct Callback {
+    pub name: &'static str,
+    pub address: usize,
+}
+
+impl Callback {
+    fn pointer_name(&self) -> String {
+        format!("inline_c_rs_callback_{}", self.name)
+    }
+
+    /// The compiler flag redirecting the calls to `name` through its
+    /// function pointer, e.g. `int add_one(int);` becomes a function
+    /// pointer declaration.
+    pub(crate) fn define_flag(&self) -> String {
+        format!("-D{}=(*{})", self.name, self.pointer_name())
+    }
+}
+
+/// A source file defining the function pointers of the callbacks.
+/// They are weak, because a prototype of a callback in the program
+/// already defines its function pointer.
+pub(crate) fn callbacks_source(callbacks: &[Callback]) -> String {
+    callbacks
+        .iter()
+        .map(|callback| format!("__attribute__((weak)) void *{};\n", callback.pointer_name()))
+        .collect()
+}
+
+/// The entry point calling the renamed `main` function, see
+/// [`ENTRY_SOURCE`].
+pub(crate) const ENTRY_POINT: &str = "inline_c_rs_entry";
+
+/// The source of the entry point, compiled with the program. It is
+/// valid C and C++; in C++, `main` may or may not take arguments. In
+/// C, it is declared with its arguments, since `int f();` declares a
+/// function without parameters in C23. The declarations are weak, so
+/// that a library without `main` still loads; on macOS, it needs
+/// `-undefined dynamic_lookup`, see [`shared_object_flags`].
+pub(crate) const ENTRY_SOURCE: &str = r#"
+#undef main
+
+#include <stdio.h>
+
+#ifdef __cplusplus
+__attribute__((weak)) int inline_c_rs_main();
+__attribute__((weak)) int inline_c_rs_main(int, char **);
+
+extern "C" __attribute__((visibility("default"))) int inline_c_rs_entry(int argc, char **argv) {
+    int (*with_arguments)(int, char **) = inline_c_rs_main;
+    int (*without_arguments)() = inline_c_rs_main;
+
+    if (with_arguments) {
+        return with_arguments(argc, argv);
+    }
+
+    if (without_arguments) {
+        return without_arguments();
+    }
+#else
+__attribute__((weak)) int inline_c_rs_main(int, char **);
+
+__attribute__((visibility("default"))) int inline_c_rs_entry(int argc, char **argv) {
+    if (inline_c_rs_main) {
+        return inline_c_rs_main(argc, argv);
+    }
+#endif
+
+    fputs("The program has no `main` function\n", stderr);
+
+    return 1;
+}
+"#;
+
+/// The compiler flags producing a shared object.
+pub(crate) fn shared_object_flags() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["-shared", "-fPIC", "-undefined", "dynamic_lookup"]
+    } else {
+        &["-shared", "-fPIC"]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SharedObject {
+    path: std::path::PathBuf,
+    entry_point: String,
+    callbacks: Vec<Callback>,
+    variables: Vec<(String, String)>,
+}
+
+impl SharedObject {
+    pub(crate) fn new<I>(
+        path: std::path::PathBuf,
+        entry_point: &str,
+        callbacks: &[Callback],
+        variables: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        Self {
+            path,
+            entry_point: entry_point.to_string(),
+            callbacks: callbacks.to_vec(),
+            variables: variables.into_iter().collect(),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn run(
+        &self,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+    ) -> Result<Output, Box<dyn Error>> {
+        unix::run(self, args, stdin)
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn run(
+        &self,
+        _args: &[OsString],
+        _stdin: Option<&[u8]>,
+    ) -> Result<Output, Box<dyn Error>> {
+        Err("Running a program in-process is only supported on Unix".into())
+    }
+}
+
+/// Load the shared object at `path` with all its symbols resolved
+/// immediately, i.e. `dlopen` with `RTLD_NOW` or `LoadLibrary`, then
+/// unload it. The error names the missing symbols or libraries.
+#[cfg(unix)]
+pub(crate) fn load(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    unix::Library::open(&path).map(drop)
+}
+
+#[cfg(windows)]
+pub(crate) fn load(path: &Path) -> Result<(), Box<dyn Error>> {
+    use std::{ffi::c_void, io, os::windows::ffi::OsStrExt};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn FreeLibrary(module: *mut c_void) -> i32;
+    }
+
+    let path = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<_>>();
+    let module = unsafe { LoadLibraryW(path.as_ptr()) };
+
+    if module.is_null() {
+        return Err(format!(
+            "Failed to load the shared object: {}",
+            io::Error::last_os_error()
+        )
+        .into());
+    }
+
+    unsafe { FreeLibrary(module) };
+
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn load(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Err("Loading a shared object is only supported on Unix and Windows".into())
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::SharedObject;
+    use std::{
+        collections::HashMap,
+        error::Error,
+        ffi::{CStr, CString, OsString},
+        fs::File,
+        io::{self, prelude::*},
+        os::unix::{
+            ffi::OsStrExt,
+            io::{AsRawFd, FromRawFd},
+            process::ExitStatusExt,
+        },
+        process::{ExitStatus, Output},
+        ptr, thread,
+    };
+
+    type Main = unsafe extern "C" fn(libc::c_int, *const *const libc::c_char) -> libc::c_int;
+
+    /// The location of the `environ` pointer of the process.
+    #[cfg(target_os = "macos")]
+    unsafe fn environ() -> *mut *const *const libc::c_char {
+        libc::_NSGetEnviron() as *mut *const *const libc::c_char
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    unsafe fn environ() -> *mut *const *const libc::c_char {
+        extern "C" {
+            static mut environ: *const *const libc::c_char;
+        }
+
+        ptr::addr_of_mut!(environ)
+    }
+
+    /// A `dlopen`ed library, closed when dropped.
+    pub(super) struct Library(*mut libc::c_void);
+
+    impl Library {
+        pub(super) fn open(path: &CStr) -> Result<Self, Box<dyn Error>> {
+            let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+
+            if handle.is_null() {
+                return Err(format!("Failed to load the shared object: {}", dlerror()).into());
+            }
+
+            Ok(Self(handle))
+        }
+
+        fn symbol(&self, name: &str) -> Result<*mut libc::c_void, Box<dyn Error>> {
+            let name = CString::new(name)?;
+            let symbol = unsafe { libc::dlsym(self.0, name.as_ptr()) };
+
+            if symbol.is_null() {
+                return Err(format!("Symbol not found: {}", dlerror()).into());
+            }
+
+            Ok(symbol)
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            unsafe { libc::dlclose(self.0) };
+        }
+    }
+
+    fn dlerror() -> String {
+        let error = unsafe { libc::dlerror() };
+
+        if error.is_null() {
+            String::from("unknown error")
+        } else {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    fn pipe() -> io::Result<(File, File)> {
+        let mut fds = [0; 2];
+
+        // Processes spawned concurrently by other tests must not
+        // inherit the pipes, otherwise they would be kept open.
+        #[cfg(target_os = "linux")]
+        {
+            if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            for fd in &fds {
+                unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            }
+        }
+
+        Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+    }
+
+    /// Close the file descriptors from `first` up to `max`, in the
+    /// child. Unlike with `exec`, `FD_CLOEXEC` has no effect there, so
+    /// the child would otherwise keep the pipes of the tests running
+    /// concurrently open, e.g. the standard input of another program,
+    /// which would never see its end. Only async-signal-safe functions
+    /// are called.
+    unsafe fn close_from(first: libc::c_int, max: libc::c_int) {
+        #[cfg(target_os = "linux")]
+        {
+            if libc::syscall(
+                libc::SYS_close_range,
+                first as libc::c_uint,
+                libc::c_uint::MAX,
+                0,
+            ) == 0
+            {
+                return;
+            }
+        }
+
+        for fd in first..max {
+            libc::close(fd);
+        }
+    }
+
+    pub(super) fn run(
+        shared_object: &SharedObject,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+    ) -> Result<Output, Box<dyn Error>> {
+        // Everything that allocates happens before `fork`: the test
+        // process is multi-threaded, and the child may only call
+        // async-signal-safe functions until the program runs.
+        let path = CString::new(shared_object.path.as_os_str().as_bytes())?;
+        let library = Library::open(&path)?;
+
+        for callback in &shared_object.callbacks {
+            let pointer = library.symbol(&callback.pointer_name())? as *mut usize;
+            unsafe { *pointer = callback.address };
+        }
+
+        let main: Main =
+            unsafe { std::mem::transmute(library.symbol(&shared_object.entry_point)?) };
+
+        let argv = std::iter::once(path.clone())
+            .chain(
+                args.iter()
+                    .map(|arg| CString::new(arg.as_bytes()))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )
+            .collect::<Vec<_>>();
+        let mut argv_pointers = argv.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+        argv_pointers.push(ptr::null());
+
+        // The environment of the program is built here, and the child
+        // only swaps `environ`: `setenv` may allocate, or wait for a lock
+        // held by another thread at the time of the `fork`.
+        let mut variables = std::env::vars_os().collect::<HashMap<_, _>>();
+        variables.extend(
+            shared_object
+                .variables
+                .iter()
+                .map(|(name, value)| (OsString::from(name), OsString::from(value))),
+        );
+        let environment = variables
+            .iter()
+            .map(|(name, value)| {
+                let mut variable = name.as_bytes().to_vec();
+                variable.push(b'=');
+                variable.extend_from_slice(value.as_bytes());
+
+                CString::new(variable)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut environment_pointers = environment
+            .iter()
+            .map(|variable| variable.as_ptr())
+            .collect::<Vec<_>>();
+        environment_pointers.push(ptr::null());
+
+        let (stdin_reader, mut stdin_writer) = pipe()?;
+        let (mut stdout_reader, stdout_writer) = pipe()?;
+        let (mut stderr_reader, stderr_writer) = pipe()?;
+
+        let max_fd = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+            max_fd if max_fd > 0 => max_fd.min(libc::c_int::MAX as libc::c_long) as libc::c_int,
+            _ => 1024,
+        };
+
+        // Do not duplicate the pending buffers of the parent.
+        unsafe { libc::fflush(ptr::null_mut()) };
+
+        let pid = unsafe { libc::fork() };
+
+        if pid < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        if pid == 0 {
+            unsafe {
+                libc::dup2(stdin_reader.as_raw_fd(), 0);
+                libc::dup2(stdout_writer.as_raw_fd(), 1);
+                libc::dup2(stderr_writer.as_raw_fd(), 2);
+                close_from(3, max_fd);
+
+                *environ() = environment_pointers.as_ptr();
+
+                let code = main(
+                    (argv_pointers.len() - 1) as libc::c_int,
+                    argv_pointers.as_ptr(),
+                );
+
+                libc::fflush(ptr::null_mut());
+                libc::_exit(code);
+            }
+        }
+
+        drop(stdin_reader);
+        drop(stdout_writer);
+        drop(stderr_writer);
+
+        let stdin = stdin.unwrap_or_default().to_vec();
+        let stdin_thread = thread::spawn(move || {
+            // The program may exit without reading its input.
+            let _ = stdin_writer.write_all(&stdin);
+        });
+        let stderr_thread = thread::spawn(move || {
+            let mut stderr = Vec::new();
+            stderr_reader.read_to_end(&mut stderr).map(|_| stderr)
+        });
+
+        let mut stdout = Vec::new();
+        stdout_reader.read_to_end(&mut stdout)?;
+        let stderr = stderr_thread
+            .join()
+            .expect("The stderr reader has panicked")?;
+        let _ = stdin_thread.join();
+
+        let mut status = 0;
+
+        if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        drop(library);
+
+        Ok(Output {
+            status: ExitStatus::from_raw(status),
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Expose Rust functions of the test to the program, as the first
+/// statement of `assert_c!` or `assert_cxx!`. The functions must be
+/// `extern "C"`, and must be declared in the program.
+///
+/// The program is then compiled as a shared object, loaded inside the
+/// test process, and run in a forked child. It is only supported on
+/// Unix.
+///
+/// ```rust
+/// use inline_c::assert_c;
+///
+/// extern "C" fn add_one(x: i32) -> i32 {
+///     x + 1
+/// }
+///
+/// fn test_callbacks() {
+///     (assert_c! {
+///         callbacks!(add_one);
+///
+///         int add_one(int x);
+///
+///         int main() {
+///             return add_one(41);
+///         }
+///     })
+///     .assert()
+///     .code(42);
+/// }
+///
+/// # fn main() {
+/// #     #[cfg(unix)]
+/// #     test_callbacks()
+/// # }
+/// ```
+#[macro_export]
+macro_rules! callbacks {
+    ($($name:ident),* $(,)?) => {
+        [$(
+            $crate::Callback {
+                name: stringify!($name),
+                address: $name as *const () as usize,
+            }
+        ),*]
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callbacks_source() {
+        let callback = Callback {
+            name: "add_one",
+            address: 0,
+        };
+
+        assert_eq!(
+            callback.define_flag(),
+            "-Dadd_one=(*inline_c_rs_callback_add_one)"
+        );
+        assert_eq!(
+            callbacks_source(&[callback]),
+            "__attribute__((weak)) void *inline_c_rs_callback_add_one;\n"
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_shared_object_flags() {
+        // The weak `inline_c_rs_main` of `ENTRY_SOURCE` is undefined
+        // in a library without `main`.
+        assert!(shared_object_flags()
+            .windows(2)
+            .any(|flags| flags == ["-undefined", "dynamic_lookup"]));
+    }
+}
@@ -0,0 +1,240 @@
+//! Record the outputs of programs into a journal file, and replay
+//! them later on, e.g. on a machine without a C compiler.
+//!
+//! The journal is configured with the special `journal` directive (the
+//! path to the journal file) and the special `journal_mode` directive
+//! (`record` or `replay`).
+//!
+//! The journal is a text file, with one entry per line. An entry has
+//! 4 tab-separated columns: the key of the execution, the exit status
+//! (`code:<n>` or `signal:<n>`), and the hex-encoded standard output
+//! and standard error.
+
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::prelude::*,
+    path::PathBuf,
+    process::{ExitStatus, Output},
+    sync::Mutex,
+};
+
+lazy_static! {
+    // Tests run in parallel, let's not interleave the entries.
+    static ref JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum JournalMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    mode: JournalMode,
+    key: u64,
+}
+
+impl Journal {
+    /// Read the journal configuration from the variables, if any.
+    /// `key` identifies the program.
+    pub(crate) fn from_variables(
+        variables: &HashMap<String, String>,
+        key: u64,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        let path = match variables.get("journal") {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(None),
+        };
+
+        let mode = match variables.get("journal_mode").map(String::as_str) {
+            Some("record") | None => JournalMode::Record,
+            Some("replay") => JournalMode::Replay,
+            Some(mode) => {
+                return Err(format!(
+                    "Invalid `journal_mode` value `{}`, expected `record` or `replay`",
+                    mode
+                )
+                .into())
+            }
+        };
+
+        Ok(Some(Self { path, mode, key }))
+    }
+
+    pub(crate) fn mode(&self) -> JournalMode {
+        self.mode
+    }
+
+    /// Compute the key of an execution, from the key of the program,
+    /// its arguments and its standard input.
+    fn execution_key(&self, args: &[OsString], stdin: Option<&[u8]>) -> String {
+        let mut bytes = self.key.to_le_bytes().to_vec();
+
+        for arg in args {
+            bytes.extend_from_slice(arg.to_string_lossy().as_bytes());
+            bytes.push(0);
+        }
+
+        if let Some(stdin) = stdin {
+            bytes.extend_from_slice(stdin);
+        }
+
+        format!("{:016x}", hash(&bytes))
+    }
+
+    pub(crate) fn record(
+        &self,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+        output: &Output,
+    ) -> Result<(), Box<dyn Error>> {
+        let status = match output.status.code() {
+            Some(code) => format!("code:{}", code),
+            None => format!("signal:{}", signal(&output.status).unwrap_or(0)),
+        };
+
+        let entry = format!(
+            "{}\t{}\t{}\t{}\n",
+            self.execution_key(args, stdin),
+            status,
+            hex_encode(&output.stdout),
+            hex_encode(&output.stderr),
+        );
+
+        let _lock = JOURNAL_LOCK
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(entry.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub(crate) fn replay(
+        &self,
+        args: &[OsString],
+        stdin: Option<&[u8]>,
+    ) -> Result<Output, Box<dyn Error>> {
+        let key = self.execution_key(args, stdin);
+        let journal = {
+            let _lock = JOURNAL_LOCK
+                .lock()
+                .unwrap_or_else(|error| error.into_inner());
+
+            fs::read_to_string(&self.path)?
+        };
+
+        // The last recorded entry wins.
+        let entry = journal
+            .lines()
+            .rev()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .find(|columns| columns.len() == 4 && columns[0] == key)
+            .ok_or_else(|| {
+                format!(
+                    "No entry `{}` in the journal `{}`; record it first",
+                    key,
+                    self.path.display()
+                )
+            })?;
+
+        let status = match entry[1].split_once(':') {
+            Some(("code", code)) => exit_status_from_code(code.parse()?),
+            Some(("signal", signal)) => exit_status_from_signal(signal.parse()?),
+            _ => return Err(format!("Invalid exit status `{}` in the journal", entry[1]).into()),
+        };
+
+        Ok(Output {
+            status,
+            stdout: hex_decode(entry[2])?,
+            stderr: hex_decode(entry[3])?,
+        })
+    }
+}
+
+/// A FNV-1a hash, which is stable across platforms and Rust versions,
+/// unlike `DefaultHasher`.
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(string: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    (0..string.len())
+        .step_by(2)
+        .map(|index| {
+            string
+                .get(index..index + 2)
+                .ok_or_else(|| "Odd hex-encoded string in the journal".into())
+                .and_then(|byte| u8::from_str_radix(byte, 16).map_err(Into::into))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(unix)]
+fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+
+    ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(unix)]
+fn exit_status_from_signal(signal: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+
+    ExitStatus::from_raw(signal)
+}
+
+#[cfg(windows)]
+fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code as u32)
+}
+
+#[cfg(windows)]
+fn exit_status_from_signal(_signal: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+
+    // There is no signal on Windows, let's use an abnormal exit code.
+    ExitStatus::from_raw(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(b"Hello\n"), "48656c6c6f0a");
+        assert_eq!(hex_decode("48656c6c6f0a").unwrap(), b"Hello\n");
+        assert!(hex_decode("486").is_err());
+    }
+}
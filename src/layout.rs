@@ -0,0 +1,191 @@
+//! Check that the layout of Rust types matches the layout of C types,
+//! with `_Static_assert`s on `sizeof`, `_Alignof` and `offsetof`.
+
+use crate::{
+    run::{run, Language},
+    Assert,
+};
+use std::{error::Error, fmt::Write as _};
+
+/// The layout of a Rust type, to check against a C type with
+/// [`check_layouts`]. It is usually built with
+/// [`layout!`](crate::layout).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    c_type: String,
+    size: usize,
+    align: usize,
+    fields: Vec<(String, usize)>,
+}
+
+impl Layout {
+    /// The layout of `T`, to check against the C type `c_type`, e.g.
+    /// `struct point`.
+    pub fn of<T>(c_type: &str) -> Self {
+        Self {
+            c_type: c_type.to_string(),
+            size: std::mem::size_of::<T>(),
+            align: std::mem::align_of::<T>(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a field of the C type, named `name`, at `offset`.
+    pub fn field(mut self, name: &str, offset: usize) -> Self {
+        self.fields.push((name.to_string(), offset));
+
+        self
+    }
+
+    fn static_asserts(&self) -> String {
+        let mut asserts = String::new();
+
+        let _ = writeln!(
+            asserts,
+            "_Static_assert(sizeof({c_type}) == {size}, \"the size of `{c_type}` is not {size} bytes, as in Rust\");",
+            c_type = self.c_type,
+            size = self.size,
+        );
+        let _ = writeln!(
+            asserts,
+            "_Static_assert(_Alignof({c_type}) == {align}, \"the alignment of `{c_type}` is not {align} bytes, as in Rust\");",
+            c_type = self.c_type,
+            align = self.align,
+        );
+
+        for (field, offset) in &self.fields {
+            let _ = writeln!(
+                asserts,
+                "_Static_assert(offsetof({c_type}, {field}) == {offset}, \"the offset of `{c_type}.{field}` is not {offset} bytes, as in Rust\");",
+                c_type = self.c_type,
+                field = field,
+                offset = offset,
+            );
+        }
+
+        asserts
+    }
+}
+
+/// Generate a C program checking `layouts` at compile time, after
+/// `prelude` (e.g. `#include "foo.h"`), and compile it. The returned
+/// [`Assert`] succeeds if all the layouts match; otherwise, the
+/// compilation fails, and the diagnostics of the compiler list the
+/// mismatches, see [`Assert::compile_failure`].
+///
+/// ```rust
+/// use inline_c::{check_layouts, layout};
+///
+/// #[repr(C)]
+/// struct Point {
+///     x: u8,
+///     y: u32,
+/// }
+///
+/// fn test_layouts() {
+///     check_layouts(
+///         r#"
+///             #include <stdint.h>
+///
+///             struct point {
+///                 uint8_t x;
+///                 uint32_t y;
+///             };
+///         "#,
+///         &[layout!(Point as "struct point" { x, y })],
+///     )
+///     .unwrap()
+///     .success();
+/// }
+///
+/// # fn main() { test_layouts() }
+/// ```
+pub fn check_layouts(prelude: &str, layouts: &[Layout]) -> Result<Assert, Box<dyn Error>> {
+    let mut program = String::from("#include <stddef.h>\n");
+    program.push_str(prelude);
+    program.push('\n');
+
+    for layout in layouts {
+        program.push_str(&layout.static_asserts());
+    }
+
+    program.push_str("int main(void) { return 0; }\n");
+
+    run(Language::C, &program)
+}
+
+/// Describe the layout of a Rust type and of its fields, to check
+/// against a C type with [`check_layouts`]. The fields must have the
+/// same names in Rust and in C; otherwise, use [`Layout::field`].
+///
+/// ```rust
+/// use inline_c::layout;
+///
+/// #[repr(C)]
+/// struct Point {
+///     x: u8,
+///     y: u32,
+/// }
+///
+/// let point = layout!(Point as "struct point" { x, y });
+/// ```
+#[macro_export]
+macro_rules! layout {
+    ($rust_type:ty as $c_type:literal { $($field:ident),* $(,)? }) => {
+        $crate::Layout::of::<$rust_type>($c_type)
+            $(.field(stringify!($field), ::core::mem::offset_of!($rust_type, $field)))*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Point {
+        x: u8,
+        y: u32,
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(
+            layout!(Point as "struct point" { x, y }),
+            Layout {
+                c_type: String::from("struct point"),
+                size: 8,
+                align: 4,
+                fields: vec![(String::from("x"), 0), (String::from("y"), 4)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_layouts() {
+        let prelude = |y_type: &str| {
+            format!(
+                r#"
+                    #include <stdint.h>
+
+                    struct point {{
+                        uint8_t x;
+                        {} y;
+                    }};
+                "#,
+                y_type
+            )
+        };
+        let layouts = [layout!(Point as "struct point" { x, y })];
+
+        check_layouts(&prelude("uint32_t"), &layouts)
+            .unwrap()
+            .success();
+
+        check_layouts(&prelude("uint16_t"), &layouts)
+            .unwrap()
+            .compile_failure()
+            .stderr(predicates::str::contains(
+                "the offset of `struct point.y` is not 4 bytes",
+            ));
+    }
+}
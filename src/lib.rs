@@ -69,10 +69,49 @@
 //! # }
 //! ```
 //!
+//! Or with a CUDA program, with the `cuda` feature. The program is
+//! compiled with `nvcc` (or the `NVCC` environment variable), and run
+//! on the host. If `nvcc` or a GPU is missing, the enclosing function
+//! returns early, i.e. the test is skipped.
+//!
+//! ```rust,ignore
+//! use inline_c::assert_cuda;
+//!
+//! fn test_cuda() {
+//!     (assert_cuda! {
+//!         #include <stdio.h>
+//!
+//!         __global__ void add_one(int *x) {
+//!             *x += 1;
+//!         }
+//!
+//!         int main() {
+//!             int *x;
+//!             cudaMallocManaged(&x, sizeof(int));
+//!             *x = 41;
+//!
+//!             add_one<<<1, 1>>>(x);
+//!             cudaDeviceSynchronize();
+//!             printf("%d", *x);
+//!
+//!             return 0;
+//!         }
+//!     })
+//!     .success()
+//!     .stdout("42");
+//! }
+//! ```
+//!
 //! The [`assert_c`] and [`assert_cxx`] macros return a
 //! `Result<Assert, Box<dyn Error>>`. See [`Assert`] to learn more
 //! about the possible assertions.
 //!
+//! Crates building their own harness on top of `inline-c` can compile
+//! a program without the macros, with [`run_with`] and a
+//! [`RunConfig`].
+//! Tests can be gated on the capabilities of the platform with
+//! [`compiles`], which tells whether a program compiles.
+//!
 //! The following example tests the returned value:
 //!
 //! ```rust
@@ -138,6 +177,233 @@
 //! # }
 //! ```
 //!
+//! The value of a directive can refer to the environment variables of
+//! the process with `${NAME}`, so that paths are machine-independent:
+//!
+//! ```c
+//! #inline_c_rs DATA_DIR: "${CARGO_MANIFEST_DIR}/tests/data"
+//! ```
+//!
+//! A literal `${` is written `$${`.
+//!
+//! The special directives, whose names are lowercase, e.g. `verbose`
+//! or `stack_size`, configure `inline-c` itself: they are neither
+//! given to the compiler nor to the program. All the other names,
+//! e.g. `HOST` or `DEBUG`, are ordinary variables.
+//!
+//! The variables are given to both the compiler and the program. Some
+//! names have a meaning for one of them only, e.g. `INCLUDE` or `LIB`
+//! for MSVC: the `#inline_c_rs_compile` directive defines a variable
+//! for the compiler only, and the `#inline_c_rs_run` directive for the
+//! program only. They do not configure `inline-c`, unlike
+//! `#inline_c_rs`.
+//!
+//! The names reserved by the toolchains, i.e. `PATH`, `INCLUDE`,
+//! `LIB`, `LIBPATH`, `TMP`, `TEMP` and `TMPDIR`, are neither given to
+//! the compiler nor to the program, unless they are defined for it
+//! with `#inline_c_rs_compile` or `#inline_c_rs_run`.
+//!
+//! ```c
+//! #inline_c_rs_compile INCLUDE: "${CARGO_MANIFEST_DIR}/include"
+//! #inline_c_rs_run INCLUDE: "program value"
+//! ```
+//!
+//! The flags variables, i.e. `CFLAGS`, `CPPFLAGS`, `CXXFLAGS` and
+//! `LDFLAGS`, are not given to the compiler either, since they are
+//! already its arguments: a compiler wrapper reading them would apply
+//! them twice. The special `compiler_env` directive changes which
+//! variables the compiler inherits, besides the `#inline_c_rs_compile`
+//! ones: `all`, `none`, or a comma-separated list of names.
+//!
+//! ### Standard error expectations
+//!
+//! When the special `strict_stderr` directive is set to `1`, any output
+//! on the standard error of the program fails the assertions, unless it
+//! is expected with the special `expect_stderr` directive. This
+//! directive can also be used alone, to expect some text on the
+//! standard error:
+//!
+//! ```c
+//! #inline_c_rs strict_stderr: "1"
+//! #inline_c_rs expect_stderr: "deprecated function"
+//! ```
+//!
+//! It helps to catch warnings or logs that silently appear during FFI
+//! calls.
+//!
+//! ### Preloading a library
+//!
+//! The special `preload` directive preloads a shared library into
+//! the program, e.g. to mock the time with `libfaketime`, or to
+//! interpose the allocator. It sets `LD_PRELOAD`, or
+//! `DYLD_INSERT_LIBRARIES` on macOS. It is not supported on Windows.
+//!
+//! ```c
+//! #inline_c_rs preload: "./libfaketime.so"
+//! ```
+//!
+//! A relative path is resolved against the current directory.
+//!
+//! ### Time and locale
+//!
+//! The special `timezone` and `locale` directives respectively set
+//! `TZ` and `LC_ALL` for the program. The special `faketime` directive
+//! runs the program with a fixed clock, thanks to
+//! [`libfaketime`](https://github.com/wolfcw/libfaketime), which must
+//! be installed (its path can be set with the special
+//! `faketime_library` directive):
+//!
+//! ```c
+//! #inline_c_rs timezone: "UTC"
+//! #inline_c_rs locale: "C"
+//! #inline_c_rs faketime: "2020-12-24 20:00:00"
+//! ```
+//!
+//! ### Working directory
+//!
+//! The program runs in the current directory of the tests, which
+//! depends on how they are run. The special `cwd` directive sets the
+//! working directory of the program; a relative path is resolved
+//! against the manifest directory of the crate, e.g. to open the
+//! fixtures of the tests:
+//!
+//! ```c
+//! #inline_c_rs cwd: "tests/fixtures"
+//! ```
+//!
+//! ### File permissions
+//!
+//! On Unix, the special `umask` directive sets the file mode creation
+//! mask of the program, in octal, so that the permissions of the files
+//! it creates do not depend on the environment of the tests. They are
+//! asserted with [`Assert::created_file`] and the
+//! [`has_mode`](predicates::has_mode) predicate.
+//!
+//! ```c
+//! #inline_c_rs umask: "077"
+//! ```
+//!
+//! ### Standard input from a file
+//!
+//! The standard input of the program is given by [`Assert::stdin`].
+//! The special `stdin_file` directive streams a file instead, e.g. a
+//! binary fixture for a parser or a decoder; a relative path is
+//! resolved against the manifest directory of the crate.
+//!
+//! ```c
+//! #inline_c_rs stdin_file: "tests/data/input.bin"
+//! ```
+//!
+//! ### Invalid directives
+//!
+//! A line starting with `#inline_c_rs` which is not a valid directive,
+//! e.g. with a missing quote, fails the run with a [`DirectiveError`]
+//! naming its line, instead of being left in the program.
+//!
+//! A name close to the one of a special directive, e.g. `timzone`, is
+//! likely a typo: it is still given as a variable, with a warning on
+//! the standard error. The special `allowed_directives` directive is a
+//! comma-separated list of such names which are intended:
+//!
+//! ```c
+//! #inline_c_rs allowed_directives: "tracee"
+//! #inline_c_rs tracee: "1"
+//! ```
+//!
+//! ### Network isolation
+//!
+//! On Linux, the special `network` directive set to `none` runs the
+//! program in a new network namespace, with `unshare`. The program
+//! has no network access, and can bind fixed ports without
+//! interfering with other tests. It requires unprivileged user
+//! namespaces; note that the program runs as the root user of its
+//! namespace.
+//!
+//! ```c
+//! #inline_c_rs network: "none"
+//! ```
+//!
+//! ### Stack size
+//!
+//! The special `stack_size` directive sets the stack size of the
+//! program, in bytes (with an optional `K`, `M` or `G` suffix), so
+//! that stack-usage bugs can be reproduced deterministically. On Unix,
+//! it sets the `RLIMIT_STACK` resource limit; on Windows, it sets the
+//! stack size at link time.
+//!
+//! ```c
+//! #inline_c_rs stack_size: "64K"
+//! ```
+//!
+//! ### Pseudo-terminal
+//!
+//! Some programs behave differently when their standard output is a
+//! terminal, e.g. with line buffering or colors. When the special
+//! `pty` directive is set to `1`, the standard output of the program
+//! is a pseudo-terminal, and its output is still captured (without
+//! translating `\n` into `\r\n`). It is only supported on Unix.
+//!
+//! ```c
+//! #inline_c_rs pty: "1"
+//! ```
+//!
+//! ### Merged standard output and standard error
+//!
+//! When the special `merge_stderr` directive is set to `1`, the
+//! standard error of the program is redirected into its standard
+//! output, in the same pipe, so that their relative order is kept (e.g.
+//! logs and results). The merged output is asserted with `stdout`, and
+//! the standard error is empty. It is only supported on Unix.
+//!
+//! ```c
+//! #inline_c_rs merge_stderr: "1"
+//! ```
+//!
+//! ### Scheduling
+//!
+//! To get less noisy timings, e.g. for micro-benchmarks, the `nice`
+//! directive defines the niceness of the program (Unix only), the
+//! `scheduler` directive its scheduling policy (`other`, `batch`,
+//! `idle`, `fifo[:priority]` or `rr[:priority]`, Linux only), and the
+//! `cpu_affinity` directive the CPUs it can run on (Linux only). The
+//! program is run through `nice`, `chrt` and `taskset`.
+//!
+//! ```c
+//! #inline_c_rs nice: "-5"
+//! #inline_c_rs scheduler: "fifo:10"
+//! #inline_c_rs cpu_affinity: "0,2-3"
+//! ```
+//!
+//! ### Minimum compiler version
+//!
+//! A program using recent features (e.g. C23 attributes) can declare
+//! the minimum version of the compilers it supports with the
+//! `min_compiler` directive, a comma-separated list of `<kind>
+//! <version>`, where `<kind>` is `gcc`, `clang`, `apple-clang` or
+//! `msvc`. If the compiler is not listed, or is too old, the program
+//! fails with a clear error instead of a cryptic compilation error.
+//!
+//! ```c
+//! #inline_c_rs min_compiler: "gcc 12, clang 15"
+//! ```
+//!
+//! To skip a test instead, [`toolchain_info`] exposes the kind and
+//! the version of the compilers, and the target.
+//!
+//! ### Language standard
+//!
+//! The special `standard` directive selects the language standard: `c89`,
+//! `c99`, `c11`, `c17` or `c23` for C, `c++11`, `c++14`, `c++17`,
+//! `c++20` or `c++23` for C++, and their `gnu` variants (e.g. `gnu11`
+//! or `gnu++17`) to enable the GNU extensions. The compiler is probed
+//! first, so that a standard unknown to an old toolchain fails with a
+//! clear error. With MSVC, `c23` and `c++23` map to `/std:clatest` and
+//! `/std:c++latest`, and the GNU extensions are not supported.
+//!
+//! ```c
+//! #inline_c_rs standard: "c23"
+//! ```
+//!
 //! ### Meta environment variables
 //!
 //! Using the `#inline_c_rs` C directive can be repetitive if one
@@ -188,13 +454,57 @@
 //! # }
 //! ```
 //!
+//! Meta environment variables can be scoped to a module, so that
+//! different test files do not interfere with each other. They have
+//! the following syntax, where `<MODULE>` is the uppercased module
+//! path with `::` replaced by `__`:
+//!
+//! ```sh
+//! INLINE_C_RS_<MODULE>__<variable_name>=<variable_value>
+//! ```
+//!
+//! For example, `INLINE_C_RS_FOO__BAR__INPUT_PATH` defines `INPUT_PATH`
+//! for the programs in the `foo::bar` module (and its sub-modules)
+//! only. The variables of the innermost module win over the ones of
+//! its parents, which win over the global ones. A scoped variable is
+//! never given to the programs under its scoped name, e.g.
+//! `FOO__BAR__INPUT_PATH`.
+//!
+//! The variables finally given to a program, once the meta environment
+//! variables and the directives are merged, are returned by
+//! [`Assert::program_env`], and asserted with
+//! [`Assert::has_env_var`].
+//!
 //! ### `CFLAGS`, `CPPFLAGS`, `CXXFLAGS` and `LDFLAGS`
 //!
 //! Some classical `Makefile` variables like `CFLAGS`, `CPPFLAGS`,
 //! `CXXFLAGS` and `LDFLAGS` are understood by `inline-c` and
 //! consequently have a special treatment. Their values are added to
 //! the appropriate compilers when the C code is compiled and linked
-//! into an object file.
+//! into an object file: `CPPFLAGS` (the preprocessor flags, e.g.
+//! `-I` or `-D`) for all the programs, `CFLAGS` for the C programs
+//! only, and `CXXFLAGS` for the C++ programs only.
+//!
+//! When a variable is not defined, the environment of the process is
+//! read like `cc-rs` does, e.g. `CFLAGS`, then `HOST_CFLAGS` (or
+//! `TARGET_CFLAGS`), then `CFLAGS_<target>`. Each flag is given once
+//! to the compiler, whatever the toolchain.
+//!
+//! In MSYS2 and Cygwin, the POSIX paths of these variables, e.g.
+//! `-I/c/foo` or `/mingw64/lib/libfoo.a`, are translated to Windows
+//! paths with `cygpath`, since the MinGW compilers do not understand
+//! them. The special `translate_paths` directive enables (`1`) or
+//! disables (`0`) the translation on other environments. The flags of
+//! MSVC, e.g. `/O2`, are never translated, even in MSYS2.
+//!
+//! Macros are better defined with the `define` directive than in
+//! `CFLAGS`, which is split on whitespace: the argument of the
+//! compiler is built for its toolchain (`-D` or `/D`), whatever the
+//! value is. With [`run_with`], see [`RunConfig::define`].
+//!
+//! ```c
+//! #inline_c_rs define GREETING_SIZE: "sizeof(char) * 64"
+//! ```
 //!
 //! Pro tip: Let's say we have a Rust crate named `foo`, and it
 //! exports a C API. It is possible to define `CFLAGS` and `LDFLAGS`
@@ -245,6 +555,583 @@
 //! shared objects) and then `cargo test --release` to see it in
 //! action.
 //!
+//! ### Custom compilers
+//!
+//! The special `compiler` directive replaces the compiler, with its own
+//! flags, split like a shell does, e.g. `'/opt/my tools/cc' -O2`, and
+//! the special `source_extension` directive replaces the
+//! extension of the source file, which selects its language for most
+//! compilers. For example, a SYCL program can be compiled with `icpx`
+//! or `dpcpp`, or an Objective-C program with `clang`:
+//!
+//! ```c
+//! #inline_c_rs compiler: "icpx -fsycl"
+//! ```
+//!
+//! ```c
+//! #inline_c_rs compiler: "clang -framework Foundation"
+//! #inline_c_rs source_extension: "m"
+//! ```
+//!
+//! ### Compilation timeout and retries
+//!
+//! The standard input of the compiler is closed, and the
+//! `compile_timeout` directive defines the time the compiler has to
+//! finish, in seconds. Past it, the compiler is killed, and a clear
+//! error is returned instead of a hanging test.
+//!
+//! ```c
+//! #inline_c_rs compile_timeout: "60"
+//! ```
+//!
+//! On busy machines, the compiler can fail for reasons unrelated to
+//! the program, e.g. with the PDB errors of MSVC. The
+//! `compile_retries` directive defines how many times the compilation
+//! is retried, with an exponential backoff, when the compiler output
+//! matches such a transient failure. Real compilation errors are never
+//! retried.
+//!
+//! ### Profile
+//!
+//! By default, the program is compiled with the default flags of
+//! `cc-rs`. The special `profile` directive selects the `debug` (without
+//! optimizations, with debug information) or the `release` (with
+//! optimizations) profile:
+//!
+//! ```c
+//! #inline_c_rs profile: "release"
+//! ```
+//!
+//! With `cargo`, the program is compiled consistently with the Rust
+//! crate of the test (with the `assert_c!` and `assert_cxx!` macros):
+//! with the `debug` profile if `debug_assertions` are enabled (e.g.
+//! with `cargo test`), with the `release` profile otherwise (e.g. with
+//! `cargo test --release`). When run from a build script, the `PROFILE`
+//! and `DEBUG` variables of Cargo are honored instead. It is best set
+//! once for all the tests, with `INLINE_C_RS_profile=cargo`.
+//!
+//! ### Artifacts
+//!
+//! The files produced to run a program (the sources, the intermediate
+//! objects, the executable and the provenance) are listed by
+//! [`Assert::artifacts`]. They are removed with the `Assert`, unless
+//! they are copied somewhere stable with [`ArtifactSet::persist_to`],
+//! e.g. to sign or to package them.
+//!
+//! The executable, or the shared object, is named after the test
+//! and the hash of the program, e.g. `test_foo-84f7e2a1-Xq3b9z`, so
+//! that a hanging program can be found with `ps` or a debugger,
+//! unless the special `output_name` directive gives its file name, with its
+//! extension, e.g. `libplugin.so` to load it with `libloading`. It
+//! is then created in a build directory of its own.
+//! [`Assert::persist_binary_to`] copies it to a stable path, e.g. for
+//! the later tests or for external tools.
+//!
+//! The provenance records, in JSON, the toolchain which has compiled
+//! the program: the compiler, its kind and its version, its flags,
+//! and the host and target triples. It is also returned by
+//! [`Assert::provenance`], e.g. for the bug reports or the cache keys.
+//!
+//! ### Footprint
+//!
+//! Large test suites can fill the temporary directory. The disk space
+//! used by all the programs of the process, and the one still used,
+//! e.g. by persisted artifacts, is summarized by [`footprint`], or
+//! printed by [`report`], e.g. from the last test.
+//!
+//! ### Temporary files
+//!
+//! The program is compiled in the temporary directory. On Windows, if
+//! its path contains non-ASCII characters (e.g. a localized user name)
+//! or is too long for MSVC, the `inline-c-rs` directory at the root of
+//! the system drive is used instead.
+//!
+//! ### Verbose mode
+//!
+//! When bringing up a new platform, it helps to see what is run. When
+//! the special `verbose` directive is set to `1`, e.g. with
+//! `INLINE_C_RS_verbose=1`, the path to the generated source, the
+//! compiler command and the program command are printed on the
+//! standard error, whether they fail or not.
+//!
+//! ### Live output
+//!
+//! The output of the program is captured for the assertions, so a long
+//! program shows no progress. When the special `tee` directive is set
+//! to `1`, e.g. with `INLINE_C_RS_tee=1`, its standard output and its
+//! standard error are also streamed, line by line, to the standard
+//! error of the test while they are captured. The test harness shows
+//! them live with `cargo test -- --nocapture`. It has no effect with a
+//! pseudo-terminal, the fork server, or in-process.
+//!
+//! ```c
+//! #inline_c_rs tee: "1"
+//! ```
+//!
+//! ### Redaction of the failure output
+//!
+//! When an assertion fails, the output tells the command running the
+//! program, with its environment variables, and its standard input. So
+//! that the logs of the CI do not leak secrets, the values of the
+//! variables whose names look like secrets (`*TOKEN*`, `*SECRET*`,
+//! `*PASSWORD*`, `*API_KEY*`, `*AUTH*` etc.) are replaced by
+//! `<redacted>`. The special `redact` directive adds a comma-separated
+//! list of case-insensitive patterns, where `*` matches anything, and
+//! the `quiet` directive set to `1` leaves the command and the standard
+//! input out.
+//!
+//! ```c
+//! #inline_c_rs redact: "AWS_*,DATABASE_URL"
+//! ```
+//!
+//! A secret, e.g. a token, should not be written in the program
+//! either. A value written `env("NAME")`, without quotes, is read from
+//! the environment variable `NAME` when the program runs, so it is
+//! neither in the source nor in the artifacts, and it is always
+//! redacted:
+//!
+//! ```c
+//! #inline_c_rs_run TOKEN: env("CI_TOKEN")
+//! ```
+//!
+//! Only the command is redacted: the standard output and the standard
+//! error of the program are shown as they are, since the assertions
+//! are made on them. A program must not print its secrets.
+//!
+//! ### Colors of the diagnostics
+//!
+//! The output of the compiler is captured, so whether it has colors
+//! depends on the compiler. The special `diagnostics_color` directive forces
+//! them (`always`), drops them (`never`), or lets the compiler decide
+//! (`auto`, the default), e.g. to keep the failure logs of the CI
+//! readable. It has no effect with MSVC.
+//!
+//! ```c
+//! #inline_c_rs diagnostics_color: "always"
+//! ```
+//!
+//! ### Cross-compilation and test matrix
+//!
+//! The special `target` directive defines the target triple the program is
+//! compiled for, e.g. `powerpc-unknown-linux-gnu`, and the special
+//! `runner` directive the program running it, e.g. an emulator:
+//!
+//! ```c
+//! #inline_c_rs target: "powerpc-unknown-linux-gnu"
+//! #inline_c_rs runner: "qemu-ppc -L /usr/powerpc-linux-gnu"
+//! ```
+//!
+//! The special `sysroot` directive defines the sysroot of the compiler
+//! (`--sysroot`), and the special `host` directive overrides the host
+//! triple, which is the one `inline-c` has been compiled for by
+//! default, e.g. in containers. As all the variables, they can be set
+//! for the whole test suite with the `INLINE_C_RS_target`,
+//! `INLINE_C_RS_sysroot` and `INLINE_C_RS_host` environment variables,
+//! or with
+//! [`RunConfig::target`] and [`RunConfig::sysroot`].
+//!
+//! For hermetic toolchains (e.g. Nix or Bazel), the
+//! `toolchain_prefixes` directive lists the directories of the programs
+//! of the compiler (`-B`), e.g. the assembler and the linker, separated
+//! like in `PATH`, and the special `gcc_toolchain` directive the GCC
+//! installation used by Clang (`--gcc-toolchain`), see
+//! [`RunConfig::toolchain_prefix`] and [`RunConfig::gcc_toolchain`].
+//! These variables are not supported with MSVC.
+//!
+//! To check that a C API is portable, e.g. across endiannesses and
+//! pointer sizes, a [`Matrix`] runs the same program across several
+//! [`Configuration`]s, and reports all the failing ones together. It
+//! also runs all the combinations of several dimensions, e.g. of
+//! standards, optimization levels, sanitizers or compilers, see
+//! [`Matrix::combine`].
+//!
+//! ### Local headers
+//!
+//! With the `assert_c!` and `assert_cxx!` macros, the directory of
+//! the Rust source file is added to the include path, so that
+//! `#include "local.h"` resolves next to the test file.
+//!
+//! When the special `auto_include` directive is set to `1`, the `include/`
+//! directory of the crate (next to its `Cargo.toml`) and the `OUT_DIR`
+//! directory, where build scripts usually generate headers, are also
+//! added to the include path, if they exist.
+//!
+//! When the special `use_out_dir` directive is set to `1`, the `OUT_DIR`
+//! directory only is added to the include path. Unlike with
+//! `auto_include`, it is an error if the crate has no build script,
+//! instead of a missing header:
+//!
+//! ```c
+//! #inline_c_rs use_out_dir: "1"
+//! #include "bindings.h"
+//! ```
+//!
+//! The warnings are turned into errors, including the ones of
+//! third-party or generated headers. The directories of the
+//! `system_include_dirs` directive, separated like in `PATH`, are added
+//! to the include path as system directories (with `-isystem`, or
+//! `/external:I` with MSVC), whose warnings are silenced. With
+//! [`run_with`], see [`RunConfig::include_system_dir`].
+//!
+//! When the special `record_headers` directive is set to `1`, the
+//! headers the program includes, directly or not, are recorded (with
+//! `-M`, or `/showIncludes` with MSVC), see
+//! [`Assert::included_headers`]. It checks, for example, that a public
+//! header does not drag in private headers:
+//!
+//! ```rust
+//! use inline_c::assert_c;
+//!
+//! fn test_public_header() {
+//!     (assert_c! {
+//!         #inline_c_rs record_headers: "1"
+//!
+//!         #include <stdio.h>
+//!
+//!         int main() {
+//!             return 0;
+//!         }
+//!     })
+//!     .includes_header("stdio.h")
+//!     .does_not_include_header("private.h");
+//! }
+//!
+//! # fn main() { test_public_header() }
+//! ```
+//!
+//! ### Rust constants
+//!
+//! Rust constants can be exported into the program as `#define`s with
+//! a leading `export_consts!(…);` statement, see
+//! [`export_consts!`](crate::export_consts).
+//!
+//! ### Layout of Rust types
+//!
+//! The layout of `#[repr(C)]` Rust types can be checked against the
+//! C types of a header, in one place, with [`check_layouts`] and
+//! [`layout!`](crate::layout): the size, the alignment and the offsets
+//! of the fields are checked by the C compiler with
+//! `_Static_assert`s.
+//!
+//! ### Callbacks
+//!
+//! The program can call `extern "C"` Rust functions of the test, with
+//! a leading `callbacks!(…);` statement, see
+//! [`callbacks!`](crate::callbacks). The program is then compiled as a
+//! shared object, and run inside the test process (Unix only).
+//!
+//! ### In-process execution
+//!
+//! The `assert_c_inproc!` and `assert_cxx_inproc!` macros compile the
+//! program as a shared object, load it inside the test process, and
+//! call its entry point in a forked child whose standard output and
+//! standard error are captured. It is faster than spawning a program
+//! for thousands of micro-tests, while a crash is still isolated. The
+//! entry point is `main`, or the function named by the special
+//! `entry_point` directive, with the same signature. It is only supported on Unix.
+//!
+//! Without `entry_point`, the `main` function of a shared object is
+//! renamed, so that the library does not export it: the same program
+//! builds both as an executable and as a library. The `INLINE_C_EXPORT`
+//! macro, always defined, exports a function of the library whatever
+//! the default visibility is, e.g. with `-fvisibility=hidden`, or with
+//! MSVC (`__declspec(dllexport)`).
+//!
+//! ```rust
+//! # #[cfg(unix)]
+//! # fn main() {
+//! use inline_c::assert_c_inproc;
+//!
+//! (assert_c_inproc! {
+//!     #inline_c_rs entry_point: "entry"
+//!     #include <stdio.h>
+//!
+//!     int entry(int argc, char** argv) {
+//!         printf("Hello, %s!", argv[argc - 1]);
+//!
+//!         return 0;
+//!     }
+//! })
+//! .arg("World")
+//! .success()
+//! .stdout("Hello, World!");
+//! # }
+//! #
+//! # #[cfg(not(unix))]
+//! # fn main() {}
+//! ```
+//!
+//! The symbols exported by the shared object are listed by
+//! [`Assert::exported_symbols`], and [`Assert::exports_exactly`]
+//! asserts that no symbol leaks, e.g. to check a version script or
+//! the visibility attributes of a C API.
+//!
+//! [`Assert::loads_cleanly`] loads the shared object with all its
+//! symbols resolved, i.e. `dlopen` with `RTLD_NOW` (or `LoadLibrary`
+//! on Windows), and reports the missing symbols or libraries, a
+//! common regression once a library is installed.
+//!
+//! The special `version_script` directive holds the path of the list of the
+//! symbols the shared object exports: a GNU version script (`.map`),
+//! or a module-definition file (`.def`). It is translated for the
+//! linker, e.g. into a list of exported symbols for Apple ld. With
+//! [`run_with`], see [`RunConfig::version_script`].
+//!
+//! ### Fork server
+//!
+//! When the special `fork_server` directive is set to `1`, the program is
+//! started once as an [AFL](https://lcamtuf.coredump.cx/afl/)-style
+//! fork server, and each run of the program is a `fork` of it instead
+//! of a full `exec`. It reduces the overhead of repeated runs, e.g.
+//! with property testing. The `main` function of the program must
+//! return explicitly. It is only supported on Unix.
+//!
+//! ### Deterministic builds
+//!
+//! When the special `deterministic` directive is set to `1`, the program is
+//! compiled so that the produced binary is bit-reproducible:
+//! `SOURCE_DATE_EPOCH` is set to `0` (unless defined), the files
+//! have fixed names inside a temporary build directory, and this
+//! directory is removed from the embedded paths with
+//! `-ffile-prefix-map` (or `/Brepro` with MSVC).
+//!
+//! ```rust
+//! use inline_c::assert_c;
+//!
+//! fn test_deterministic() {
+//!     (assert_c! {
+//!         #inline_c_rs deterministic: "1"
+//!
+//!         #include <stdio.h>
+//!
+//!         int main() {
+//!             printf("Hello, World!");
+//!
+//!             return 0;
+//!         }
+//!     })
+//!     .success()
+//!     .stdout("Hello, World!");
+//! }
+//!
+//! # fn main() { test_deterministic() }
+//! ```
+//!
+//! ### Backtraces
+//!
+//! When the special `backtrace` directive is set to `1`, the program is
+//! compiled with debug information, and if it crashes, it is run
+//! again under `gdb` or `lldb` (whichever is available) to collect a
+//! symbolized backtrace. The backtrace is attached to the assertion
+//! failure message.
+//!
+//! ### Debugging
+//!
+//! When the special `debugger` directive is set to `gdb`, `lldb` or
+//! `auto` (the first available one), the program is compiled with
+//! debug information, and instead of running the assertions, it is
+//! run under the debugger, interactively. The assertions are skipped
+//! and the test fails. It is best to run a single test, e.g.:
+//!
+//! ```sh
+//! $ INLINE_C_RS_debugger=gdb cargo test test_foo -- --nocapture
+//! ```
+//!
+//! ### Heap profiling
+//!
+//! When the special `heap_profiler` directive is set to `massif`
+//! (Valgrind) or `heaptrack`, the program runs under the heap profiler.
+//! The report is stored next to the program (see
+//! [`Assert::heap_profile_path`]), and the peak heap consumption can be
+//! asserted:
+//!
+//! ```rust,ignore
+//! use inline_c::assert_c;
+//!
+//! fn test_peak_heap() {
+//!     (assert_c! {
+//!         #inline_c_rs heap_profiler: "massif"
+//!         #include <stdlib.h>
+//!
+//!         int main() {
+//!             free(malloc(1024));
+//!
+//!             return 0;
+//!         }
+//!     })
+//!     .peak_heap_below(1_000_000)
+//!     .success();
+//! }
+//! ```
+//!
+//! ### Sanitizers
+//!
+//! The special `sanitizer` directive enables sanitizers, as a
+//! comma-separated list of `address`, `undefined`, `thread`, `leak` and
+//! `memory` (only `address` is supported with MSVC). With `thread`,
+//! [`Assert::no_data_races`] asserts that ThreadSanitizer has reported
+//! no data race. [`Assert::no_leaks_with_suppressions`] asserts that
+//! the program does not leak memory, except for the leaks matched by a
+//! suppressions file, with LeakSanitizer (`address` or `leak`), or
+//! else with Valgrind.
+//!
+//! The runtimes of the sanitizers write their reports in a temporary
+//! directory (`log_path`), which are then appended to the standard
+//! error of the program, whether it is run by an assertion,
+//! [`Assert::spawn`], [`Assert::run_concurrently`] or
+//! [`Assert::evaluate`]. Their default behavior is kept, e.g.
+//! UndefinedBehaviorSanitizer does not halt the program; the options
+//! in `ASAN_OPTIONS`, `UBSAN_OPTIONS` etc. are kept too. The reports
+//! are parsed by [`Assert::sanitizer_findings`].
+//!
+//! Programs including `pthread.h`, `threads.h` or `thread` are
+//! automatically compiled with `-pthread`.
+//!
+//! ### Automatic linking
+//!
+//! When the special `auto_link` directive is set to `1`, the program is
+//! linked against the well-known libraries matching its includes,
+//! e.g. `-lm` for `math.h`, or `-ldl` for `dlfcn.h`. Libraries
+//! already present in the flags are not repeated. With MSVC, the
+//! programs including `stdarg.h` are linked against
+//! `legacy_stdio_definitions.lib`, so that the C libraries built
+//! before Visual Studio 2015 find the `printf` family they expect.
+//!
+//! ### Static linking
+//!
+//! When the special `static` directive is set to `1`, the program is linked
+//! statically (`-static`; with MSVC, only the C runtime is, with
+//! `/MT`). [`Assert::is_statically_linked`] asserts that it has no
+//! dynamic dependencies. The [`Configuration::musl_static`]
+//! configuration links fully static programs against musl, if
+//! [`musl_available`]:
+//!
+//! ```rust
+//! use inline_c::{musl_available, Configuration, Language, Matrix};
+//!
+//! fn test_static() {
+//!     if !musl_available() {
+//!         return;
+//!     }
+//!
+//!     Matrix::new()
+//!         .configuration(Configuration::musl_static())
+//!         .run(Language::C, "int main(void) { return 0; }", |assert| {
+//!             assert.is_statically_linked().success();
+//!         });
+//! }
+//!
+//! # fn main() { test_static() }
+//! ```
+//!
+//! ### Linking a Rust `staticlib`
+//!
+//! To test a crate built with `crate-type = ["staticlib"]` from C, the
+//! `staticlib` directive can be set to the name of the crate. Its
+//! static library is looked up in the target directory (e.g.
+//! `target/debug/lib<crate>.a`), and linked as a whole archive with
+//! the system libraries required by Rust on the target (e.g.
+//! `-lpthread -ldl -lm`, or `ws2_32.lib` on Windows). The variable
+//! can also be set to the path of a static library.
+//!
+//! ```c
+//! #inline_c_rs staticlib: "my-crate"
+//! ```
+//!
+//! ### OpenMP
+//!
+//! When the special `openmp` directive is set to `1`, the program is
+//! compiled with OpenMP support (`-fopenmp`, or `/openmp` with MSVC),
+//! and linked against the OpenMP runtime.
+//!
+//! ### Tracing
+//!
+//! When the special `tracer` directive is set to `strace` or `ltrace`, the
+//! program runs under the tracer, which follows forks. The trace is
+//! available with [`Assert::trace_output`], so that one can assert
+//! that some system calls do or don't occur.
+//!
+//! ### Report
+//!
+//! When the special `report` directive is set to a path, every program
+//! compiled and run in the process is recorded in a machine-readable
+//! report: the test name, the source hash, the compile and run times,
+//! and the result. The report is written in JUnit XML if the path ends
+//! with `.xml`, in JSON otherwise, so that CI dashboards can track the
+//! health of the C examples:
+//!
+//! ```sh
+//! $ INLINE_C_RS_report=$(pwd)/target/inline-c.xml cargo test
+//! ```
+//!
+//! ### Record and replay
+//!
+//! On machines without a C compiler (e.g. docs.rs, or minimal CI legs),
+//! it is possible to replay the outputs recorded on a machine with a C
+//! compiler. The special `journal` directive defines the path to the
+//! journal file, and the special `journal_mode` directive defines
+//! whether the outputs must be recorded (`record`, the default) or
+//! replayed (`replay`):
+//!
+//! ```sh
+//! $ INLINE_C_RS_journal=$(pwd)/inline-c.journal cargo test
+//! $ INLINE_C_RS_journal=$(pwd)/inline-c.journal INLINE_C_RS_journal_mode=replay cargo test
+//! ```
+//!
+//! An execution is identified by the program, its arguments and its
+//! standard input.
+//!
+//! ### Performance baseline
+//!
+//! [`Assert::finishes_within`] asserts that a program runs within a
+//! given duration. To catch performance regressions without fixing
+//! a duration, [`Assert::not_slower_than_baseline`] compares the run
+//! time of a program against a baseline file, with a tolerance. The
+//! special `baseline` directive defines the path to the baseline file,
+//! which is meant to be committed. A benchmark missing from the
+//! baseline is added to it. The special `baseline_mode` directive
+//! defines whether the run times must be compared (`compare`, the
+//! default) or must update the baseline (`update`):
+//!
+//! ```rust
+//! use inline_c::assert_c;
+//!
+//! fn test_baseline() {
+//!     # let baseline = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+//!     # std::fs::remove_file(&baseline).unwrap();
+//!     # std::env::set_var("INLINE_C_RS_baseline", &baseline);
+//!     (assert_c! {
+//!         int main() {
+//!             return 0;
+//!         }
+//!     })
+//!     .not_slower_than_baseline("empty_main", 0.1)
+//!     .success();
+//! }
+//!
+//! # fn main() { test_baseline() }
+//! ```
+//!
+//! ```sh
+//! $ INLINE_C_RS_baseline=$(pwd)/inline-c-baseline.json cargo test
+//! $ INLINE_C_RS_baseline=$(pwd)/inline-c-baseline.json INLINE_C_RS_baseline_mode=update cargo test
+//! ```
+//!
+//! ### Observer
+//!
+//! To plug custom logging, metrics or report formats, an
+//! [`Observer`] can be registered with [`set_observer`]. It receives
+//! an [`Event`] when the source of a program is generated, when the
+//! compilation starts and ends, and when the program starts and ends.
+//!
+//! ### Custom directives
+//!
+//! Other crates can define their own directives, e.g. `#inline_c_rs
+//! artifactory_lib: "zlib"` to fetch a library, by registering a
+//! [`DirectiveHandler`] with [`register_directive`]. The handler
+//! receives the value of the directive, and a [`DirectiveContext`]
+//! giving the commands which compile and run the program.
+//!
 //! ## Using `inline-c` inside Rust documentation
 //!
 //! Since it is now possible to write C code inside Rust, it is
@@ -331,19 +1218,155 @@
 //! }
 //! ```
 //!
+//! On nightly, the C source is the source as written, byte for byte,
+//! instead of a reconstruction from the Rust tokens: the spacing, the
+//! line breaks and the literals are preserved.
+//!
 //! Note that multi-lines macros don't work! That's because the `\` symbol
 //! is consumed by the Rust lexer. The best workaround is to define the
 //! macro in another `.h` file, and to include it with the `#include`
 //! directive.
+//!
+//! ## Comments
+//!
+//! The Rust lexer drops the comments, except the doc comments: `///`
+//! and `//!` comments are kept in the C source as `//` comments, e.g.
+//! for the `// CHECK:` patterns of [`Assert::file_check`], or to read
+//! the [persisted artifacts](ArtifactSet::persist_to). On nightly,
+//! the source is kept as written, with all its comments.
+//!
+//! To keep all the comments on stable, the program can be given as a
+//! string literal, usually a raw string:
+//!
+//! ```rust
+//! use inline_c::assert_c;
+//!
+//! fn test_raw_string() {
+//!     (assert_c!(
+//!         r#"
+//!         #include <stdio.h>
+//!
+//!         int main() {
+//!             // CHECK: "quoted"
+//!             printf("\"quoted\"\n");
+//!
+//!             return 0;
+//!         }
+//!         "#
+//!     ))
+//!     .file_check()
+//!     .success();
+//! }
+//!
+//! # fn main() { test_raw_string() }
+//! ```
+//!
+//! ## Debugging the macros
+//!
+//! The macros reconstruct the C source from the Rust tokens, which can
+//! lose some spacing, or mangle some operators. To report such a bug
+//! with the exact reconstructed source, set the
+//! `INLINE_C_RS_DEBUG_MACROS` environment variable when the tests are
+//! compiled: with `1`, each macro emits its source as a compiler
+//! warning; with a path, each macro appends its source to this file.
+//! The macros are not expanded again when only this variable changes,
+//! e.g. `touch` the test file, or run `cargo clean`.
+//!
+//! ```sh
+//! $ INLINE_C_RS_DEBUG_MACROS=1 cargo test --no-run
+//! ```
+//!
+//! The reconstruction never forms a digraph (e.g. `<:`) or a trigraph
+//! (e.g. `??=`): the adjacent operators forming one are separated, and
+//! the `??` of the literals are escaped.
+//! [`reconstruct!`] returns the C source the other macros compile, so
+//! that a snippet can be tested to round-trip:
+//!
+//! ```rust
+//! use inline_c::reconstruct;
+//!
+//! # #[cfg(not(nightly))]
+//! assert_eq!(
+//!     reconstruct! { int *p = &s->x; },
+//!     "int * p = & s -> x ;\n",
+//! );
+//! ```
 
+mod artifacts;
 mod assert;
+mod baseline;
+mod consts;
+#[cfg(feature = "cuda")]
+mod cuda;
+mod debugger;
+mod dependencies;
+mod diagnostics;
+mod directive;
+mod evaluate;
+mod exception;
+mod filecheck;
+mod footprint;
+mod forkserver;
+mod guard;
+mod headers;
+mod heap;
+mod inproc;
+mod journal;
+mod layout;
+mod matrix;
+mod msys;
+mod observer;
+mod permissions;
+#[cfg(feature = "proptest")]
+mod property;
+mod provenance;
+mod pty;
+mod redact;
+mod report;
 mod run;
+mod sanitizer;
+mod scheduling;
+mod spawn;
+mod standard;
+mod staticlib;
+mod stderr;
+mod symbols;
+mod temp;
+mod toolchain;
+mod trace;
+mod version_script;
 
-pub use crate::run::{run, Language};
+pub use crate::run::{
+    compiles, run, run_at, run_in_process, run_with, run_with_callbacks, Language, Location,
+    OutputKind, RunConfig,
+};
+pub use artifacts::ArtifactSet;
 pub use assert::Assert;
-pub use inline_c_macro::{assert_c, assert_cxx};
+#[doc(hidden)]
+pub use consts::CLiteral;
+#[cfg(feature = "cuda")]
+#[doc(hidden)]
+pub use cuda::cuda_available;
+pub use directive::{register_directive, DirectiveContext, DirectiveError, DirectiveHandler};
+pub use evaluate::{Check, Finding, RunReport};
+pub use footprint::{footprint, report, Footprint};
+#[cfg(feature = "cuda")]
+pub use inline_c_macro::assert_cuda;
+pub use inline_c_macro::{assert_c, assert_c_inproc, assert_cxx, assert_cxx_inproc, reconstruct};
+#[doc(hidden)]
+pub use inproc::Callback;
+pub use layout::{check_layouts, Layout};
+pub use matrix::{matrix, musl_available, Configuration, Matrix};
+pub use observer::{set_observer, Event, Observer};
+#[cfg(feature = "proptest")]
+pub use property::ProgramInput;
+pub use provenance::Provenance;
+pub use sanitizer::SanitizerFinding;
+pub use spawn::Spawned;
+pub use toolchain::{toolchain_info, Compiler, CompilerKind, CompilerVersion, ToolchainInfo};
 pub mod predicates {
-    //! Re-export the prelude of the `predicates` crate, which is useful for assertions.
+    //! Re-export the prelude of the `predicates` crate, which is useful
+    //! for assertions.
     //!
     //! # Example
     //!
@@ -373,6 +1396,7 @@ pub mod predicates {
     //! # fn main() { test_predicates() }
     //! ```
 
+    pub use crate::permissions::{has_mode, ModePredicate};
     pub use predicates::prelude::*;
 }
 
@@ -397,6 +1421,81 @@ mod tests {
         .code(3);
     }
 
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_cxx_macro_with_extern_c() {
+        (assert_cxx! {
+            #include <cstdio>
+
+            extern "C" {
+                int add(int x, int y);
+            }
+
+            extern "C" int sub(int x, int y) { return x - y; }
+
+            int add(int x, int y) { return x + y; }
+
+            int main() {
+                printf("%d", sub(add(1, 2), 1));
+
+                return 0;
+            }
+        })
+        .success()
+        .stdout("2");
+    }
+
+    #[test]
+    fn test_c_macro_with_variadic_function() {
+        (assert_c! {
+            #inline_c_rs auto_link: "1"
+            #include <stdarg.h>
+            #include <stdio.h>
+
+            static int format(char *buffer, size_t size, const char *format, ...) {
+                va_list arguments;
+                va_list copy;
+
+                va_start(arguments, format);
+                va_copy(copy, arguments);
+
+                int length = vsnprintf(NULL, 0, format, copy);
+                vsnprintf(buffer, size, format, arguments);
+
+                va_end(copy);
+                va_end(arguments);
+
+                return length;
+            }
+
+            static int sum(int count, ...) {
+                va_list arguments;
+                int total = 0;
+
+                va_start(arguments, count);
+
+                for (int nth = 0; nth < count; ++nth) {
+                    total += va_arg(arguments, int);
+                }
+
+                va_end(arguments);
+
+                return total;
+            }
+
+            int main() {
+                char buffer[32];
+                int length = format(buffer, sizeof(buffer), "%s=%.*f", "sum", 1, (double) sum(3, 1, 2, 3));
+
+                printf("%s (%d)", buffer, length);
+
+                return 0;
+            }
+        })
+        .success()
+        .stdout("sum=6.0 (7)");
+    }
+
     #[test]
     fn test_c_macro_with_include() {
         (assert_c! {
@@ -450,6 +1549,28 @@ mod tests {
         remove_var("INLINE_C_RS_CFLAGS");
     }
 
+    #[test]
+    fn test_c_macro_with_env_value() {
+        set_var("INLINE_C_TEST_MACRO_ENV_VALUE", "World!");
+
+        (assert_c! {
+            #inline_c_rs_run HELLO: env("INLINE_C_TEST_MACRO_ENV_VALUE")
+
+            #include <stdio.h>
+            #include <stdlib.h>
+
+            int main() {
+                printf("Hello, %s", getenv("HELLO"));
+
+                return 0;
+            }
+        })
+        .success()
+        .stdout("Hello, World!");
+
+        remove_var("INLINE_C_TEST_MACRO_ENV_VALUE");
+    }
+
     #[test]
     fn test_c_macro_with_env_vars_from_env_vars() {
         // Define env vars through env vars.
@@ -489,6 +1610,64 @@ mod tests {
         remove_var("INLINE_C_RS_CFLAGS");
     }
 
+    #[test]
+    fn test_c_macro_same_as() {
+        let reference = assert_c! {
+            #include <stdio.h>
+            #include <stdlib.h>
+
+            int main(int argc, char** argv) {
+                (void) argc;
+                int n = atoi(argv[1]);
+                int sum = 0;
+
+                for (int i = 1; i <= n; ++i) {
+                    sum += i;
+                }
+
+                printf("%d", sum);
+
+                return 0;
+            }
+        };
+
+        (assert_c! {
+            #include <stdio.h>
+            #include <stdlib.h>
+
+            int main(int argc, char** argv) {
+                (void) argc;
+                int n = atoi(argv[1]);
+
+                printf("%d", n * (n + 1) / 2);
+
+                return 0;
+            }
+        })
+        .arg("10")
+        .same_as(&reference)
+        .success()
+        .stdout("55");
+
+        // The reference is not changed by the comparison.
+        (assert_c! {
+            #include <stdio.h>
+            #include <stdlib.h>
+
+            int main(int argc, char** argv) {
+                (void) argc;
+
+                printf("%d", atoi(argv[1]) == 4 ? 10 : -1);
+
+                return 0;
+            }
+        })
+        .arg("4")
+        .same_as(&reference)
+        .success()
+        .stdout("10");
+    }
+
     #[cfg(nightly)]
     #[test]
     fn test_c_macro_with_define() {
@@ -0,0 +1,350 @@
+//! Run the same program across several configurations, e.g. native,
+//! 32-bit and big-endian with an emulator, or all the combinations of
+//! standards and optimization levels, and aggregate the results.
+
+use crate::{
+    run::{run, Language},
+    Assert,
+};
+use lazy_static::lazy_static;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    process::Command,
+};
+
+/// A configuration of a [`Matrix`]: a name, and variables defined for
+/// the program, as with `#inline_c_rs` directives.
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    name: String,
+    variables: Vec<(String, String)>,
+}
+
+impl Configuration {
+    /// Create a configuration without variables.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            variables: Vec::new(),
+        }
+    }
+
+    /// The native configuration.
+    pub fn native() -> Self {
+        Self::new("native")
+    }
+
+    /// A 32-bit configuration, with `-m32`. It requires the 32-bit C
+    /// libraries (e.g. `gcc-multilib` on Debian).
+    pub fn bits32() -> Self {
+        Self::new("32-bit")
+            .variable("CFLAGS", "-m32")
+            .variable("CXXFLAGS", "-m32")
+    }
+
+    /// A cross-compiled configuration for `target` (e.g.
+    /// `powerpc-unknown-linux-gnu`, big-endian), run with `runner`
+    /// (e.g. `qemu-ppc -L /usr/powerpc-linux-gnu`).
+    pub fn cross(name: &str, target: &str, runner: &str) -> Self {
+        Self::new(name)
+            .variable("target", target)
+            .variable("runner", runner)
+    }
+
+    /// A configuration compiling with the `standard` language
+    /// standard, e.g. `c11`, see the special `standard` directive.
+    pub fn standard(standard: &str) -> Self {
+        Self::new(standard).variable("standard", standard)
+    }
+
+    /// A configuration compiling with the `level` optimization level,
+    /// e.g. `2` for `-O2`.
+    pub fn opt_level(level: &str) -> Self {
+        let flag = format!("-O{}", level);
+
+        Self::new(&flag)
+            .variable("CFLAGS", &flag)
+            .variable("CXXFLAGS", &flag)
+    }
+
+    /// A configuration compiling with the `sanitizers`, e.g.
+    /// `address,undefined`, see the special `sanitizer` directive.
+    pub fn sanitizer(sanitizers: &str) -> Self {
+        Self::new(sanitizers).variable("sanitizer", sanitizers)
+    }
+
+    /// A configuration compiling with `compiler`, e.g. `clang`, see
+    /// the special `compiler` directive.
+    pub fn compiler(compiler: &str) -> Self {
+        Self::new(compiler).variable("compiler", compiler)
+    }
+
+    /// A configuration compiling fully static programs against musl,
+    /// with `musl-gcc` and the special `static` directive. It requires the
+    /// musl toolchain (e.g. `musl-tools` on Debian), see
+    /// [`musl_available`], and C programs.
+    pub fn musl_static() -> Self {
+        Self::new("musl static")
+            .variable("compiler", "musl-gcc")
+            .variable("static", "1")
+    }
+
+    /// Define the variable `name` for the program. The value cannot
+    /// contain a double quote.
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.variables.push((name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// The combination of `self` and `other`. The flags variables, e.g.
+    /// `CFLAGS`, are concatenated; for the other variables, the ones of
+    /// `other` win.
+    fn combine(&self, other: &Self) -> Self {
+        const FLAGS: [&str; 4] = ["CPPFLAGS", "CFLAGS", "CXXFLAGS", "LDFLAGS"];
+
+        let mut combination = Self::new(&format!("{}, {}", self.name, other.name));
+        combination.variables = self.variables.clone();
+
+        for (name, value) in &other.variables {
+            match combination
+                .variables
+                .iter_mut()
+                .find(|(combined_name, _)| combined_name == name)
+            {
+                Some((_, combined_value)) if FLAGS.contains(&name.as_str()) => {
+                    combined_value.push(' ');
+                    combined_value.push_str(value);
+                }
+                Some((_, combined_value)) => *combined_value = value.clone(),
+                None => combination.variables.push((name.clone(), value.clone())),
+            }
+        }
+
+        combination
+    }
+
+    fn directives(&self) -> String {
+        self.variables
+            .iter()
+            .map(|(name, value)| format!("#inline_c_rs {}: \"{}\"\n", name, value))
+            .collect()
+    }
+}
+
+/// Whether the musl toolchain (`musl-gcc`) is available, e.g. to skip
+/// the [`Configuration::musl_static`] configuration otherwise. The
+/// detection runs once per process.
+pub fn musl_available() -> bool {
+    lazy_static! {
+        static ref MUSL_AVAILABLE: bool = Command::new("musl-gcc")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+    }
+
+    *MUSL_AVAILABLE
+}
+
+/// Run the same program across several [`Configuration`]s. All the
+/// configurations run, and the failing ones are reported together.
+///
+/// ```rust
+/// use inline_c::{Configuration, Language, Matrix};
+///
+/// fn test_matrix() {
+///     Matrix::new()
+///         .configuration(Configuration::native())
+///         .run(
+///             Language::C,
+///             r#"
+///                 #include <stdio.h>
+///                 #include <stdint.h>
+///
+///                 int main() {
+///                     uint32_t x = 1;
+///                     printf("%d", *(uint8_t *) &x == 1 ? 1 : 2);
+///
+///                     return 0;
+///                 }
+///             "#,
+///             |assert| {
+///                 assert.success();
+///             },
+///         );
+/// }
+///
+/// # fn main() { test_matrix() }
+/// ```
+///
+/// All the combinations of several dimensions run with
+/// [`Matrix::combine`]:
+///
+/// ```rust
+/// use inline_c::{matrix, Configuration, Language};
+///
+/// fn test_combinations() {
+///     matrix()
+///         .combine(["c99", "c11"].map(Configuration::standard))
+///         .combine(["0", "2"].map(Configuration::opt_level))
+///         .run(Language::C, "int main(void) { return 0; }", |assert| {
+///             assert.success();
+///         });
+/// }
+///
+/// # fn main() { test_combinations() }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Matrix {
+    configurations: Vec<Configuration>,
+}
+
+impl Matrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Combine each configuration of the matrix with each of
+    /// `configurations`, to run all the combinations, e.g. of
+    /// standards and optimization levels. On an empty matrix, it adds
+    /// `configurations`.
+    pub fn combine<I>(mut self, configurations: I) -> Self
+    where
+        I: IntoIterator<Item = Configuration>,
+    {
+        let configurations = configurations.into_iter().collect::<Vec<_>>();
+
+        self.configurations = if self.configurations.is_empty() {
+            configurations
+        } else {
+            self.configurations
+                .iter()
+                .flat_map(|configuration| {
+                    configurations
+                        .iter()
+                        .map(move |other| configuration.combine(other))
+                })
+                .collect()
+        };
+
+        self
+    }
+
+    /// Add a configuration to the matrix.
+    pub fn configuration(mut self, configuration: Configuration) -> Self {
+        self.configurations.push(configuration);
+
+        self
+    }
+
+    /// Compile and run `program` for each configuration, and call
+    /// `check` with its [`Assert`]. It panics at the end if `check`
+    /// has panicked, or if the program has failed to compile, for
+    /// some configurations.
+    pub fn run<F>(&self, language: Language, program: &str, check: F)
+    where
+        F: Fn(&mut Assert),
+    {
+        let failures = self
+            .configurations
+            .iter()
+            .filter_map(|configuration| {
+                let program = format!("{}{}", configuration.directives(), program);
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    let mut assert = run(language, &program)
+                        .unwrap_or_else(|error| panic!("Failed to compile: {}", error));
+
+                    check(&mut assert);
+                }));
+
+                result.err().map(|error| {
+                    let message = error
+                        .downcast_ref::<String>()
+                        .map(String::as_str)
+                        .or_else(|| error.downcast_ref::<&str>().copied())
+                        .unwrap_or("unknown error");
+
+                    format!("[{}] {}", configuration.name, message)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !failures.is_empty() {
+            panic!(
+                "{} of {} configuration(s) have failed:\n\n{}",
+                failures.len(),
+                self.configurations.len(),
+                failures.join("\n\n")
+            );
+        }
+    }
+}
+
+/// Shortcut to [`Matrix::new`].
+pub fn matrix() -> Matrix {
+    Matrix::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix() {
+        let matrix = Matrix::new()
+            .configuration(Configuration::native())
+            .configuration(Configuration::new("answer").variable("CFLAGS", "-DANSWER=42"));
+        let program = r#"
+            #include <stdio.h>
+
+            #ifndef ANSWER
+            #define ANSWER 0
+            #endif
+
+            int main() {
+                printf("%d", ANSWER);
+
+                return 0;
+            }
+        "#;
+
+        matrix.run(Language::C, program, |assert| {
+            assert.success();
+        });
+
+        let result = panic::catch_unwind(|| {
+            matrix.run(Language::C, program, |assert| {
+                assert.success().stdout("42");
+            });
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+
+        assert!(message.starts_with("1 of 2 configuration(s) have failed"));
+        assert!(message.contains("[native]"));
+    }
+
+    #[test]
+    fn test_combine() {
+        let matrix = matrix()
+            .configuration(Configuration::new("debug").variable("CFLAGS", "-g"))
+            .combine(["c99", "c11"].map(Configuration::standard))
+            .combine([Configuration::opt_level("2")]);
+
+        assert_eq!(
+            matrix
+                .configurations
+                .iter()
+                .map(|configuration| configuration.name.as_str())
+                .collect::<Vec<_>>(),
+            ["debug, c99, -O2", "debug, c11, -O2"]
+        );
+        assert_eq!(
+            matrix.configurations[1].variables,
+            [
+                (String::from("CFLAGS"), String::from("-g -O2")),
+                (String::from("standard"), String::from("c11")),
+                (String::from("CXXFLAGS"), String::from("-O2")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Translate the POSIX paths of the compiler flags, e.g. `-I/c/foo`,
+//! for the MinGW compilers of MSYS2 and Cygwin, which only understand
+//! Windows paths, e.g. `-IC:/foo`.
+
+use std::{env, process::Command};
+
+/// The flags whose value is a path, when it is attached to them.
+const PATH_FLAGS: &[&str] = &[
+    "--sysroot=",
+    "--gcc-toolchain=",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-I",
+    "-L",
+    "-B",
+];
+
+/// Whether the process runs in an MSYS2 (or Cygwin) environment.
+pub(crate) fn is_msys() -> bool {
+    cfg!(windows) && (env::var_os("MSYSTEM").is_some() || env::var_os("CYGWIN").is_some())
+}
+
+/// Translate the POSIX paths of `arguments` with `cygpath`, or else
+/// the drive-letter paths only, e.g. `/c/foo` or `/cygdrive/c/foo`.
+pub(crate) fn translate_paths(arguments: Vec<String>) -> Vec<String> {
+    let paths = arguments
+        .iter()
+        .filter_map(|argument| split_path(argument))
+        .map(|(_, path)| path)
+        .collect::<Vec<_>>();
+
+    if paths.is_empty() {
+        return arguments;
+    }
+
+    let mut translated_paths = cygpath(&paths)
+        .unwrap_or_else(|| paths.iter().map(|path| translate_drive(path)).collect())
+        .into_iter();
+
+    arguments
+        .iter()
+        .map(|argument| match split_path(argument) {
+            Some((flag, _)) => format!("{}{}", flag, translated_paths.next().unwrap_or_default()),
+            None => argument.clone(),
+        })
+        .collect()
+}
+
+/// Split `argument` into a flag and a POSIX path, e.g. `-I/usr/include`
+/// into `-I` and `/usr/include`. The flag is empty if `argument` is a
+/// path, which has at least two components, e.g. `/c/libfoo.a`, so
+/// that `/O2` or `/DFOO` are not paths.
+fn split_path(argument: &str) -> Option<(&str, &str)> {
+    if let Some((component, _)) = argument
+        .strip_prefix('/')
+        .and_then(|path| path.split_once('/'))
+    {
+        let is_component = |character: char| {
+            character.is_ascii_alphanumeric() || matches!(character, '_' | '-' | '.' | '+')
+        };
+
+        return (!component.is_empty() && component.chars().all(is_component))
+            .then_some(("", argument));
+    }
+
+    PATH_FLAGS.iter().find_map(|flag| {
+        argument
+            .strip_prefix(flag)
+            .filter(|path| path.starts_with('/'))
+            .map(|path| (*flag, path))
+    })
+}
+
+fn cygpath(paths: &[&str]) -> Option<Vec<String>> {
+    let output = Command::new("cygpath")
+        .arg("-m")
+        .args(paths)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let translated_paths = String::from_utf8(output.stdout)
+        .ok()?
+        .lines()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    (translated_paths.len() == paths.len()).then_some(translated_paths)
+}
+
+fn translate_drive(path: &str) -> String {
+    let drive_path = path.strip_prefix("/cygdrive").unwrap_or(path);
+    let mut characters = drive_path.chars();
+
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some('/'), Some(drive), None) | (Some('/'), Some(drive), Some('/'))
+            if drive.is_ascii_alphabetic() =>
+        {
+            format!(
+                "{}:/{}",
+                drive.to_ascii_uppercase(),
+                drive_path.get(3..).unwrap_or_default()
+            )
+        }
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("-I/c/foo"), Some(("-I", "/c/foo")));
+        assert_eq!(
+            split_path("-isystem/usr/include"),
+            Some(("-isystem", "/usr/include"))
+        );
+        assert_eq!(split_path("/c/foo/libfoo.a"), Some(("", "/c/foo/libfoo.a")));
+        assert_eq!(
+            split_path("--sysroot=/opt/sysroot"),
+            Some(("--sysroot=", "/opt/sysroot"))
+        );
+        assert_eq!(split_path("-B/opt/bin/"), Some(("-B", "/opt/bin/")));
+        assert_eq!(split_path("-IC:/foo"), None);
+        assert_eq!(split_path("-DFOO=/c/foo"), None);
+        assert_eq!(split_path("/O2"), None);
+        assert_eq!(split_path("/W4"), None);
+        assert_eq!(split_path("/DFOO"), None);
+        assert_eq!(split_path("/DFOO=/c/foo"), None);
+        assert_eq!(
+            split_path("/usr/lib/libfoo.a"),
+            Some(("", "/usr/lib/libfoo.a"))
+        );
+    }
+
+    #[test]
+    fn test_translate_drive() {
+        assert_eq!(translate_drive("/c/foo/bar"), "C:/foo/bar");
+        assert_eq!(translate_drive("/cygdrive/d/foo"), "D:/foo");
+        assert_eq!(translate_drive("/c"), "C:/");
+        assert_eq!(translate_drive("/usr/include"), "/usr/include");
+    }
+}
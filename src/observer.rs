@@ -0,0 +1,149 @@
+//! Observe the lifecycle of programs, e.g. for custom logging,
+//! metrics, or report formats.
+
+use lazy_static::lazy_static;
+use std::{
+    ffi::OsString,
+    path::Path,
+    process::{Command, Output},
+    sync::RwLock,
+    time::Duration,
+};
+
+lazy_static! {
+    static ref OBSERVER: RwLock<Option<Box<dyn Observer>>> = RwLock::new(None);
+}
+
+/// An event in the lifecycle of a program.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// The source of the program has been written in `path`.
+    SourceGenerated { path: &'a Path, source: &'a str },
+
+    /// The compiler is about to run.
+    CompileStarted { command: &'a Command },
+
+    /// The compiler has finished.
+    CompileFinished {
+        output: &'a Output,
+        duration: Duration,
+    },
+
+    /// The program is about to run, with the arguments `args`.
+    RunStarted { args: &'a [OsString] },
+
+    /// The program has finished.
+    RunFinished {
+        output: &'a Output,
+        duration: Duration,
+    },
+}
+
+/// An observer of events, see [`set_observer`].
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+/// Register the global observer, receiving the events of all the
+/// programs. It replaces the previous observer, if any.
+///
+/// # Example
+///
+/// ```rust
+/// use inline_c::{assert_c, set_observer, Event, Observer};
+///
+/// struct Logger;
+///
+/// impl Observer for Logger {
+///     fn on_event(&self, event: &Event) {
+///         if let Event::CompileFinished { duration, .. } = event {
+///             eprintln!("Compiled in {:?}", duration);
+///         }
+///     }
+/// }
+///
+/// fn test_observer() {
+///     set_observer(Box::new(Logger));
+///
+///     (assert_c! {
+///         int main() {
+///             return 0;
+///         }
+///     })
+///     .success();
+/// }
+///
+/// # fn main() { test_observer() }
+/// ```
+pub fn set_observer(observer: Box<dyn Observer>) {
+    *OBSERVER.write().unwrap_or_else(|error| error.into_inner()) = Some(observer);
+}
+
+/// Send `event` to the global observer, if any.
+pub(crate) fn notify(event: Event) {
+    if let Some(observer) = OBSERVER
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .as_ref()
+    {
+        observer.on_event(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run, Language};
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Observer for Recorder {
+        fn on_event(&self, event: &Event) {
+            let name = match event {
+                Event::SourceGenerated { source, .. } if source.contains("observed") => "source",
+                Event::CompileStarted { .. } => "compile-started",
+                Event::CompileFinished { .. } => "compile-finished",
+                Event::RunStarted { args } if args == &["observed"] => "run-started",
+                Event::RunFinished { .. } => "run-finished",
+                _ => return,
+            };
+
+            self.0.lock().unwrap().push(name.to_string());
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        set_observer(Box::new(Recorder(events.clone())));
+
+        run(
+            Language::C,
+            r#"
+                // observed
+                int main() {
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .arg("observed")
+        .success();
+
+        // Other tests may run concurrently, only check that our
+        // events are there.
+        let events = events.lock().unwrap();
+
+        for expected in &[
+            "source",
+            "compile-started",
+            "compile-finished",
+            "run-started",
+            "run-finished",
+        ] {
+            assert!(events.iter().any(|event| event == expected), "{}", expected);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Predicates on the permissions of the files created by the program,
+//! for [`Assert::created_file`](crate::Assert::created_file), e.g.
+//! with the `umask` directive.
+
+use predicates::{reflection::PredicateReflection, Predicate};
+use std::{fmt, path::Path};
+
+/// A predicate on the permission bits of a file, created by
+/// [`has_mode`].
+#[derive(Debug, Clone, Copy)]
+pub struct ModePredicate {
+    mode: u32,
+}
+
+/// Whether the permission bits of a file (including the setuid, setgid
+/// and sticky bits) are exactly `mode`, e.g. `0o600`. It is only
+/// supported on Unix; it is always false elsewhere.
+///
+/// ```rust
+/// use inline_c::{assert_c, predicates::*};
+///
+/// fn test_has_mode() {
+///     (assert_c! {
+///         #inline_c_rs umask: "077"
+///
+///         #include <stdio.h>
+///
+///         int main() {
+///             FILE *file = fopen("secret.txt", "w");
+///
+///             return file == NULL || fclose(file) != 0;
+///         }
+///     })
+///     .created_file("secret.txt", has_mode(0o600));
+///
+/// #   std::fs::remove_file("secret.txt").unwrap();
+/// }
+///
+/// # #[cfg(unix)]
+/// # fn main() { test_has_mode() }
+/// # #[cfg(not(unix))]
+/// # fn main() {}
+/// ```
+pub fn has_mode(mode: u32) -> ModePredicate {
+    ModePredicate { mode }
+}
+
+/// The permission bits of the file at `path`, if it exists.
+pub(crate) fn mode(path: &Path) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        path.metadata()
+            .ok()
+            .map(|metadata| metadata.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+
+        None
+    }
+}
+
+impl Predicate<Path> for ModePredicate {
+    fn eval(&self, path: &Path) -> bool {
+        mode(path) == Some(self.mode)
+    }
+}
+
+impl PredicateReflection for ModePredicate {}
+
+impl fmt::Display for ModePredicate {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "mode == {:#o}", self.mode)
+    }
+}
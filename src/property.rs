@@ -0,0 +1,191 @@
+//! Property-based testing across the FFI boundary, with
+//! [`proptest`](https://docs.rs/proptest).
+
+use crate::Assert;
+use proptest::{
+    strategy::Strategy,
+    test_runner::{TestCaseResult, TestError, TestRunner},
+};
+use std::{cell::RefCell, ffi::OsString};
+
+/// The input of one execution of a program: its arguments and its
+/// standard input.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramInput {
+    pub args: Vec<String>,
+    pub stdin: Vec<u8>,
+}
+
+impl ProgramInput {
+    /// Create an input made of arguments only.
+    pub fn args<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+            stdin: Vec::new(),
+        }
+    }
+
+    /// Create an input made of a standard input only.
+    pub fn stdin<B: Into<Vec<u8>>>(stdin: B) -> Self {
+        Self {
+            args: Vec::new(),
+            stdin: stdin.into(),
+        }
+    }
+}
+
+impl Assert {
+    /// Run the compiled program once per input generated by
+    /// `strategy`, and check `property` on each execution. The
+    /// program is compiled only once, and each case runs like with
+    /// [`Assert::assert`], e.g. in the fork server with the special
+    /// `fork_server` directive. When the property fails, the input is
+    /// shrunk, and this method panics with the minimal failing input.
+    ///
+    /// The number of cases can be configured with the
+    /// `PROPTEST_CASES` environment variable, as usual with
+    /// `proptest`.
+    ///
+    /// Requires the `proptest` feature.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use inline_c::{assert_c, ProgramInput};
+    /// use proptest::prelude::*;
+    ///
+    /// fn test_property() {
+    ///     (assert_c! {
+    ///         #include <stdio.h>
+    ///         #include <stdlib.h>
+    ///
+    ///         int main(int argc, char** argv) {
+    ///             (void) argc;
+    ///             printf("%d", abs(atoi(argv[1])));
+    ///
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .property(
+    ///         (-1000i32..1000).prop_map(|n| ProgramInput::args([n.to_string()])),
+    ///         |input, assert| {
+    ///             let n: i32 = input.args[0].parse().unwrap();
+    ///             assert.success().stdout(n.abs().to_string());
+    ///
+    ///             Ok(())
+    ///         },
+    ///     );
+    /// }
+    ///
+    /// # fn main() { test_property() }
+    /// ```
+    pub fn property<S, F>(&mut self, strategy: S, property: F)
+    where
+        S: Strategy<Value = ProgramInput>,
+        F: Fn(&ProgramInput, assert_cmd::assert::Assert) -> TestCaseResult,
+    {
+        if self.compilation_failed() {
+            panic!("The program has failed to compile");
+        }
+
+        let this = RefCell::new(self);
+        let mut runner = TestRunner::default();
+        let result = runner.run(&strategy, |input| {
+            let assert = this.borrow_mut().assert_with(
+                input.args.iter().map(OsString::from).collect(),
+                Some(input.stdin.clone()),
+            );
+
+            property(&input, assert)
+        });
+
+        match result {
+            Ok(()) => (),
+            Err(TestError::Fail(reason, input)) => panic!(
+                "Property failed with the minimal input `{:?}`: {}",
+                input, reason
+            ),
+            Err(TestError::Abort(reason)) => panic!("Property testing aborted: {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run::run, Language};
+    use proptest::prop_assert;
+    use std::panic;
+
+    #[test]
+    fn test_property_shrinks() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main(int argc, char** argv) {
+                    (void) argc;
+                    printf("%d", atoi(argv[1]) < 100);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        let message = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            assert.property(
+                (0..1000i32).prop_map(|n| ProgramInput::args([n.to_string()])),
+                |_, assert| {
+                    prop_assert!(assert.get_output().stdout == b"1");
+
+                    Ok(())
+                },
+            )
+        }))
+        .expect_err("The property was expected to fail")
+        .downcast::<String>()
+        .unwrap();
+
+        assert!(
+            message.contains(r#"minimal input `ProgramInput { args: ["100"], stdin: [] }`"#),
+            "{}",
+            message
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_property_in_process() {
+        crate::run_in_process(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main(int argc, char** argv) {
+                    (void) argc;
+                    printf("%s", argv[1]);
+
+                    return 0;
+                }
+            "#,
+            None,
+            &[],
+        )
+        .unwrap()
+        .property(
+            (0..1000i32).prop_map(|n| ProgramInput::args([n.to_string()])),
+            |input, assert| {
+                prop_assert!(assert.get_output().stdout == input.args[0].as_bytes());
+
+                Ok(())
+            },
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! The provenance of a program, i.e. the toolchain which has compiled
+//! it, for the bug reports and the cache keys.
+
+use crate::{report::escape_json, toolchain::Compiler};
+use std::{error::Error, fs, path::Path};
+
+/// The toolchain which has compiled a program, see
+/// [`Assert::provenance`](crate::Assert::provenance).
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    /// The compiler, with its kind and its version.
+    pub compiler: Compiler,
+    /// The arguments given to the compiler.
+    pub flags: Vec<String>,
+    /// The host triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub host: String,
+    /// The target triple.
+    pub target: String,
+}
+
+impl Provenance {
+    /// Serialize the provenance to JSON.
+    pub fn to_json(&self) -> String {
+        let string = |string: &str| format!("\"{}\"", escape_json(string));
+
+        format!(
+            "{{\n  \"compiler\": {{\n    \"path\": {},\n    \"kind\": {},\n    \"version\": {}\n  }},\n  \"flags\": [{}],\n  \"host\": {},\n  \"target\": {}\n}}\n",
+            string(&self.compiler.path.to_string_lossy()),
+            string(&self.compiler.kind.to_string()),
+            self.compiler
+                .version
+                .map_or_else(|| "null".to_string(), |version| string(&version.to_string())),
+            self.flags
+                .iter()
+                .map(|flag| string(flag))
+                .collect::<Vec<_>>()
+                .join(", "),
+            string(&self.host),
+            string(&self.target)
+        )
+    }
+
+    /// Write the provenance to `path`, in JSON.
+    pub(crate) fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_json()).map_err(|error| {
+            format!(
+                "Failed to write the provenance to `{}`: {}",
+                path.display(),
+                error
+            )
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::toolchain::{CompilerKind, CompilerVersion};
+
+    #[test]
+    fn test_to_json() {
+        let provenance = Provenance {
+            compiler: Compiler {
+                kind: CompilerKind::Gcc,
+                version: Some(CompilerVersion {
+                    major: 12,
+                    minor: 2,
+                    patch: 0,
+                }),
+                path: "/usr/bin/cc".into(),
+            },
+            flags: vec!["-O1".to_string(), "-DNAME=\"value\"".to_string()],
+            host: "x86_64-unknown-linux-gnu".to_string(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+        };
+
+        assert_eq!(
+            provenance.to_json(),
+            r#"{
+  "compiler": {
+    "path": "/usr/bin/cc",
+    "kind": "gcc",
+    "version": "12.2.0"
+  },
+  "flags": ["-O1", "-DNAME=\"value\""],
+  "host": "x86_64-unknown-linux-gnu",
+  "target": "x86_64-unknown-linux-gnu"
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Run the program with its standard output connected to a
+//! pseudo-terminal, for programs that behave differently when they
+//! write to a TTY (buffering, colors etc.).
+//!
+//! The output post-processing of the terminal is disabled, so that
+//! `\n` is not translated into `\r\n`. The standard input and the
+//! standard error are still pipes.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    process::{Command, Output},
+};
+
+#[cfg(unix)]
+pub(crate) fn run(
+    mut command: Command,
+    args: &[OsString],
+    stdin: Option<&[u8]>,
+) -> Result<Output, Box<dyn Error>> {
+    use std::{
+        fs::File,
+        io::{self, prelude::*},
+        mem::MaybeUninit,
+        os::unix::io::FromRawFd,
+        process::Stdio,
+        ptr, thread,
+    };
+
+    let (mut master, slave) = unsafe {
+        let mut master = 0;
+        let mut slave = 0;
+
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        ) != 0
+        {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // Processes spawned concurrently by other tests must not
+        // inherit the terminal, otherwise it would be kept open.
+        libc::fcntl(master, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(slave, libc::F_SETFD, libc::FD_CLOEXEC);
+
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+
+        if libc::tcgetattr(slave, termios.as_mut_ptr()) == 0 {
+            let mut termios = termios.assume_init();
+            termios.c_oflag &= !libc::OPOST;
+            libc::tcsetattr(slave, libc::TCSANOW, &termios);
+        }
+
+        (File::from_raw_fd(master), File::from_raw_fd(slave))
+    };
+
+    let mut child = command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::from(slave))
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // `command` holds the last copy of the slave in this process; it
+    // must be closed to observe the end of the output.
+    drop(command);
+
+    let mut child_stdin = child.stdin.take().expect("The child has no stdin");
+    let stdin = stdin.unwrap_or_default().to_vec();
+    let stdin_thread = thread::spawn(move || {
+        // The program may exit without reading its input.
+        let _ = child_stdin.write_all(&stdin);
+    });
+
+    let mut child_stderr = child.stderr.take().expect("The child has no stderr");
+    let stderr_thread = thread::spawn(move || {
+        let mut stderr = Vec::new();
+        child_stderr.read_to_end(&mut stderr).map(|_| stderr)
+    });
+
+    let mut stdout = Vec::new();
+    let mut buffer = [0; 4096];
+
+    loop {
+        match master.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(length) => stdout.extend_from_slice(&buffer[..length]),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            // Linux reports `EIO` once all the slaves are closed.
+            Err(_) => break,
+        }
+    }
+
+    let status = child.wait()?;
+    let stderr = stderr_thread
+        .join()
+        .expect("The stderr reader has panicked")?;
+    let _ = stdin_thread.join();
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(not(unix))]
+pub(crate) fn run(
+    _command: Command,
+    _args: &[OsString],
+    _stdin: Option<&[u8]>,
+) -> Result<Output, Box<dyn Error>> {
+    Err("The special `pty` directive is only supported on Unix".into())
+}
@@ -0,0 +1,150 @@
+//! Control what the failure output tells about the program, so that
+//! the logs of the CI do not leak the secrets given to it, e.g. in
+//! its environment variables.
+
+use std::process::Command;
+
+/// The patterns of the names of the environment variables whose values
+/// are redacted by default.
+const DEFAULT_PATTERNS: &[&str] = &[
+    "*TOKEN*",
+    "*SECRET*",
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*CREDENTIAL*",
+    "*API_KEY*",
+    "*ACCESS_KEY*",
+    "*PRIVATE_KEY*",
+    "*AUTH*",
+];
+
+#[derive(Clone, Debug)]
+pub(crate) struct Redaction {
+    // Whether the command and the standard input are left out.
+    quiet: bool,
+    patterns: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self::new(false, &[])
+    }
+}
+
+impl Redaction {
+    /// Redact the variables matching the default patterns, and
+    /// `patterns`, e.g. `AWS_*`. The patterns are case-insensitive,
+    /// and `*` matches any sequence of characters.
+    pub(crate) fn new(quiet: bool, patterns: &[&str]) -> Self {
+        Self {
+            quiet,
+            patterns: DEFAULT_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .chain(patterns.iter().map(|pattern| pattern.trim().to_string()))
+                .filter(|pattern| !pattern.is_empty())
+                .map(|pattern| pattern.to_ascii_uppercase())
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    pub(crate) fn is_redacted(&self, name: &str) -> bool {
+        let name = name.to_ascii_uppercase();
+
+        self.patterns
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &name))
+    }
+
+    /// Format `command` like its `Debug` implementation, with the
+    /// values of the redacted variables replaced by `<redacted>`.
+    pub(crate) fn command(&self, command: &Command) -> String {
+        let mut formatted = String::new();
+
+        for (name, value) in command.get_envs() {
+            let name = name.to_string_lossy();
+
+            match value {
+                Some(_) if self.is_redacted(&name) => {
+                    formatted.push_str(&format!("{}=<redacted> ", name))
+                }
+                Some(value) => formatted.push_str(&format!("{}={:?} ", name, value)),
+                None => (),
+            }
+        }
+
+        formatted.push_str(&format!("{:?}", command.get_program()));
+
+        for arg in command.get_args() {
+            formatted.push_str(&format!(" {:?}", arg));
+        }
+
+        formatted
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any sequence of
+/// characters.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // There is always a first part, maybe empty.
+    let first = parts.next().unwrap_or_default();
+
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts = parts.collect::<Vec<_>>();
+
+    match parts.split_last() {
+        // No `*`.
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(position) => rest = &rest[position + part.len()..],
+                    None => return false,
+                }
+            }
+
+            rest.ends_with(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction() {
+        let redaction = Redaction::new(false, &["aws_*", " "]);
+
+        assert!(redaction.is_redacted("GITHUB_TOKEN"));
+        assert!(redaction.is_redacted("db_password"));
+        assert!(redaction.is_redacted("AWS_REGION"));
+        assert!(!redaction.is_redacted("HOME"));
+        assert!(!redaction.is_redacted("MY_AWS"));
+
+        assert!(matches_pattern("A*B*C", "AXBYC"));
+        assert!(!matches_pattern("A*B*C", "AXBY"));
+        assert!(!matches_pattern("A*A", "A"));
+
+        let mut command = Command::new("program");
+        command
+            .env("API_TOKEN", "1234")
+            .env("LEVEL", "debug")
+            .arg("--flag");
+
+        let formatted = redaction.command(&command);
+
+        assert!(formatted.contains("API_TOKEN=<redacted>"));
+        assert!(formatted.contains("LEVEL=\"debug\""));
+        assert!(formatted.ends_with("\"program\" \"--flag\""));
+        assert!(!formatted.contains("1234"));
+    }
+}
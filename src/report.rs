@@ -0,0 +1,321 @@
+//! A machine-readable report of all the programs compiled and run in
+//! the process, in JSON or JUnit XML.
+//!
+//! The report is enabled with the special `report` directive, which
+//! holds the path to the report file. The format is JUnit XML if the
+//! path ends with `.xml`, JSON otherwise. The file is rewritten after
+//! each record, so that it is complete when the process exits.
+
+use crate::exception;
+use lazy_static::lazy_static;
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fmt::{self, Write as _},
+    fs,
+    path::{Path, PathBuf},
+    process::Output,
+    sync::Mutex,
+    time::Duration,
+};
+
+lazy_static! {
+    static ref RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+}
+
+/// The result of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Outcome {
+    CompilationFailed,
+    Exited(i32),
+    /// Terminated by an uncaught C++ exception, with its type and its
+    /// message.
+    UncaughtException(String),
+    Aborted,
+    Crashed,
+}
+
+impl Outcome {
+    pub(crate) fn from_output(output: &Output) -> Self {
+        let status = &output.status;
+
+        if let Some(exception) = exception::uncaught_exception(output) {
+            return Self::UncaughtException(exception);
+        }
+
+        if exception::has_aborted(status) {
+            return Self::Aborted;
+        }
+
+        match status.code() {
+            Some(code) if !crate::debugger::has_crashed(status) => Self::Exited(code),
+            _ => Self::Crashed,
+        }
+    }
+
+    fn is_failure(&self) -> bool {
+        !matches!(self, Self::Exited(_))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CompilationFailed => write!(formatter, "compilation failed"),
+            Self::Exited(code) => write!(formatter, "exited with {}", code),
+            Self::UncaughtException(exception) => {
+                write!(formatter, "uncaught exception `{}`", exception)
+            }
+            Self::Aborted => write!(formatter, "aborted"),
+            Self::Crashed => write!(formatter, "crashed"),
+        }
+    }
+}
+
+/// A record of the report.
+#[derive(Debug, Clone)]
+pub(crate) struct Record {
+    /// The name of the test, i.e. the name of the current thread.
+    pub(crate) test: String,
+    /// The label of the program in the test, see
+    /// [`Assert::context`](crate::Assert::context).
+    pub(crate) context: Option<String>,
+    pub(crate) language: String,
+    pub(crate) source_hash: u64,
+    pub(crate) compile_time: Duration,
+    pub(crate) run_time: Option<Duration>,
+    pub(crate) outcome: Outcome,
+}
+
+/// The reporter of one program.
+#[derive(Debug, Clone)]
+pub(crate) struct Reporter {
+    path: PathBuf,
+    test: String,
+    context: Option<String>,
+    language: String,
+    source_hash: u64,
+    compile_time: Duration,
+}
+
+impl Reporter {
+    pub(crate) fn new(
+        path: &str,
+        language: String,
+        source_hash: u64,
+        compile_time: Duration,
+    ) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            test: std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string(),
+            context: None,
+            language,
+            source_hash,
+            compile_time,
+        }
+    }
+
+    /// Record an outcome of the program, and rewrite the report.
+    pub(crate) fn set_context(&mut self, context: &str) {
+        self.context = Some(context.to_string());
+    }
+
+    pub(crate) fn record(&self, run_time: Option<Duration>, outcome: Outcome) {
+        let record = Record {
+            test: self.test.clone(),
+            context: self.context.clone(),
+            language: self.language.clone(),
+            source_hash: self.source_hash,
+            compile_time: self.compile_time,
+            run_time,
+            outcome,
+        };
+
+        let mut records = RECORDS.lock().unwrap_or_else(|error| error.into_inner());
+        records.push(record);
+
+        if let Err(error) = write(&self.path, &records) {
+            panic!(
+                "Failed to write the report `{}`: {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+}
+
+fn write(path: &Path, records: &[Record]) -> Result<(), Box<dyn Error>> {
+    let report = if path.extension() == Some(OsStr::new("xml")) {
+        to_junit(records)
+    } else {
+        to_json(records)
+    };
+
+    fs::write(path, report)?;
+
+    Ok(())
+}
+
+fn to_json(records: &[Record]) -> String {
+    let mut json = String::from("[\n");
+
+    for (index, record) in records.iter().enumerate() {
+        let _ = writeln!(
+            json,
+            "  {{\"test\": \"{test}\", \"context\": {context}, \"language\": \"{language}\", \
+             \"source_hash\": \"{hash:016x}\", \"compile_time\": {compile_time}, \"run_time\": {run_time}, \
+             \"result\": \"{result}\"}}{comma}",
+            test = escape_json(&record.test),
+            context = record.context.as_ref().map_or_else(
+                || "null".to_string(),
+                |context| format!("\"{}\"", escape_json(context))
+            ),
+            language = record.language,
+            hash = record.source_hash,
+            compile_time = record.compile_time.as_secs_f64(),
+            run_time = record
+                .run_time
+                .map_or_else(|| "null".to_string(), |time| time.as_secs_f64().to_string()),
+            result = record.outcome,
+            comma = if index + 1 < records.len() { "," } else { "" },
+        );
+    }
+
+    json.push(']');
+    json.push('\n');
+
+    json
+}
+
+fn to_junit(records: &[Record]) -> String {
+    let failures = records
+        .iter()
+        .filter(|record| record.outcome.is_failure())
+        .count();
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuite name=\"inline-c\" tests=\"{}\" failures=\"{}\">\n",
+        records.len(),
+        failures
+    );
+
+    for record in records {
+        let time = record.compile_time + record.run_time.unwrap_or_default();
+        let _ = write!(
+            xml,
+            "  <testcase classname=\"{test}\" name=\"{context}{language}-{hash:016x}\" time=\"{time}\">",
+            test = escape_xml(&record.test),
+            context = record
+                .context
+                .as_ref()
+                .map_or_else(String::new, |context| format!("{} ", escape_xml(context))),
+            language = record.language,
+            hash = record.source_hash,
+            time = time.as_secs_f64(),
+        );
+
+        if record.outcome.is_failure() {
+            let _ = write!(
+                xml,
+                "<failure message=\"{}\"/>",
+                escape_xml(&record.outcome.to_string())
+            );
+        } else {
+            let _ = write!(
+                xml,
+                "<system-out>{}</system-out>",
+                escape_xml(&record.outcome.to_string())
+            );
+        }
+
+        xml.push_str("</testcase>\n");
+    }
+
+    xml.push_str("</testsuite>\n");
+
+    xml
+}
+
+pub(crate) fn escape_json(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+
+    for character in string.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", character as u32);
+            }
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+fn escape_xml(string: &str) -> String {
+    string
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                test: "tests::test_\"foo\"".to_string(),
+                context: Some("static library".to_string()),
+                language: "c".to_string(),
+                source_hash: 42,
+                compile_time: Duration::from_millis(500),
+                run_time: Some(Duration::from_millis(250)),
+                outcome: Outcome::Exited(0),
+            },
+            Record {
+                test: "tests::test_bar".to_string(),
+                context: None,
+                language: "cpp".to_string(),
+                source_hash: 7,
+                compile_time: Duration::from_millis(100),
+                run_time: None,
+                outcome: Outcome::CompilationFailed,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            to_json(&records()),
+            "[\n  \
+             {\"test\": \"tests::test_\\\"foo\\\"\", \"context\": \"static library\", \"language\": \"c\", \
+             \"source_hash\": \"000000000000002a\", \"compile_time\": 0.5, \"run_time\": 0.25, \
+             \"result\": \"exited with 0\"},\n  \
+             {\"test\": \"tests::test_bar\", \"context\": null, \"language\": \"cpp\", \
+             \"source_hash\": \"0000000000000007\", \"compile_time\": 0.1, \"run_time\": null, \
+             \"result\": \"compilation failed\"}\n\
+             ]\n"
+        );
+    }
+
+    #[test]
+    fn test_to_junit() {
+        let xml = to_junit(&records());
+
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(xml.contains("classname=\"tests::test_&quot;foo&quot;\""));
+        assert!(xml.contains("<failure message=\"compilation failed\"/>"));
+        assert!(xml.contains("name=\"static library c-000000000000002a\""));
+    }
+}
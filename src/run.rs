@@ -1,15 +1,55 @@
-use crate::assert::Assert;
+use crate::{
+    artifacts::ArtifactSet,
+    assert::Assert,
+    baseline::Baseline,
+    debugger::Debugger,
+    diagnostics::Diagnostics,
+    directive::{self, is_identifier, DirectiveContext, DirectiveError},
+    footprint, forkserver, headers,
+    heap::HeapProfile,
+    inproc::{self, Callback, SharedObject},
+    journal::{self, Journal, JournalMode},
+    msys,
+    observer::{self, Event},
+    provenance::Provenance,
+    redact::Redaction,
+    report::{Outcome, Reporter},
+    sanitizer::Sanitizers,
+    scheduling::Scheduling,
+    standard::{self, Standard},
+    staticlib::StaticLib,
+    stderr::StderrExpectation,
+    temp,
+    toolchain::{self, MinCompiler},
+    trace::Trace,
+    version_script::{self, Linker},
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
-    borrow::Cow, collections::HashMap, env, error::Error, ffi::OsString, io::prelude::*,
-    path::PathBuf, process::Command,
+    borrow::Cow,
+    collections::HashMap,
+    env,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fs,
+    io::prelude::*,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
 };
+use tempfile::TempDir;
 
-#[doc(hidden)]
+/// The language of a program.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Language {
+    #[default]
     C,
     Cxx,
+    #[cfg(feature = "cuda")]
+    Cuda,
 }
 
 impl ToString for Language {
@@ -17,34 +57,746 @@ impl ToString for Language {
         match self {
             Self::C => String::from("c"),
             Self::Cxx => String::from("cpp"),
+            #[cfg(feature = "cuda")]
+            Self::Cuda => String::from("cu"),
+        }
+    }
+}
+
+/// Where a program is written in the Rust sources. The `assert_c!`
+/// and `assert_cxx!` macros fill it with `module_path!()`, `file!()`,
+/// `env!("CARGO_MANIFEST_DIR")` and `option_env!("OUT_DIR")`.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Location {
+    pub module_path: &'static str,
+    pub file: &'static str,
+    pub manifest_dir: &'static str,
+    pub out_dir: Option<&'static str>,
+    /// Whether the Rust crate is compiled with `debug_assertions`,
+    /// i.e. with the `dev` or `test` profile by default.
+    pub debug_assertions: bool,
+}
+
+impl Location {
+    pub fn new(module_path: &'static str, file: &'static str, manifest_dir: &'static str) -> Self {
+        Self {
+            module_path,
+            file,
+            manifest_dir,
+            out_dir: None,
+            debug_assertions: false,
+        }
+    }
+
+    /// The `OUT_DIR` of the build script of the crate, if any.
+    pub fn out_dir(mut self, out_dir: Option<&'static str>) -> Self {
+        self.out_dir = out_dir;
+
+        self
+    }
+
+    /// Whether the Rust crate is compiled with `debug_assertions`.
+    pub fn debug_assertions(mut self, debug_assertions: bool) -> Self {
+        self.debug_assertions = debug_assertions;
+
+        self
+    }
+
+    /// The directory of the Rust source file. `file!()` is relative to
+    /// the workspace root, which is the manifest directory or one of
+    /// its ancestors.
+    fn source_dir(&self) -> Option<PathBuf> {
+        let file = Path::new(self.file);
+
+        if file.is_absolute() {
+            return file.parent().map(Path::to_path_buf);
+        }
+
+        Path::new(self.manifest_dir)
+            .ancestors()
+            .map(|root| root.join(file))
+            .find(|path| path.is_file())
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+    }
+}
+
+/// The kind of output a program is compiled into.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputKind {
+    /// An executable, run in a child process.
+    #[default]
+    Executable,
+    /// A shared object, loaded inside the test process and run in a
+    /// forked child. It is only supported on Unix.
+    SharedObject,
+}
+
+/// The configuration of a program, for [`run_with`]. It is built with
+/// [`RunConfig::new`] and its builder methods, so that new fields can
+/// be added.
+///
+/// ```rust
+/// use inline_c::{run_with, Language, RunConfig};
+///
+/// fn test_run_with() {
+///     let config =
+///         RunConfig::new(Language::C, "int main() { return ANSWER; }").flag("-DANSWER=42");
+///
+///     run_with(config).unwrap().failure().code(42);
+/// }
+///
+/// # fn main() { test_run_with() }
+/// ```
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct RunConfig {
+    pub language: Language,
+    /// The source of the program, which can contain `#inline_c_rs`
+    /// directives.
+    pub source: String,
+    /// Compiler flags, added after `CPPFLAGS`, and `CFLAGS` or
+    /// `CXXFLAGS`.
+    pub flags: Vec<String>,
+    /// Variables of the program, as with `#inline_c_rs` directives.
+    /// They win over the directives and the meta environment
+    /// variables.
+    pub env: Vec<(String, String)>,
+    /// Environment variables of the compiler only, as with
+    /// `#inline_c_rs_compile` directives.
+    pub compile_env: Vec<(String, String)>,
+    /// Environment variables of the program only, as with
+    /// `#inline_c_rs_run` directives.
+    pub run_env: Vec<(String, String)>,
+    /// Include directories whose headers are system headers: their
+    /// warnings are not turned into errors, e.g. for third-party or
+    /// generated headers.
+    pub system_include_dirs: Vec<PathBuf>,
+    /// Macros defined for the program, with their value if any, as
+    /// with `#inline_c_rs define` directives.
+    pub defines: Vec<(String, Option<String>)>,
+    /// The target triple, as with the special `target` directive.
+    pub target: Option<String>,
+    /// The sysroot of the compiler, as with the special `sysroot` directive.
+    pub sysroot: Option<PathBuf>,
+    /// The directories of the programs of the compiler (`-B`), e.g.
+    /// the assembler and the linker, as with the special
+    /// `toolchain_prefixes` directive.
+    pub toolchain_prefixes: Vec<PathBuf>,
+    /// The GCC installation used by Clang, as with the special
+    /// `gcc_toolchain` directive.
+    pub gcc_toolchain: Option<PathBuf>,
+    /// The list of the symbols exported by the shared object, a GNU
+    /// version script (`.map`) or a module-definition file (`.def`),
+    /// as with the special `version_script` directive.
+    pub version_script: Option<PathBuf>,
+    pub output_kind: OutputKind,
+    #[doc(hidden)]
+    pub location: Option<Location>,
+    /// If there are callbacks, the program is compiled as a shared
+    /// object, whatever `output_kind` is.
+    #[doc(hidden)]
+    pub callbacks: Vec<Callback>,
+}
+
+impl RunConfig {
+    /// The configuration of the program `source`, written in
+    /// `language`.
+    pub fn new(language: Language, source: &str) -> Self {
+        Self {
+            language,
+            source: source.to_string(),
+            ..Default::default()
         }
     }
+
+    /// Add a compiler flag, see [`flags`](RunConfig::flags).
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_string());
+
+        self
+    }
+
+    /// Set the variable `name` of the program, see
+    /// [`env`](RunConfig::env).
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.push((name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Set the environment variable `name` of the compiler only, see
+    /// [`compile_env`](RunConfig::compile_env).
+    pub fn compile_env(mut self, name: &str, value: &str) -> Self {
+        self.compile_env.push((name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Set the environment variable `name` of the program only, see
+    /// [`run_env`](RunConfig::run_env).
+    pub fn run_env(mut self, name: &str, value: &str) -> Self {
+        self.run_env.push((name.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Compile the program into `output_kind`.
+    pub fn output_kind(mut self, output_kind: OutputKind) -> Self {
+        self.output_kind = output_kind;
+
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn location(mut self, location: Option<Location>) -> Self {
+        self.location = location;
+
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn callbacks(mut self, callbacks: &[Callback]) -> Self {
+        self.callbacks = callbacks.to_vec();
+
+        self
+    }
+
+    /// Add a system include directory, see
+    /// [`system_include_dirs`](RunConfig::system_include_dirs).
+    pub fn include_system_dir<P>(mut self, directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.system_include_dirs.push(directory.into());
+
+        self
+    }
+
+    /// Define the macro `name`, with `value` if any, e.g.
+    /// `define("GREETING", Some("\"Hello, World!\""))`. The argument
+    /// of the compiler is built for its toolchain, so `value` needs no
+    /// shell quoting.
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.to_string(), value.map(ToString::to_string)));
+
+        self
+    }
+
+    /// Compile the program for `target`, e.g.
+    /// `aarch64-unknown-linux-gnu`, as with the special `target` directive.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+
+        self
+    }
+
+    /// Compile the program against the `sysroot` directory, as with
+    /// the special `sysroot` directive.
+    pub fn sysroot<P>(mut self, sysroot: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.sysroot = Some(sysroot.into());
+
+        self
+    }
+
+    /// Add a directory of the programs of the compiler, as with the
+    /// `toolchain_prefixes` directive.
+    pub fn toolchain_prefix<P>(mut self, directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.toolchain_prefixes.push(directory.into());
+
+        self
+    }
+
+    /// Use the GCC installation at `directory` with Clang, as with the
+    /// `gcc_toolchain` directive.
+    pub fn gcc_toolchain<P>(mut self, directory: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.gcc_toolchain = Some(directory.into());
+
+        self
+    }
+
+    /// Export the symbols listed at `path` only, as with the
+    /// `version_script` directive.
+    pub fn version_script<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.version_script = Some(path.into());
+
+        self
+    }
+}
+
+/// Compile the program described by `config`, and return an
+/// [`Assert`] to run it and to assert its output. It is the API the
+/// `assert_c!` and `assert_cxx!` macros are built on, for crates
+/// building their own harness.
+pub fn run_with(config: RunConfig) -> Result<Assert, Box<dyn Error>> {
+    compile(config)
+}
+
+/// Whether `program` compiles, to gate tests on the capabilities of
+/// the platform, e.g. `__int128`. The program is not run, and the
+/// result is cached for the process, along with the meta environment
+/// variables, e.g. `INLINE_C_RS_CFLAGS`. The other variables of the
+/// process, e.g. the ones referred to with `${NAME}`, are not part of
+/// the cache.
+///
+/// ```rust
+/// use inline_c::{compiles, Language};
+///
+/// fn test_int128() {
+///     if !compiles(Language::C, "int main() { __int128 x = 0; return (int) x; }") {
+///         return;
+///     }
+///
+///     // …
+/// }
+///
+/// # fn main() { test_int128() }
+/// ```
+pub fn compiles(language: Language, program: &str) -> bool {
+    // The language, the program and the meta environment variables.
+    type Key = (String, String, Vec<(String, String)>);
+
+    lazy_static! {
+        static ref CACHE: Mutex<HashMap<Key, bool>> = Mutex::new(HashMap::new());
+    }
+
+    let mut meta_variables = env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_VAR_PREFIX))
+        .collect::<Vec<_>>();
+    meta_variables.sort();
+
+    let key = (language.to_string(), program.to_string(), meta_variables);
+
+    if let Some(compiles) = CACHE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .get(&key)
+    {
+        return *compiles;
+    }
+
+    let compiles = run(language, program).is_ok_and(|assert| assert.has_compiled());
+
+    CACHE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .insert(key, compiles);
+
+    compiles
 }
 
 #[doc(hidden)]
 pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn Error>> {
-    let (program, variables) = collect_environment_variables(program);
+    run_at(language, program, None)
+}
+
+/// Like [`run`], but:
+///
+/// * the meta environment variables scoped to the module of
+///   `location` (or to one of its parents) win over the global ones,
+/// * the directory of the Rust source file is added to the include
+///   path, so that `#include "local.h"` resolves next to it.
+#[doc(hidden)]
+pub fn run_at(
+    language: Language,
+    program: &str,
+    location: Option<Location>,
+) -> Result<Assert, Box<dyn Error>> {
+    run_with_callbacks(language, program, location, &[])
+}
 
-    let mut program_file = tempfile::Builder::new()
-        .prefix("inline-c-rs-")
-        .suffix(&format!(".{}", language.to_string()))
-        .tempfile()?;
-    program_file.write_all(program.as_bytes())?;
+/// Like [`run_at`], but the program can call the Rust `callbacks`. If
+/// there are callbacks, the program is compiled as a shared object
+/// and run inside the test process.
+#[doc(hidden)]
+pub fn run_with_callbacks(
+    language: Language,
+    program: &str,
+    location: Option<Location>,
+    callbacks: &[Callback],
+) -> Result<Assert, Box<dyn Error>> {
+    compile(RunConfig {
+        language,
+        source: program.to_string(),
+        location,
+        callbacks: callbacks.to_vec(),
+        ..Default::default()
+    })
+}
+
+/// Like [`run_with_callbacks`], but the program is always compiled as
+/// a shared object and run inside the test process. The
+/// `assert_c_inproc!` and `assert_cxx_inproc!` macros use it.
+#[doc(hidden)]
+pub fn run_in_process(
+    language: Language,
+    program: &str,
+    location: Option<Location>,
+    callbacks: &[Callback],
+) -> Result<Assert, Box<dyn Error>> {
+    compile(RunConfig {
+        language,
+        source: program.to_string(),
+        output_kind: OutputKind::SharedObject,
+        location,
+        callbacks: callbacks.to_vec(),
+        ..Default::default()
+    })
+}
 
-    let host = target_lexicon::HOST.to_string();
-    let target = &host;
+fn compile(config: RunConfig) -> Result<Assert, Box<dyn Error>> {
+    let RunConfig {
+        language,
+        source: program,
+        flags,
+        env,
+        compile_env,
+        run_env,
+        mut system_include_dirs,
+        defines: config_defines,
+        target: config_target,
+        sysroot: config_sysroot,
+        toolchain_prefixes: mut config_toolchain_prefixes,
+        gcc_toolchain: config_gcc_toolchain,
+        version_script: config_version_script,
+        output_kind,
+        location,
+        callbacks,
+    } = config;
+    let in_process = output_kind == OutputKind::SharedObject || !callbacks.is_empty();
+    let callbacks = callbacks.as_slice();
+    let program = program.as_str();
+    let source = program.to_string();
+    let module_path = location
+        .as_ref()
+        .map_or("", |location| location.module_path);
+    let Environment {
+        program,
+        mut defines,
+        mut variables,
+        compile_env: mut compile_variables,
+        run_env: mut run_variables,
+        secrets,
+    } = collect_environment_variables(program, module_path)?;
+    variables.extend(env);
+    compile_variables.extend(compile_env);
+    run_variables.extend(run_env);
+    defines.extend(config_defines);
 
-    let msvc = target.contains("msvc");
+    if let Some(target) = config_target {
+        variables.insert("target".to_string(), target);
+    }
+
+    if let Some(sysroot) = config_sysroot {
+        variables.insert("sysroot".to_string(), sysroot.display().to_string());
+    }
+
+    if !config_toolchain_prefixes.is_empty() {
+        if let Some(prefixes) = variables.get("toolchain_prefixes") {
+            config_toolchain_prefixes.splice(0..0, env::split_paths(prefixes));
+        }
+
+        let prefixes = env::join_paths(&config_toolchain_prefixes)
+            .map_err(|error| format!("Invalid toolchain prefix: {}", error))?;
+        variables.insert(
+            "toolchain_prefixes".to_string(),
+            prefixes.to_string_lossy().into_owned(),
+        );
+    }
+
+    if let Some(version_script) = config_version_script {
+        variables.insert(
+            "version_script".to_string(),
+            version_script.display().to_string(),
+        );
+    }
+
+    if let Some(gcc_toolchain) = config_gcc_toolchain {
+        variables.insert(
+            "gcc_toolchain".to_string(),
+            gcc_toolchain.display().to_string(),
+        );
+    }
+
+    let preload = variables.remove("preload");
+    let timezone = variables.remove("timezone");
+    let locale = variables.remove("locale");
+    let faketime = variables.remove("faketime");
+    let network = variables.remove("network");
+    let mut working_dir = variables
+        .remove("cwd")
+        .map(|cwd| working_dir(&cwd, location.as_ref()))
+        .transpose()?;
+    // A binary fixture, e.g. the input of a parser.
+    let stdin = variables
+        .remove("stdin_file")
+        .map(|stdin_file| {
+            let path = manifest_path(&stdin_file, location.as_ref());
 
-    let (_, input_path) = program_file.keep()?;
-    let mut output_temp = tempfile::Builder::new();
-    let output_temp = output_temp.prefix("inline-c-rs-");
+            fs::read(&path).map_err(|error| {
+                format!(
+                    "Failed to read the `stdin_file` `{}`: {}",
+                    path.display(),
+                    error
+                )
+            })
+        })
+        .transpose()?;
+    let min_compiler = variables.remove("min_compiler");
+    let stderr_expectation = StderrExpectation {
+        strict: is_enabled(&variables, "strict_stderr"),
+        expected: variables.remove("expect_stderr"),
+    };
+    let scheduling = Scheduling::parse(
+        variables.remove("nice").as_deref(),
+        variables.remove("scheduler").as_deref(),
+        variables.remove("cpu_affinity").as_deref(),
+    )?;
+    let umask = variables
+        .remove("umask")
+        .map(|umask| match u32::from_str_radix(&umask, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(format!("{:03o}", mode)),
+            _ => Err(format!(
+                "Invalid `umask` value `{}`, expected an octal mode, e.g. `077`",
+                umask
+            )),
+        })
+        .transpose()?;
+    let stack_size = variables
+        .remove("stack_size")
+        .map(|stack_size| parse_size(&stack_size))
+        .transpose()?;
+    let custom_directives = directive::take_registered(&mut variables);
+    let program_key = journal::hash(format!("{}\0{}", language.to_string(), program).as_bytes());
+    let journal = Journal::from_variables(&variables, program_key)?;
+
+    if let Some(journal) = &journal {
+        if journal.mode() == JournalMode::Replay {
+            // The command is never spawned: outputs are read from the
+            // journal.
+            return Ok(Assert::new(Command::new("inline-c-rs-replay"), None)
+                .with_source(source)
+                .with_stdin(stdin)
+                .with_journal(journal.clone()));
+        }
+    }
+
+    // The host triple is the one `inline-c` has been compiled for,
+    // unless `host` says otherwise, e.g. in a container.
+    let host = variables
+        .get("host")
+        .cloned()
+        .unwrap_or_else(|| target_lexicon::HOST.to_string());
+    // Cross-compiled programs are usually run with `runner`, e.g.
+    // `qemu-ppc`.
+    let target = &variables
+        .get("target")
+        .cloned()
+        .unwrap_or_else(|| host.clone());
+
+    let msvc = target.contains("msvc");
 
     if msvc {
-        output_temp.suffix(".exe");
+        if let Some(name) = ["sysroot", "toolchain_prefixes", "gcc_toolchain"]
+            .iter()
+            .find(|name| variables.contains_key(**name))
+        {
+            return Err(format!(
+                "The special `{}` directive is not supported with MSVC",
+                name
+            )
+            .into());
+        }
+    }
+
+    let fork_server = is_enabled(&variables, "fork_server") && !in_process;
+
+    let pty = is_enabled(&variables, "pty");
+
+    if pty && !cfg!(unix) {
+        return Err("The special `pty` directive is only supported on Unix".into());
+    }
+
+    if fork_server && (msvc || !cfg!(unix)) {
+        return Err("The fork server is only supported on Unix".into());
+    }
+
+    if in_process && working_dir.is_some() {
+        return Err("The special `cwd` directive is not supported in-process".into());
     }
 
-    let (_, output_path) = output_temp.tempfile()?.keep()?;
+    if in_process && (msvc || !cfg!(unix)) {
+        return Err(
+            "Running a program in-process, e.g. with callbacks, is only supported on Unix".into(),
+        );
+    }
+
+    // In deterministic mode, all the files live in a build directory
+    // with fixed file names, so that no random path ends up inside
+    // the binary.
+    let deterministic = is_enabled(&variables, "deterministic");
+    // A named output, e.g. `libplugin.so`, lives in a build directory
+    // too, so that the names do not collide between the programs.
+    let output_name = variables
+        .get("output_name")
+        .map(|name| {
+            if Path::new(name)
+                .file_name()
+                .is_some_and(|file_name| file_name == name.as_str())
+            {
+                Ok(name.clone())
+            } else {
+                Err(format!(
+                    "Invalid `output_name` value `{}`, expected a file name, e.g. `libplugin.so`",
+                    name
+                ))
+            }
+        })
+        .transpose()?;
+    let verbose = is_enabled(&variables, "verbose");
+    let tee = is_enabled(&variables, "tee");
+    // The variables read with `env("…")` are always redacted.
+    let redaction = Redaction::new(
+        is_enabled(&variables, "quiet"),
+        &variables
+            .get("redact")
+            .map(|patterns| patterns.split(','))
+            .into_iter()
+            .flatten()
+            .chain(secrets.iter().map(String::as_str))
+            .collect::<Vec<_>>(),
+    );
+    let diagnostics_color = match variables.get("diagnostics_color").map(String::as_str) {
+        None | Some("auto") => None,
+        Some(color @ "always") | Some(color @ "never") => Some(color.to_string()),
+        Some(color) => {
+            return Err(format!(
+                "Invalid `diagnostics_color` value `{}`, expected `always`, `never` or `auto`",
+                color
+            )
+            .into())
+        }
+    };
+    let backtrace = is_enabled(&variables, "backtrace");
+    let sanitizers = variables
+        .get("sanitizer")
+        .map(|sanitizers| Sanitizers::parse(sanitizers))
+        .transpose()?
+        .unwrap_or_default();
+    let sanitizer_logs = if sanitizers.is_empty() {
+        None
+    } else {
+        Some(
+            tempfile::Builder::new()
+                .prefix("inline-c-rs-sanitizer-")
+                .tempdir_in(temp::temp_dir())?,
+        )
+    };
+    let staticlib = variables
+        .get("staticlib")
+        .map(|staticlib| StaticLib::find(staticlib, msvc))
+        .transpose()?;
+    let uses_threads = uses_threads(&program);
+    let openmp = is_enabled(&variables, "openmp");
+    let static_linking = is_enabled(&variables, "static");
+    let auto_link_flags = if is_enabled(&variables, "auto_link") {
+        auto_link_flags(&program, msvc)
+    } else {
+        Vec::new()
+    };
+    let debugger = variables
+        .remove("debugger")
+        .map(|debugger| Debugger::from_name(&debugger))
+        .transpose()?;
+    let profile = Profile::of(&variables, location.as_ref())?;
+    let build_dir = if deterministic || output_name.is_some() {
+        if deterministic {
+            variables
+                .entry("SOURCE_DATE_EPOCH".to_string())
+                .or_insert_with(|| "0".to_string());
+        }
+
+        Some(
+            tempfile::Builder::new()
+                .prefix("inline-c-rs-")
+                .tempdir_in(temp::temp_dir())?,
+        )
+    } else {
+        None
+    };
+
+    // A custom extension selects the language of the source file for
+    // custom compilers, e.g. `cl` for OpenCL kernels.
+    let extension = variables
+        .get("source_extension")
+        .cloned()
+        .unwrap_or_else(|| language.to_string());
+
+    let (input_path, output_path) = match build_dir.as_ref().map(TempDir::path) {
+        Some(build_dir) => {
+            let input_path = build_dir.join(format!("inline-c-rs.{}", extension));
+            fs::write(&input_path, program.as_bytes())?;
+
+            let output_path = match &output_name {
+                Some(output_name) if build_dir.join(output_name) == input_path => {
+                    return Err(format!(
+                        "Invalid `output_name` value `{}`, it is the name of the source file",
+                        output_name
+                    )
+                    .into())
+                }
+                Some(output_name) => build_dir.join(output_name),
+                None if msvc => build_dir.join("inline-c-rs.exe"),
+                None => build_dir.join("inline-c-rs"),
+            };
+
+            (input_path, output_path)
+        }
+
+        None => {
+            let mut program_file = tempfile::Builder::new()
+                .prefix("inline-c-rs-")
+                .suffix(&format!(".{}", extension))
+                .tempfile_in(temp::temp_dir())?;
+            program_file.write_all(program.as_bytes())?;
+
+            let (_, input_path) = program_file.keep()?;
+            let output_prefix = program_name(std::thread::current().name(), program_key);
+            let mut output_temp = tempfile::Builder::new();
+            let output_temp = output_temp.prefix(&output_prefix);
+
+            if msvc {
+                output_temp.suffix(".exe");
+            }
+
+            let (_, output_path) = output_temp.tempfile_in(temp::temp_dir())?.keep()?;
+
+            (input_path, output_path)
+        }
+    };
+
+    if verbose {
+        eprintln!("inline-c: source `{}`", input_path.display());
+    }
+
+    observer::notify(Event::SourceGenerated {
+        path: &input_path,
+        source: &program,
+    });
 
     let mut build = cc::Build::new();
     let mut build = build
@@ -52,15 +804,98 @@ pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn Error>>
         .warnings(true)
         .extra_warnings(true)
         .warnings_into_errors(true)
-        .debug(false)
+        .debug(backtrace || debugger.is_some() || profile.is_some_and(|profile| profile.debug_info))
         .host(&host)
         .target(target)
-        .opt_level(1);
+        .opt_level(profile.map_or(1, |profile| profile.opt_level));
 
     if let Language::Cxx = language {
         build = build.cpp(true);
     }
 
+    // With MSVC, the C runtime is linked statically; the system
+    // libraries are always linked dynamically.
+    if static_linking && msvc {
+        build = build.static_crt(true);
+    }
+
+    // A custom compiler, with its own flags, e.g. `icpx -fsycl`.
+    if let Some(custom_compiler) = variables.get("compiler") {
+        let mut custom_compiler = split_words(custom_compiler)?.into_iter();
+        build = build.compiler(
+            custom_compiler
+                .next()
+                .ok_or("The special `compiler` directive is empty")?,
+        );
+
+        for flag in custom_compiler {
+            build = build.flag(flag);
+        }
+    }
+
+    // `cc-rs` runs `nvcc`, and forwards the flags of the host compiler
+    // with `-Xcompiler`.
+    #[cfg(feature = "cuda")]
+    if let Language::Cuda = language {
+        build = build.cuda(true);
+    }
+
+    if let Some(source_dir) = location.as_ref().and_then(Location::source_dir) {
+        build = build.include(source_dir);
+    }
+
+    if is_enabled(&variables, "auto_include") {
+        for include_dir in auto_include_dirs(location.as_ref()) {
+            build = build.include(include_dir);
+        }
+    }
+
+    if is_enabled(&variables, "use_out_dir") {
+        let out_dir = out_dir(location.as_ref())
+            .filter(|out_dir| out_dir.is_dir())
+            .ok_or(
+                "The `OUT_DIR` directory is unknown (see `use_out_dir`), does the crate have a \
+                 build script?",
+            )?;
+
+        build = build.include(out_dir);
+    }
+
+    if let Some(directories) = variables.get("system_include_dirs") {
+        system_include_dirs.extend(env::split_paths(directories));
+    }
+
+    // The warnings of the system headers are silenced, so they are not
+    // turned into errors.
+    for system_include_dir in &system_include_dirs {
+        if msvc {
+            build = build
+                .flag("/external:W0")
+                .flag(format!("/external:I{}", system_include_dir.display()));
+        } else {
+            build = build.flag("-isystem").flag(system_include_dir);
+        }
+    }
+
+    for (name, value) in &defines {
+        if !is_identifier(name) {
+            return Err(format!("Invalid macro name `{}` to define", name).into());
+        }
+
+        build = build.define(name, value.as_deref());
+    }
+
+    // The functions of the API of a library can be exported whatever
+    // the default visibility is, e.g. `-fvisibility=hidden`.
+    build = build.define(
+        "INLINE_C_EXPORT",
+        Some(if msvc {
+            "__declspec(dllexport)"
+        } else {
+            "__attribute__((visibility(\"default\")))"
+        }),
+    );
+
     // Usually, `cc-rs` is used to produce libraries. In our case, we
     // want to produce an (executable) object file. The following code
     // is kind of a hack around `cc-rs`. It avoids the addition of the
@@ -68,158 +903,4619 @@ pub fn run(language: Language, program: &str) -> Result<Assert, Box<dyn Error>>
     // arguments.
 
     let compiler = build.try_get_compiler()?;
+
+    if let Some(min_compiler) = min_compiler {
+        MinCompiler::parse(&min_compiler)?.check(&toolchain::detect_once(&compiler))?;
+    }
+
+    let standard_flag = variables
+        .get("standard")
+        .map(|standard| Standard::parse(standard)?.flag(&language, msvc))
+        .transpose()?;
+
+    if let Some(standard_flag) = &standard_flag {
+        standard::probe(&compiler, &language, standard_flag)?;
+    }
+
     let mut command;
 
     if msvc {
-        command = compiler.to_command();
+        command = compiler_command(&compiler, &language, &variables, target, &host, None);
+        command.args(&flags);
+        command.args(&standard_flag);
 
-        command_add_compiler_flags(&mut command, &variables);
-        command_add_output_file(&mut command, &output_path, msvc, compiler.is_like_clang());
-        command.arg(input_path.clone());
-        command.envs(variables.clone());
-    } else {
-        command = Command::new(compiler.path());
+        if deterministic {
+            command.arg("-Brepro");
+        }
+
+        command.args(sanitizers.compiler_flags(msvc)?);
+
+        if openmp {
+            command.arg("-openmp");
+        }
+
+        if let Some(stack_size) = stack_size {
+            command.arg(format!("-F{}", stack_size));
+        }
 
-        command.arg(input_path.clone()); // the input must come first
-        command.args(compiler.args());
-        command_add_compiler_flags(&mut command, &variables);
         command_add_output_file(&mut command, &output_path, msvc, compiler.is_like_clang());
-    }
+        command.arg(input_path.clone());
 
-    command.envs(variables.clone());
+        // `cl` gives the libraries to the linker.
+        for flag in &auto_link_flags {
+            if !command.get_args().any(|argument| argument == *flag) {
+                command.arg(flag);
+            }
+        }
+    } else {
+        // The input must come first.
+        command = compiler_command(
+            &compiler,
+            &language,
+            &variables,
+            target,
+            &host,
+            Some(&input_path),
+        );
+        command.args(&flags);
+        command.args(&standard_flag);
 
-    let mut files_to_remove = vec![input_path, output_path.clone()];
-    if msvc {
-        let mut intermediate_path = output_path.clone();
-        intermediate_path.set_extension("obj");
-        files_to_remove.push(intermediate_path);
-    }
+        // The output of the compiler is captured, so colors would be
+        // disabled by default.
+        if let Some(diagnostics_color) = diagnostics_color {
+            command.arg(format!("-fdiagnostics-color={}", diagnostics_color));
+        }
 
-    let clang_output = command.output()?;
+        if let Some(build_dir) = build_dir.as_ref().filter(|_| deterministic) {
+            let mut prefix_map = OsString::from("-ffile-prefix-map=");
+            prefix_map.push(build_dir.path());
+            prefix_map.push("=.");
+            command.arg(prefix_map);
 
-    if !clang_output.status.success() {
-        return Ok(Assert::new(command, Some(files_to_remove)));
-    }
+            if compiler.is_like_gnu() {
+                command.arg("-frandom-seed=inline-c-rs");
+            }
+        }
 
-    let mut command = Command::new(output_path);
-    command.envs(variables);
+        command.args(sanitizers.compiler_flags(msvc)?);
 
-    Ok(Assert::new(command, Some(files_to_remove)))
-}
+        if uses_threads {
+            command.arg("-pthread");
+        }
 
-fn collect_environment_variables<'p>(program: &'p str) -> (Cow<'p, str>, HashMap<String, String>) {
-    const ENV_VAR_PREFIX: &str = "INLINE_C_RS_";
+        if static_linking {
+            command.arg("-static");
+        }
 
-    lazy_static! {
-        static ref REGEX: Regex = Regex::new(
-            r#"#inline_c_rs (?P<variable_name>[^:]+):\s*"(?P<variable_value>[^"]+)"\r?\n"#
-        )
-        .unwrap();
-    }
+        // Libraries already given by the user are not repeated.
+        for flag in &auto_link_flags {
+            if !command.get_args().any(|argument| argument == *flag) {
+                command.arg(flag);
+            }
+        }
 
-    let mut variables = HashMap::new();
+        if openmp {
+            // Apple Clang has no `-fopenmp` driver flag, and its
+            // runtime must be linked explicitly.
+            if cfg!(target_os = "macos") && compiler.is_like_clang() {
+                command.args(["-Xpreprocessor", "-fopenmp", "-lomp"]);
+            } else {
+                command.arg("-fopenmp");
+            }
+        }
 
-    for (variable_name, variable_value) in env::vars().filter_map(|(mut name, value)| {
-        if name.starts_with(ENV_VAR_PREFIX) {
-            Some((name.split_off(ENV_VAR_PREFIX.len()), value))
-        } else {
-            None
+        // On Unix, the stack size is a resource limit, set when the
+        // program is run.
+        if let (Some(stack_size), true) = (stack_size, cfg!(target_os = "windows")) {
+            command.arg(format!("-Wl,--stack,{}", stack_size));
         }
-    }) {
-        variables.insert(variable_name, variable_value);
+
+        command_add_output_file(&mut command, &output_path, msvc, compiler.is_like_clang());
     }
 
-    for captures in REGEX.captures_iter(program) {
-        variables.insert(
-            captures["variable_name"].trim().to_string(),
-            captures["variable_value"].to_string(),
-        );
+    if in_process {
+        command.args(inproc::shared_object_flags());
     }
 
-    let program = REGEX.replace_all(program, "");
+    // The list of the exported symbols is translated for the linker,
+    // possibly into a generated file.
+    let version_script_path = match variables.get("version_script") {
+        Some(_) if !in_process => {
+            return Err(
+                "The special `version_script` directive requires a shared object, see \
+                 `OutputKind::SharedObject`"
+                    .into(),
+            )
+        }
+        Some(version_script) => {
+            let linker = if msvc {
+                Linker::Msvc
+            } else if target.contains("apple") {
+                Linker::Apple
+            } else {
+                Linker::Gnu
+            };
+            let (linker_flags, generated_path) =
+                version_script::linker_flags(Path::new(version_script), linker)?;
+            command.args(linker_flags);
 
-    (program, variables)
-}
+            generated_path
+        }
+        None => None,
+    };
 
-// This is copy-pasted and edited from `cc-rs`.
-fn command_add_output_file(command: &mut Command, output_path: &PathBuf, msvc: bool, clang: bool) {
-    if msvc && !clang {
-        let mut intermediate_path = output_path.clone();
-        intermediate_path.set_extension("obj");
+    // Without an explicit entry point, the `main` function of a shared
+    // object is renamed, so that the same program is an executable and
+    // a library.
+    let entry_point = variables.get("entry_point").cloned();
+    let entry_path = if in_process && entry_point.is_none() {
+        let mut entry_file = tempfile::Builder::new()
+            .prefix("inline-c-rs-entry-")
+            .suffix(&format!(".{}", language.to_string()))
+            .tempfile_in(temp::temp_dir())?;
+        entry_file.write_all(inproc::ENTRY_SOURCE.as_bytes())?;
+        let (_, entry_path) = entry_file.keep()?;
 
-        let mut fo_arg = OsString::from("-Fo");
-        fo_arg.push(intermediate_path);
-        command.arg(fo_arg);
+        command.arg(forkserver::RENAME_MAIN_FLAG);
+        command.arg(&entry_path);
 
-        let mut fe_arg = OsString::from("-Fe");
-        fe_arg.push(output_path);
-        command.arg(fe_arg);
+        Some(entry_path)
     } else {
-        command.arg("-o").arg(output_path);
-    }
-}
-
-fn command_add_compiler_flags(command: &mut Command, variables: &HashMap<String, String>) {
-    let get_env_flags = |env_name: &str| -> Vec<String> {
-        variables
-            .get(env_name)
-            .map(|e| e.to_string())
-            .ok_or_else(|| env::var(env_name))
-            .unwrap_or_default()
-            .split_ascii_whitespace()
-            .map(|slice| slice.to_string())
-            .collect()
+        None
     };
 
-    command.args(get_env_flags("CFLAGS"));
-    command.args(get_env_flags("CPPFLAGS"));
-    command.args(get_env_flags("CXXFLAGS"));
+    let callbacks_path = if !callbacks.is_empty() {
+        let mut callbacks_file = tempfile::Builder::new()
+            .prefix("inline-c-rs-callbacks-")
+            .suffix(&format!(".{}", language.to_string()))
+            .tempfile_in(temp::temp_dir())?;
+        callbacks_file.write_all(inproc::callbacks_source(callbacks).as_bytes())?;
+        let (_, callbacks_path) = callbacks_file.keep()?;
 
-    for linker_argument in get_env_flags("LDFLAGS") {
-        command.arg(format!("-Wl,{}", linker_argument));
-    }
+        command.args(callbacks.iter().map(Callback::define_flag));
+        command.arg(&callbacks_path);
+
+        Some(callbacks_path)
+    } else {
+        None
+    };
+
+    let fork_server_path = if fork_server {
+        let mut fork_server_file = tempfile::Builder::new()
+            .prefix("inline-c-rs-fork-server-")
+            .suffix(&format!(".{}", language.to_string()))
+            .tempfile_in(temp::temp_dir())?;
+        fork_server_file.write_all(forkserver::SOURCE.as_bytes())?;
+        let (_, fork_server_path) = fork_server_file.keep()?;
+
+        command.arg(forkserver::RENAME_MAIN_FLAG);
+        command.arg(&fork_server_path);
+
+        Some(fork_server_path)
+    } else {
+        None
+    };
+
+    // Libraries must come after the input file.
+    if let Some(staticlib) = &staticlib {
+        command.args(staticlib.linker_flags(target));
+    }
+
+    // The variables reserved by the toolchains are given to the
+    // compiler only if they are explicitly defined for it.
+    let compiler_env = CompilerEnv::from_variables(&variables)?;
+    command.envs(variables.iter().filter(|(name, _)| {
+        !directive::is_special(name)
+            && !is_reserved_by_toolchains(name)
+            && compiler_env.inherits(name)
+    }));
+    command.envs(compile_variables);
+
+    // The custom directives come last, so that they can change
+    // anything, e.g. add libraries after the source file.
+    let mut custom_program_command = Command::new(&output_path);
+
+    for (name, handler, value) in &custom_directives {
+        handler
+            .handle(
+                value,
+                &mut DirectiveContext::new(&variables, &mut command, &mut custom_program_command),
+            )
+            .map_err(|error| format!("The `{}` directive has failed: {}", name, error))?;
+    }
+
+    let mut sources = vec![input_path.clone()];
+    sources.extend(entry_path);
+    sources.extend(callbacks_path);
+    sources.extend(fork_server_path);
+    let mut objects = Vec::new();
+    if msvc {
+        let mut intermediate_path = output_path.clone();
+        intermediate_path.set_extension("obj");
+        objects.push(intermediate_path);
+    }
+
+    // The provenance is written next to the output, before the
+    // compilation, so that it is there if the compilation fails.
+    let provenance = Provenance {
+        compiler: toolchain::detect_once(&compiler),
+        flags: command
+            .get_args()
+            .map(|argument| argument.to_string_lossy().into_owned())
+            .collect(),
+        host: host.clone(),
+        target: target.clone(),
+    };
+    let mut provenance_path = output_path.clone();
+    provenance_path.set_extension("provenance.json");
+    provenance.write(&provenance_path)?;
+
+    let artifacts =
+        ArtifactSet::new(sources, objects, output_path.clone()).with_provenance(provenance_path);
+    let mut files_to_remove = artifacts.iter().map(Path::to_path_buf).collect::<Vec<_>>();
+    files_to_remove.extend(version_script_path);
+
+    if verbose {
+        eprintln!("inline-c: compile {}", redaction.command(&command));
+    }
+
+    observer::notify(Event::CompileStarted { command: &command });
+
+    let compile_timeout = variables
+        .get("compile_timeout")
+        .map(|timeout| match timeout.trim().parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                Ok(Duration::from_secs_f64(seconds))
+            }
+            _ => Err(format!(
+                "Invalid `compile_timeout` value `{}`, expected a positive number of seconds",
+                timeout
+            )),
+        })
+        .transpose()?;
+
+    let compile_retries = variables
+        .get("compile_retries")
+        .map(|retries| {
+            retries.trim().parse::<u32>().map_err(|_| {
+                format!(
+                    "Invalid `compile_retries` value `{}`, expected a number of retries",
+                    retries
+                )
+            })
+        })
+        .transpose()?
+        .unwrap_or(0);
+
+    let compile_start = Instant::now();
+    let mut attempt = 0;
+    let clang_output = loop {
+        let output = compiler_output(&mut command, compile_timeout)?;
+
+        if output.status.success() || attempt >= compile_retries || !is_transient_failure(&output) {
+            break output;
+        }
+
+        thread::sleep(Duration::from_millis(500) * 2u32.pow(attempt.min(6)));
+        attempt += 1;
+    };
+
+    observer::notify(Event::CompileFinished {
+        output: &clang_output,
+        duration: compile_start.elapsed(),
+    });
+
+    footprint::record_program(artifacts.iter());
+
+    let baseline = Baseline::from_variables(&variables)?;
+    let reporter = variables.get("report").map(|path| {
+        Reporter::new(
+            path,
+            language.to_string(),
+            program_key,
+            compile_start.elapsed(),
+        )
+    });
+
+    if !clang_output.status.success() {
+        if let Some(reporter) = &reporter {
+            reporter.record(None, Outcome::CompilationFailed);
+        }
+
+        return Ok(Assert::new(command, Some(files_to_remove))
+            .with_diagnostics(Diagnostics::parse(&clang_output))
+            .with_provenance(provenance)
+            .with_compiler_output(clang_output)
+            .with_artifacts(artifacts)
+            .with_build_dir(build_dir));
+    }
+
+    let diagnostics = Diagnostics::parse(&clang_output);
+
+    // The headers are listed in a preprocessing pass, with the same
+    // flags and environment as the compilation.
+    let included_headers = if is_enabled(&variables, "record_headers") {
+        let mut headers_command =
+            compiler_command(&compiler, &language, &variables, target, &host, None);
+        headers_command.args(&flags).args(&standard_flag);
+
+        for (name, value) in command.get_envs() {
+            match value {
+                Some(value) => headers_command.env(name, value),
+                None => headers_command.env_remove(name),
+            };
+        }
+
+        Some(headers::included_headers(
+            headers_command,
+            &input_path,
+            msvc,
+        )?)
+    } else {
+        None
+    };
+
+    // Variables that are given to the program only, not to the
+    // compiler. The variables reserved by the toolchains are given to
+    // the program only if they are explicitly defined for it.
+    let mut program_variables = variables;
+    program_variables.retain(|name, _| !is_reserved_by_toolchains(name));
+    program_variables.extend(run_variables);
+
+    for (name, value) in custom_program_command.get_envs() {
+        let name = name.to_string_lossy().into_owned();
+
+        match value {
+            Some(value) => program_variables.insert(name, value.to_string_lossy().into_owned()),
+            None => program_variables.remove(&name),
+        };
+    }
+
+    let custom_program_args = custom_program_command
+        .get_args()
+        .map(OsStr::to_os_string)
+        .collect::<Vec<_>>();
+
+    if let Some(custom_working_dir) = custom_program_command.get_current_dir() {
+        working_dir = Some(custom_working_dir.to_path_buf());
+    }
+
+    if let Some(sanitizer_logs) = &sanitizer_logs {
+        let runtime_options = sanitizers.runtime_options(sanitizer_logs.path(), &program_variables);
+        program_variables.extend(runtime_options);
+    }
+
+    if let Some(preload) = preload {
+        add_preload(&mut program_variables, &preload)?;
+    }
+
+    if let Some(timezone) = timezone {
+        program_variables.insert("TZ".to_string(), timezone);
+    }
+
+    if let Some(locale) = locale {
+        program_variables.insert("LC_ALL".to_string(), locale);
+    }
+
+    if let Some(faketime) = faketime {
+        let library = faketime_library(&program_variables)?;
+
+        add_preload(&mut program_variables, &library)?;
+        program_variables.insert("FAKETIME".to_string(), faketime);
+    }
+
+    // Programs the compiled program is run through, e.g. `unshare`,
+    // with their arguments.
+    let mut launcher: Vec<OsString> = Vec::new();
+
+    match network.as_deref() {
+        None | Some("host") => (),
+        Some("none") => {
+            if !network_isolation_is_available() {
+                return Err("`network: \"none\"` requires Linux with unprivileged user \
+                     namespaces, and the `unshare` command"
+                    .into());
+            }
+
+            launcher.extend(
+                ["unshare", "--map-root-user", "--net", "--"]
+                    .iter()
+                    .map(OsString::from),
+            );
+        }
+        Some(network) => {
+            return Err(format!(
+                "Invalid `network` value `{}`, expected `host` or `none`",
+                network
+            )
+            .into())
+        }
+    }
+
+    launcher.extend(scheduling.launcher());
+
+    if let Some(umask) = umask {
+        if !cfg!(unix) {
+            return Err("The `umask` directive is only supported on Unix".into());
+        }
+
+        launcher.extend([
+            OsString::from("sh"),
+            OsString::from("-c"),
+            OsString::from(format!("umask {} && exec \"$0\" \"$@\"", umask)),
+        ]);
+    }
+
+    if let (Some(stack_size), true) = (stack_size, cfg!(unix)) {
+        // `ulimit -s` expects kibibytes.
+        launcher.extend([
+            OsString::from("sh"),
+            OsString::from("-c"),
+            OsString::from(format!(
+                "ulimit -s {} && exec \"$0\" \"$@\"",
+                stack_size.div_ceil(1024)
+            )),
+        ]);
+    }
+
+    let heap_profile = program_variables
+        .get("heap_profiler")
+        .map(|profiler| HeapProfile::new(profiler, &output_path))
+        .transpose()?;
+
+    if let Some(heap_profile) = &heap_profile {
+        launcher.extend(heap_profile.launcher());
+        files_to_remove.extend(heap_profile.files());
+    }
+
+    let trace = program_variables
+        .get("tracer")
+        .map(|tracer| Trace::new(tracer, &output_path))
+        .transpose()?;
+
+    if let Some(trace) = &trace {
+        launcher.extend(trace.launcher());
+        files_to_remove.push(trace.path().to_path_buf());
+    }
+
+    // The program writes its standard output and its standard error
+    // into the same pipe, so their relative order is kept. It comes
+    // last, so that the output of the other launchers is not merged.
+    if is_enabled(&program_variables, "merge_stderr") {
+        if !cfg!(unix) {
+            return Err("The special `merge_stderr` directive is only supported on Unix".into());
+        }
+
+        launcher.extend([
+            OsString::from("sh"),
+            OsString::from("-c"),
+            OsString::from("exec \"$0\" \"$@\" 2>&1"),
+        ]);
+    }
+
+    // The runner of the program, e.g. an emulator, runs the program
+    // directly.
+    if let Some(runner) = program_variables.get("runner") {
+        launcher.extend(split_words(runner)?.into_iter().map(OsString::from));
+    }
+
+    // The special directives have been consumed.
+    program_variables.retain(|name, _| !directive::is_special(name));
+
+    if in_process {
+        if !launcher.is_empty() {
+            return Err(
+                "Running the program through another program (e.g. with `network`, \
+                 `stack_size`, `umask`, `nice`, `heap_profiler`, `tracer` or `merge_stderr`) \
+                 is not supported with programs run in-process"
+                    .into(),
+            );
+        }
+
+        if !custom_program_args.is_empty() || working_dir.is_some() {
+            return Err(
+                "The arguments and the working directory of the program, set by a \
+                 custom directive, are not supported in-process"
+                    .into(),
+            );
+        }
+
+        let entry_point = entry_point.unwrap_or_else(|| String::from(inproc::ENTRY_POINT));
+        let shared_object = SharedObject::new(
+            output_path.clone(),
+            &entry_point,
+            callbacks,
+            program_variables,
+        );
+
+        // The command is never spawned: the shared object is run
+        // in-process.
+        return Ok(
+            Assert::new(Command::new(&output_path), Some(files_to_remove))
+                .with_source(source)
+                .with_artifact_path(output_path)
+                .with_artifacts(artifacts)
+                .with_diagnostics(diagnostics)
+                .with_provenance(provenance)
+                .with_shared_object(shared_object)
+                .with_stdin(stdin)
+                .with_included_headers(included_headers)
+                .with_verbose(verbose)
+                .with_build_dir(build_dir)
+                .with_stderr_expectation(stderr_expectation)
+                .with_reporter(reporter),
+        );
+    }
+
+    if fork_server && !launcher.is_empty() {
+        return Err(
+            "Running the program through another program (e.g. with `network`, \
+             `stack_size`, `umask`, `nice`, `heap_profiler`, `tracer` or `merge_stderr`) is \
+             not supported with the fork server"
+                .into(),
+        );
+    }
+
+    let artifact_path = output_path.clone();
+    let program_command = move || {
+        let mut command = match launcher.split_first() {
+            Some((launcher, arguments)) => {
+                let mut command = Command::new(launcher);
+                command.args(arguments).arg(&output_path);
+
+                command
+            }
+            None => Command::new(&output_path),
+        };
+        command.args(&custom_program_args);
+        command.envs(&program_variables);
+
+        if let Some(working_dir) = &working_dir {
+            command.current_dir(working_dir);
+        }
+
+        command
+    };
+
+    let mut assert = Assert::new(program_command(), Some(files_to_remove))
+        .with_source(source)
+        .with_program_command(program_command)
+        .with_artifact_path(artifact_path)
+        .with_artifacts(artifacts)
+        .with_diagnostics(diagnostics)
+        .with_provenance(provenance)
+        .with_build_dir(build_dir)
+        .with_backtrace(backtrace)
+        .with_debugger(debugger)
+        .with_heap_profile(heap_profile)
+        .with_trace(trace)
+        .with_sanitizers(sanitizers)
+        .with_sanitizer_logs(sanitizer_logs)
+        .with_stderr_expectation(stderr_expectation)
+        .with_reporter(reporter)
+        .with_baseline(baseline)
+        .with_fork_server(fork_server)
+        .with_pty(pty)
+        .with_tee(tee)
+        .with_stdin(stdin)
+        .with_redaction(redaction)
+        .with_included_headers(included_headers)
+        .with_verbose(verbose);
+
+    if let Some(journal) = journal {
+        assert = assert.with_journal(journal);
+    }
+
+    Ok(assert)
+}
+
+/// Preload the `preload` library into the program, by updating the
+/// appropriate environment variable. A relative path is resolved
+/// against the current directory; a bare library name is left to the
+/// dynamic loader.
+fn add_preload(
+    variables: &mut HashMap<String, String>,
+    preload: &str,
+) -> Result<(), Box<dyn Error>> {
+    let name = if cfg!(target_os = "windows") {
+        return Err(
+            "`preload` is not supported on Windows, there is no equivalent to \
+             `LD_PRELOAD`"
+                .into(),
+        );
+    } else if cfg!(target_os = "macos") {
+        "DYLD_INSERT_LIBRARIES"
+    } else {
+        "LD_PRELOAD"
+    };
+
+    let path = Path::new(preload);
+    let value = if path.is_relative() && path.components().count() > 1 {
+        env::current_dir()?
+            .join(path)
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        preload.to_string()
+    };
+    let value = match variables.get(name) {
+        Some(existing) => format!("{}:{}", existing, value),
+        None => value,
+    };
+
+    variables.insert(name.to_string(), value);
+
+    if cfg!(target_os = "macos") {
+        variables.insert("DYLD_FORCE_FLAT_NAMESPACE".to_string(), "1".to_string());
+    }
+
+    Ok(())
+}
+
+/// Find the `libfaketime` library, either from the special
+/// `faketime_library` directive, or from the usual installation paths.
+fn faketime_library(variables: &HashMap<String, String>) -> Result<String, Box<dyn Error>> {
+    const CANDIDATES: &[&str] = &[
+        "/usr/lib/x86_64-linux-gnu/faketime/libfaketime.so.1",
+        "/usr/lib/aarch64-linux-gnu/faketime/libfaketime.so.1",
+        "/usr/lib64/faketime/libfaketime.so.1",
+        "/usr/lib/faketime/libfaketime.so.1",
+        "/usr/local/lib/faketime/libfaketime.so.1",
+        "/opt/homebrew/lib/faketime/libfaketime.1.dylib",
+        "/usr/local/lib/faketime/libfaketime.1.dylib",
+    ];
+
+    if let Some(library) = variables.get("faketime_library") {
+        return Ok(library.clone());
+    }
+
+    CANDIDATES
+        .iter()
+        .find(|candidate| Path::new(candidate).exists())
+        .map(|candidate| candidate.to_string())
+        .ok_or_else(|| {
+            "`faketime` requires `libfaketime`, which has not been found; \
+             install it, or set its path with the special `faketime_library` directive"
+                .into()
+        })
+}
+
+/// Whether the program uses threads, i.e. includes a threading
+/// header, in which case it must be compiled with `-pthread`.
+fn uses_threads(program: &str) -> bool {
+    lazy_static! {
+        static ref REGEX: Regex =
+            Regex::new(r#"#\s*include\s*[<"](pthread\.h|threads\.h|thread)[>"]"#).unwrap();
+    }
+
+    REGEX.is_match(program)
+}
+
+/// The prefix of the name of the executable, so that a running
+/// program can be told apart in `ps` or in a debugger: the name of the
+/// test, i.e. of its thread, then the hash of the program, e.g.
+/// `test_foo-84f7e2a1-`. `comm` keeps the first 15 bytes only, so the
+/// test comes first.
+fn program_name(thread_name: Option<&str>, program_key: u64) -> String {
+    const MAX_TEST_NAME_LENGTH: usize = 32;
+
+    let test_name = thread_name
+        .filter(|name| *name != "main")
+        .and_then(|name| name.rsplit("::").next())
+        .map(|name| {
+            name.chars()
+                .map(|character| {
+                    if character.is_ascii_alphanumeric() || character == '-' {
+                        character
+                    } else {
+                        '_'
+                    }
+                })
+                .take(MAX_TEST_NAME_LENGTH)
+                .collect::<String>()
+        })
+        .unwrap_or_else(|| String::from("inline-c-rs"));
+
+    format!("{}-{:08x}-", test_name, program_key as u32)
+}
+
+/// The link flags of the well-known libraries that the program
+/// includes, e.g. `-lm` for `math.h`. With MSVC, the variadic
+/// functions of `stdarg.h` need the definitions of the `printf`
+/// family for the C libraries built before Visual Studio 2015.
+fn auto_link_flags(program: &str, msvc: bool) -> Vec<&'static str> {
+    const MSVC_LIBRARIES: &[(&str, &str)] = &[
+        ("stdarg.h", "legacy_stdio_definitions.lib"),
+        ("cstdarg", "legacy_stdio_definitions.lib"),
+    ];
+    const LIBRARIES: &[(&str, &str)] = &[
+        ("math.h", "-lm"),
+        ("complex.h", "-lm"),
+        ("tgmath.h", "-lm"),
+        ("cmath", "-lm"),
+        ("dlfcn.h", "-ldl"),
+        ("aio.h", "-lrt"),
+        ("mqueue.h", "-lrt"),
+        ("semaphore.h", "-pthread"),
+    ];
+
+    lazy_static! {
+        static ref REGEX: Regex =
+            Regex::new(r#"#\s*include\s*[<"](?P<header>[^>"]+)[>"]"#).unwrap();
+    }
+
+    let libraries = if msvc { MSVC_LIBRARIES } else { LIBRARIES };
+    let mut flags = Vec::new();
+
+    for captures in REGEX.captures_iter(program) {
+        for (header, flag) in libraries {
+            if captures["header"] == **header && !flags.contains(flag) {
+                flags.push(*flag);
+            }
+        }
+    }
+
+    flags
+}
+
+/// Run the compiler, and wait for its output, for `timeout` at most.
+/// The standard input is closed, so that a compiler waiting for it
+/// does not hang.
+fn compiler_output(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> Result<Output, Box<dyn Error>> {
+    command.stdin(Stdio::null());
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(command.output()?),
+    };
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut child_stdout = child.stdout.take().expect("The compiler has no stdout");
+    let stdout_thread = thread::spawn(move || {
+        let mut stdout = Vec::new();
+        child_stdout.read_to_end(&mut stdout).map(|_| stdout)
+    });
+    let mut child_stderr = child.stderr.take().expect("The compiler has no stderr");
+    let stderr_thread = thread::spawn(move || {
+        let mut stderr = Vec::new();
+        child_stderr.read_to_end(&mut stderr).map(|_| stderr)
+    });
+
+    let deadline = Instant::now() + timeout;
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            return Err(format!(
+                "The compiler `{}` did not finish within {} seconds (see `compile_timeout`)",
+                command.get_program().to_string_lossy(),
+                timeout.as_secs_f64()
+            )
+            .into());
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_thread
+            .join()
+            .expect("The stdout reader has panicked")?,
+        stderr: stderr_thread
+            .join()
+            .expect("The stderr reader has panicked")?,
+    })
+}
+
+/// Whether the compiler has failed because of the machine rather
+/// than the program, e.g. the PDB server of MSVC on busy machines, so
+/// that it is worth retrying.
+fn is_transient_failure(output: &Output) -> bool {
+    lazy_static! {
+        static ref TRANSIENT: Regex = Regex::new(
+            r"(?x)
+                \b(C1090|C1041|C1033|C1902|LNK1201)\b   # MSVC program database
+                | Text\ file\ busy
+                | Resource\ temporarily\ unavailable
+                | Cannot\ allocate\ memory
+            "
+        )
+        .unwrap();
+    }
+
+    TRANSIENT.is_match(&String::from_utf8_lossy(&output.stdout))
+        || TRANSIENT.is_match(&String::from_utf8_lossy(&output.stderr))
+}
+
+/// Parse a size in bytes, with an optional `K`, `M` or `G` suffix
+/// (powers of 1024).
+fn parse_size(size: &str) -> Result<u64, Box<dyn Error>> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((index, 'k')) | Some((index, 'K')) => (&size[..index], 1 << 10),
+        Some((index, 'm')) | Some((index, 'M')) => (&size[..index], 1 << 20),
+        Some((index, 'g')) | Some((index, 'G')) => (&size[..index], 1 << 30),
+        _ => (size, 1),
+    };
+
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid size `{}`", size).into())
+}
+
+/// Split `value` into words like a shell: the words are separated by
+/// whitespace, unless it is quoted, e.g. `'/opt/my tools/cc' -O2`. A
+/// backslash escapes a whitespace, a quote or a backslash only, so
+/// that the Windows paths are kept.
+fn split_words(value: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut characters = value.chars();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '\'' | '"' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match characters.next() {
+                        Some(next) if next == character => break,
+                        Some('\\') if character == '"' => match characters.next() {
+                            Some(escaped @ ('"' | '\\')) => word.push(escaped),
+                            Some(other) => {
+                                word.push('\\');
+                                word.push(other);
+                            }
+                            None => word.push('\\'),
+                        },
+                        Some(next) => word.push(next),
+                        None => return Err(format!("Unterminated quote in `{}`", value)),
+                    }
+                }
+            }
+
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+
+                match characters.clone().next() {
+                    Some(escaped) if escaped.is_whitespace() || "'\"\\".contains(escaped) => {
+                        word.push(escaped);
+                        characters.next();
+                    }
+                    _ => word.push('\\'),
+                }
+            }
+
+            character if character.is_whitespace() => words.extend(word.take()),
+
+            character => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+
+    words.extend(word);
+
+    Ok(words)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::predicates::*;
+/// Whether the program can be run in its own network namespace,
+/// i.e. whether `unshare` works with unprivileged user namespaces.
+fn network_isolation_is_available() -> bool {
+    lazy_static! {
+        static ref AVAILABLE: bool = cfg!(target_os = "linux")
+            && Command::new("unshare")
+                .args(["--map-root-user", "--net", "true"])
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false);
+    }
+
+    *AVAILABLE
+}
+
+/// `path` resolved against the manifest directory of the crate.
+fn manifest_path(path: &str, location: Option<&Location>) -> PathBuf {
+    location
+        .map(|location| PathBuf::from(location.manifest_dir))
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from))
+        .unwrap_or_default()
+        .join(path)
+}
+
+/// The working directory of the program, from the special `cwd`
+/// directive.
+fn working_dir(cwd: &str, location: Option<&Location>) -> Result<PathBuf, Box<dyn Error>> {
+    let working_dir = manifest_path(cwd, location);
+
+    if !working_dir.is_dir() {
+        return Err(format!(
+            "The working directory `{}` of the program does not exist",
+            working_dir.display()
+        )
+        .into());
+    }
+
+    Ok(working_dir)
+}
+
+/// The include directories of the crate: `include/` in the manifest
+/// directory, and `OUT_DIR` where build scripts generate headers.
+/// Missing directories are ignored.
+fn auto_include_dirs(location: Option<&Location>) -> Vec<PathBuf> {
+    let manifest_dir = location
+        .map(|location| PathBuf::from(location.manifest_dir))
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from));
+
+    manifest_dir
+        .map(|manifest_dir| manifest_dir.join("include"))
+        .into_iter()
+        .chain(out_dir(location))
+        .filter(|include_dir| include_dir.is_dir())
+        .collect()
+}
+
+/// The directory where the build script of the crate generates its
+/// files.
+fn out_dir(location: Option<&Location>) -> Option<PathBuf> {
+    location
+        .and_then(|location| location.out_dir.map(PathBuf::from))
+        .or_else(|| env::var_os("OUT_DIR").map(PathBuf::from))
+}
+
+/// The profile the program is compiled with, e.g. consistent with the
+/// Rust artifacts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Profile {
+    opt_level: u32,
+    debug_info: bool,
+}
+
+impl Profile {
+    const DEBUG: Self = Self {
+        opt_level: 0,
+        debug_info: true,
+    };
+    const RELEASE: Self = Self {
+        opt_level: 2,
+        debug_info: false,
+    };
+
+    /// The profile set with the special `profile` directive. With
+    /// `cargo`, it is the one of Cargo when run from a build script
+    /// (with `PROFILE` and `DEBUG`), or else the one of the Rust crate
+    /// of `location`. Without the directive, the default flags of
+    /// `cc-rs` are kept.
+    fn of(
+        variables: &HashMap<String, String>,
+        location: Option<&Location>,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        match variables.get("profile").map(String::as_str) {
+            None => return Ok(None),
+            Some("debug") => return Ok(Some(Self::DEBUG)),
+            Some("release") => return Ok(Some(Self::RELEASE)),
+            Some("cargo") => (),
+            Some(profile) => {
+                return Err(format!(
+                    "Invalid `profile` value `{}`, expected `debug`, `release` or `cargo`",
+                    profile
+                )
+                .into())
+            }
+        }
+
+        let cargo_profile = match env::var("PROFILE").as_deref() {
+            Ok("debug") => Some(Self::DEBUG),
+            Ok("release") => Some(Self::RELEASE),
+            _ => None,
+        };
+
+        if let Some(mut profile) = cargo_profile {
+            if let Ok(debug) = env::var("DEBUG") {
+                profile.debug_info = !matches!(debug.as_str(), "false" | "0" | "none");
+            }
+
+            return Ok(Some(profile));
+        }
+
+        Ok(location.map(|location| {
+            if location.debug_assertions {
+                Self::DEBUG
+            } else {
+                Self::RELEASE
+            }
+        }))
+    }
+}
+
+/// Whether the variable `name` is used by the toolchains, e.g.
+/// `INCLUDE` and `LIB` by MSVC. The names are case-insensitive on
+/// Windows.
+fn is_reserved_by_toolchains(name: &str) -> bool {
+    const RESERVED: &[&str] = &["PATH", "INCLUDE", "LIB", "LIBPATH", "TMP", "TEMP", "TMPDIR"];
+
+    RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+/// The variables inherited by the compiler, besides the ones defined
+/// for it with `#inline_c_rs_compile`, from the special
+/// `compiler_env` directive.
+#[derive(Debug, PartialEq)]
+enum CompilerEnv {
+    /// All the variables but the flags, which are already given as
+    /// arguments, so that a compiler wrapper reading them does not
+    /// apply them twice.
+    WithoutFlags,
+    /// `all`, all the variables.
+    All,
+    /// `none`, no variable.
+    None,
+    /// A comma-separated list of names.
+    Only(Vec<String>),
+}
+
+impl CompilerEnv {
+    const FLAGS: &'static [&'static str] = &["CFLAGS", "CPPFLAGS", "CXXFLAGS", "LDFLAGS"];
+
+    fn from_variables(variables: &HashMap<String, String>) -> Result<Self, String> {
+        Ok(
+            match variables.get("compiler_env").map(|value| value.trim()) {
+                None | Some("") => Self::WithoutFlags,
+                Some("all") => Self::All,
+                Some("none") => Self::None,
+                Some(names) => {
+                    let names = names
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .collect::<Vec<_>>();
+
+                    if let Some(name) = names.iter().find(|name| !is_identifier(name)) {
+                        return Err(format!(
+                            "Invalid `compiler_env` value, `{}` is not a variable name, expected \
+                         `all`, `none` or a comma-separated list of names",
+                            name
+                        ));
+                    }
+
+                    Self::Only(names)
+                }
+            },
+        )
+    }
+
+    fn inherits(&self, name: &str) -> bool {
+        match self {
+            Self::WithoutFlags => !Self::FLAGS.contains(&name),
+            Self::All => true,
+            Self::None => false,
+            Self::Only(names) => names.iter().any(|inherited| inherited == name),
+        }
+    }
+}
+
+/// Whether the boolean special directive `name` is set to a truthy
+/// value (`1`, `true`, `yes` or `on`).
+fn is_enabled(variables: &HashMap<String, String>, name: &str) -> bool {
+    matches!(
+        variables
+            .get(name)
+            .map(|value| value.to_ascii_lowercase())
+            .as_deref(),
+        Some("1") | Some("true") | Some("yes") | Some("on")
+    )
+}
+
+/// The program without its `#inline_c_rs` directives, and the
+/// variables they define.
+struct Environment<'p> {
+    program: Cow<'p, str>,
+    /// Macros of the `#inline_c_rs define` directives.
+    defines: Vec<(String, Option<String>)>,
+    /// Variables of both the compiler and the program. They also
+    /// configure `inline-c`, e.g. `CFLAGS`.
+    variables: HashMap<String, String>,
+    /// Variables of the compiler only, from the
+    /// `#inline_c_rs_compile` directives.
+    compile_env: HashMap<String, String>,
+    /// Variables of the program only, from the `#inline_c_rs_run`
+    /// directives.
+    run_env: HashMap<String, String>,
+    /// Names of the variables whose values are read from the
+    /// environment with `env("…")`, which are redacted from the
+    /// failure output.
+    secrets: Vec<String>,
+}
+
+/// The prefix of the meta environment variables, e.g.
+/// `INLINE_C_RS_CFLAGS`.
+const ENV_VAR_PREFIX: &str = "INLINE_C_RS_";
+
+fn collect_environment_variables<'p>(
+    program: &'p str,
+    module_path: &str,
+) -> Result<Environment<'p>, Box<dyn Error>> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(
+            r#"#inline_c_rs(_(?P<step>compile|run))? (?P<variable_name>[^:]+):\s*(?:"(?P<variable_value>[^"]+)"|env\s*\(\s*"(?P<env_name>[^"]+)"\s*\))[ \t]*(?:\r?\n|$)"#
+        )
+        .unwrap();
+    }
+
+    let meta_variables = env::vars()
+        .filter_map(|(mut name, value)| {
+            if name.starts_with(ENV_VAR_PREFIX) {
+                Some((name.split_off(ENV_VAR_PREFIX.len()), value))
+            } else {
+                None
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    // The scoped variables, e.g. `FOO__X`, are not variables themselves.
+    let mut variables = meta_variables
+        .iter()
+        .filter(|(name, _)| !name.contains("__"))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<HashMap<_, _>>();
+
+    // Then, the variables scoped to the module, from the outermost
+    // to the innermost module, e.g. `FOO__X`, then `FOO__BAR__X`
+    // for the `foo::bar` module.
+    for scope in module_scopes(module_path) {
+        let scope_prefix = format!("{}__", scope);
+
+        for (name, value) in &meta_variables {
+            // E.g. `FOO__BAR__X` is not `BAR__X` in the `foo` module.
+            if let Some(variable_name) = name
+                .strip_prefix(&scope_prefix)
+                .filter(|variable_name| !variable_name.contains("__"))
+            {
+                variables.insert(variable_name.to_string(), value.clone());
+            }
+        }
+    }
+
+    // The directives are parsed strictly: a line starting with
+    // `#inline_c_rs` must be a whole directive with a known name.
+    let mut directives = Vec::new();
+
+    for (index, line) in program.lines().enumerate() {
+        let directive = line.trim();
+
+        if !directive.starts_with("#inline_c_rs") {
+            continue;
+        }
+
+        let error = move |reason: String| DirectiveError {
+            line: index + 1,
+            directive: directive.to_string(),
+            reason,
+        };
+
+        let captures = REGEX
+            .captures(directive)
+            .filter(|captures| captures[0].len() == directive.len())
+            .ok_or_else(|| {
+                error(String::from(
+                    "expected `#inline_c_rs NAME: \"value\"`, `#inline_c_rs NAME: env(\"NAME\")`, \
+                     or with `_compile` or `_run`, e.g. `#inline_c_rs_run NAME: \"value\"`",
+                ))
+            })?;
+
+        directives.push((index + 1, error, captures));
+    }
+
+    let allowed_directives = directives
+        .iter()
+        .filter(|(_, _, captures)| {
+            captures.name("step").is_none()
+                && captures["variable_name"].trim() == "allowed_directives"
+        })
+        .filter_map(|(_, _, captures)| captures.name("variable_value"))
+        .map(|value| value.as_str())
+        .chain(variables.get("allowed_directives").map(String::as_str))
+        .flat_map(|allowed| allowed.split(',').map(str::trim))
+        .collect::<Vec<_>>();
+
+    for (line, error, captures) in &directives {
+        let variable_name = captures["variable_name"].trim();
+
+        directive::check_name(variable_name, captures.name("step").is_some()).map_err(error)?;
+
+        // A typo is still a valid variable, given to the program.
+        if let Some(special) = directive::near_directive(variable_name, &allowed_directives) {
+            eprintln!(
+                "inline-c: warning: `{}` at line {} is not a special directive, did you mean \
+                 `{}`? It is given as a variable; add it to `allowed_directives` to silence \
+                 this warning",
+                variable_name, line, special
+            );
+        }
+    }
+
+    let mut defines = Vec::new();
+    let mut compile_env = HashMap::new();
+    let mut run_env = HashMap::new();
+    let mut secrets = Vec::new();
+
+    for captures in REGEX.captures_iter(program) {
+        let variable_name = captures["variable_name"].trim();
+
+        // The value is read from the environment at run time, so that
+        // it is not written in the source, e.g. a token.
+        let value = match captures.name("env_name") {
+            Some(env_name) => {
+                if variable_name.starts_with("define ") {
+                    return Err(format!(
+                        "`env(\"{}\")` is not supported by `define`, the value would be \
+                         compiled in the program",
+                        env_name.as_str()
+                    )
+                    .into());
+                }
+
+                secrets.push(variable_name.to_string());
+
+                env::var(env_name.as_str()).map_err(|_| {
+                    format!(
+                        "The environment variable `{}` of `{}` is not defined",
+                        env_name.as_str(),
+                        variable_name
+                    )
+                })?
+            }
+            None => expand_variables(&captures["variable_value"])?,
+        };
+
+        if let Some(name) = variable_name.strip_prefix("define ") {
+            defines.push((name.trim().to_string(), Some(value)));
+
+            continue;
+        }
+
+        let step_variables = match captures.name("step").map(|step| step.as_str()) {
+            Some("compile") => &mut compile_env,
+            Some(_) => &mut run_env,
+            None => &mut variables,
+        };
+
+        step_variables.insert(variable_name.to_string(), value);
+    }
+
+    let program = REGEX.replace_all(program, "");
+
+    Ok(Environment {
+        program,
+        defines,
+        variables,
+        compile_env,
+        run_env,
+        secrets,
+    })
+}
+
+/// Expand the `${NAME}` occurrences in a directive value with the
+/// environment variables of the process, e.g.
+/// `${CARGO_MANIFEST_DIR}/tests/data`. `$${` is a literal `${`.
+fn expand_variables(value: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+
+            continue;
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated `${{` in `{}`", value))?;
+        let name = &rest[start + 2..start + end];
+        let variable = env::var(name).map_err(|_| {
+            format!(
+                "The environment variable `{}` used in `{}` is not defined",
+                name, value
+            )
+        })?;
+
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&variable);
+
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// The scopes of the meta environment variables for `module_path`,
+/// e.g. `FOO` and `FOO__BAR` for `foo::bar`.
+fn module_scopes(module_path: &str) -> Vec<String> {
+    let mut scopes = Vec::new();
+    let mut scope = String::new();
+
+    for segment in module_path
+        .split("::")
+        .filter(|segment| !segment.is_empty())
+    {
+        if !scope.is_empty() {
+            scope.push_str("__");
+        }
+
+        scope.push_str(&segment.to_uppercase());
+        scopes.push(scope.clone());
+    }
+
+    scopes
+}
+
+// This is copy-pasted and edited from `cc-rs`.
+fn command_add_output_file(command: &mut Command, output_path: &PathBuf, msvc: bool, clang: bool) {
+    if msvc && !clang {
+        let mut intermediate_path = output_path.clone();
+        intermediate_path.set_extension("obj");
+
+        let mut fo_arg = OsString::from("-Fo");
+        fo_arg.push(intermediate_path);
+        command.arg(fo_arg);
+
+        let mut fe_arg = OsString::from("-Fe");
+        fe_arg.push(output_path);
+        command.arg(fe_arg);
+    } else {
+        command.arg("-o").arg(output_path);
+    }
+}
+
+/// The command of the compiler, with its flags, see
+/// [`compiler_flags`]. The input comes first if it is given.
+fn compiler_command(
+    compiler: &cc::Tool,
+    language: &Language,
+    variables: &HashMap<String, String>,
+    target: &str,
+    host: &str,
+    input_path: Option<&Path>,
+) -> Command {
+    let mut command = Command::new(compiler.path());
+    command.envs(compiler.get_envs());
+    command.args(input_path);
+    command.args(compiler_flags(
+        compiler.args(),
+        compiler.is_like_msvc(),
+        language,
+        variables,
+        target,
+        host,
+        |name| env::var(name).ok(),
+    ));
+
+    command
+}
+
+/// The flags of the compiler, collected in one place for all the
+/// toolchains: the flags of `cc-rs`, then the toolchain flags,
+/// `CPPFLAGS`, `CFLAGS` or `CXXFLAGS`, and `LDFLAGS`.
+///
+/// `cc-rs` appends `CFLAGS` or `CXXFLAGS` from the environment (`getenv`)
+/// to its own flags: they are removed, so that each flag is given
+/// once, and that the variables override the environment the same way
+/// on all the toolchains.
+fn compiler_flags<E>(
+    compiler_args: &[OsString],
+    msvc: bool,
+    language: &Language,
+    variables: &HashMap<String, String>,
+    target: &str,
+    host: &str,
+    getenv: E,
+) -> Vec<OsString>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    // The POSIX paths are translated automatically in MSYS2, unless
+    // `translate_paths` says otherwise. MSVC is not a MinGW compiler,
+    // even in MSYS2 (e.g. Git Bash), and its flags start with `/`.
+    let translate_paths = !msvc
+        && if variables.contains_key("translate_paths") {
+            is_enabled(variables, "translate_paths")
+        } else {
+            msys::is_msys()
+        };
+    let translate = |flags: Vec<String>| {
+        if translate_paths {
+            msys::translate_paths(flags)
+        } else {
+            flags
+        }
+    };
+
+    // As read by `cc-rs`: `<name>`, then `HOST_<name>` or
+    // `TARGET_<name>`, then `<name>_<target>`.
+    let environment_flags = |name: &str| -> Option<Vec<String>> {
+        let kind = if target == host { "HOST" } else { "TARGET" };
+        let names = [
+            name.to_string(),
+            format!("{}_{}", kind, name),
+            format!("{}_{}", name, target.replace(['-', '.'], "_")),
+            format!("{}_{}", name, target),
+        ];
+        let mut flags = None;
+
+        for name in &names {
+            if let Some(value) = getenv(name) {
+                flags
+                    .get_or_insert_with(Vec::new)
+                    .extend(value.split_ascii_whitespace().map(str::to_string));
+            }
+        }
+
+        flags
+    };
+    let get_env_flags = |name: &str| -> Vec<String> {
+        let flags = match variables.get(name) {
+            Some(value) => value.split_ascii_whitespace().map(str::to_string).collect(),
+            None => environment_flags(name).unwrap_or_default(),
+        };
+
+        translate(flags)
+    };
+
+    // CUDA is an extension of C++.
+    let language_flags = match language {
+        Language::C => "CFLAGS",
+        _ => "CXXFLAGS",
+    };
+
+    let mut flags = compiler_args.to_vec();
+
+    if let Some(cc_flags) = environment_flags(language_flags) {
+        let cc_flags = cc_flags.into_iter().map(OsString::from).collect::<Vec<_>>();
+
+        if flags.ends_with(&cc_flags) {
+            flags.truncate(flags.len() - cc_flags.len());
+        }
+    }
+
+    // The toolchain of the compiler: its sysroot, the directories of
+    // its programs, and the GCC installation used by Clang. They are
+    // given to the compiler, which gives them to the linker.
+    let mut toolchain_flags = Vec::new();
+
+    if let Some(sysroot) = variables.get("sysroot") {
+        toolchain_flags.push(format!("--sysroot={}", sysroot));
+    }
+
+    if let Some(prefixes) = variables.get("toolchain_prefixes") {
+        toolchain_flags
+            .extend(env::split_paths(prefixes).map(|prefix| format!("-B{}", prefix.display())));
+    }
+
+    if let Some(gcc_toolchain) = variables.get("gcc_toolchain") {
+        toolchain_flags.push(format!("--gcc-toolchain={}", gcc_toolchain));
+    }
+
+    flags.extend(translate(toolchain_flags).into_iter().map(OsString::from));
+
+    // The preprocessor flags are for all the languages, the compiler
+    // flags for their language only.
+    flags.extend(get_env_flags("CPPFLAGS").into_iter().map(OsString::from));
+    flags.extend(
+        get_env_flags(language_flags)
+            .into_iter()
+            .map(OsString::from),
+    );
+
+    for linker_argument in get_env_flags("LDFLAGS") {
+        flags.push(format!("-Wl,{}", linker_argument).into());
+    }
+
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{predicates::*, Check};
+
+    /// The message of the panic of `f`, which must panic.
+    fn panic_message<F: FnOnce()>(f: F) -> String {
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .expect_err("It was expected to panic");
+
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload
+                .downcast::<&str>()
+                .map(|message| message.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn test_run_c() {
+        run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    printf("Hello, World!\n");
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout(predicate::eq("Hello, World!\n").normalize());
+    }
+
+    #[test]
+    fn test_run_cxx() {
+        run(
+            Language::Cxx,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    printf("Hello, World!\n");
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout(predicate::eq("Hello, World!\n").normalize());
+    }
+
+    #[test]
+    fn test_run_file_check() {
+        run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                // CHECK: Hello
+                // CHECK-NEXT: x = {{[0-9]+}}
+                int main() {
+                    printf("Hello, World!\n");
+                    printf("x = %d\n", 42);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .file_check()
+        .success();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_run_deterministic() {
+        let program = r#"
+            #inline_c_rs deterministic: "1"
+            #include <stdio.h>
+
+            int main() {
+                printf(__FILE__);
+
+                return 0;
+            }
+        "#;
+
+        let first = run(Language::C, program).unwrap();
+        let second = run(Language::C, program).unwrap();
+
+        let read_program =
+            |assert: &Assert| fs::read(assert.program_command().unwrap().get_program()).unwrap();
+
+        assert_eq!(read_program(&first), read_program(&second));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_preload() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs preload: "libm.so.6"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main() {
+                    printf("%s", getenv("LD_PRELOAD"));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("libm.so.6");
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_run_timezone_and_locale() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs timezone: "JST-9"
+                #inline_c_rs locale: "C"
+                #include <stdio.h>
+                #include <time.h>
+
+                int main() {
+                    time_t epoch = 0;
+                    char buffer[32];
+
+                    tzset();
+                    strftime(buffer, sizeof(buffer), "%Y-%m-%d %H:%M", localtime(&epoch));
+                    printf("%s", buffer);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("1970-01-01 09:00");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_network_isolation() {
+        if !network_isolation_is_available() {
+            return;
+        }
+
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs network: "none"
+                #include <arpa/inet.h>
+                #include <netinet/in.h>
+                #include <sys/socket.h>
+
+                int main() {
+                    int fd = socket(AF_INET, SOCK_STREAM, 0);
+                    struct sockaddr_in address = { 0 };
+
+                    address.sin_family = AF_INET;
+                    address.sin_port = htons(53);
+                    address.sin_addr.s_addr = inet_addr("1.1.1.1");
+
+                    return connect(fd, (struct sockaddr*) &address, sizeof(address)) == 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_module_scopes() {
+        assert!(module_scopes("").is_empty());
+        assert_eq!(module_scopes("foo::bar_baz"), ["FOO", "FOO__BAR_BAZ"]);
+    }
+
+    #[test]
+    fn test_module_scoped_variables() {
+        // The variables of the process are changed, so this test runs
+        // alone.
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            child_test_stderr("run::tests::test_module_scoped_variables");
+
+            return;
+        }
+
+        env::set_var("INLINE_C_RS_SCOPED", "global");
+        env::set_var("INLINE_C_RS_SCOPE_A__SCOPED", "scope_a");
+        env::set_var("INLINE_C_RS_SCOPE_A__INNER__SCOPED", "scope_a::inner");
+
+        let scoped = |module_path| {
+            let mut variables = collect_environment_variables("", module_path)
+                .unwrap()
+                .variables;
+
+            assert!(variables.keys().all(|name| !name.contains("__")));
+
+            variables.remove("SCOPED").unwrap()
+        };
+
+        assert_eq!(scoped(""), "global");
+        assert_eq!(scoped("scope_b"), "global");
+        assert_eq!(scoped("scope_a"), "scope_a");
+        assert_eq!(scoped("scope_a::inner"), "scope_a::inner");
+        assert_eq!(scoped("scope_a::other"), "scope_a");
+
+        env::remove_var("INLINE_C_RS_SCOPED");
+        env::remove_var("INLINE_C_RS_SCOPE_A__SCOPED");
+        env::remove_var("INLINE_C_RS_SCOPE_A__INNER__SCOPED");
+    }
+
+    #[test]
+    fn test_location_source_dir() {
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let location = |file| Location {
+            module_path: module_path!(),
+            file,
+            manifest_dir,
+            out_dir: None,
+            debug_assertions: cfg!(debug_assertions),
+        };
+
+        assert_eq!(
+            location(file!()).source_dir().unwrap(),
+            Path::new(manifest_dir).join("src")
+        );
+        assert!(location("src/missing.rs").source_dir().is_none());
+    }
+
+    #[test]
+    fn test_include_relative_to_source() {
+        let source_dir = tempfile::tempdir().unwrap();
+        fs::write(source_dir.path().join("local.h"), "#define ANSWER 42\n").unwrap();
+        let file = source_dir.path().join("test.rs");
+
+        run_at(
+            Language::C,
+            r#"
+                #include "local.h"
+
+                int main() {
+                    return ANSWER;
+                }
+            "#,
+            Some(Location {
+                module_path: module_path!(),
+                file: Box::leak(file.to_str().unwrap().to_string().into_boxed_str()),
+                manifest_dir: env!("CARGO_MANIFEST_DIR"),
+                out_dir: None,
+                debug_assertions: cfg!(debug_assertions),
+            }),
+        )
+        .unwrap()
+        .assert()
+        .code(42);
+    }
+
+    #[test]
+    fn test_auto_include_dirs() {
+        let out_dir = tempfile::tempdir().unwrap();
+        let location = Location {
+            module_path: module_path!(),
+            file: file!(),
+            manifest_dir: env!("CARGO_MANIFEST_DIR"),
+            out_dir: Some(Box::leak(
+                out_dir
+                    .path()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+                    .into_boxed_str(),
+            )),
+            debug_assertions: cfg!(debug_assertions),
+        };
+
+        // This crate has no `include/` directory.
+        assert_eq!(auto_include_dirs(Some(&location)), [out_dir.path()]);
+
+        fs::write(out_dir.path().join("generated.h"), "#define ANSWER 42\n").unwrap();
+
+        run_at(
+            Language::C,
+            r#"
+                #inline_c_rs auto_include: "1"
+                #include "generated.h"
+
+                int main() {
+                    return ANSWER;
+                }
+            "#,
+            Some(location),
+        )
+        .unwrap()
+        .assert()
+        .code(42);
+
+        run_at(
+            Language::C,
+            r#"
+                #inline_c_rs use_out_dir: "1"
+                #include "generated.h"
+
+                int main() {
+                    return ANSWER;
+                }
+            "#,
+            Some(location),
+        )
+        .unwrap()
+        .assert()
+        .code(42);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_callbacks() {
+        extern "C" fn add(x: i32, y: i32) -> i32 {
+            x + y
+        }
+
+        run_with_callbacks(
+            Language::C,
+            r#"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int add(int x, int y);
+
+                int main(int argc, char** argv) {
+                    int sum = add(atoi(argv[argc - 1]), 2);
+                    printf("sum = %d", sum);
+
+                    return sum;
+                }
+            "#,
+            None,
+            &[Callback {
+                name: "add",
+                address: add as *const () as usize,
+            }],
+        )
+        .unwrap()
+        .arg("40")
+        .assert()
+        .code(42)
+        .stdout("sum = 42");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_process() {
+        let mut assert = run_in_process(
+            Language::C,
+            r#"
+                #inline_c_rs entry_point: "entry"
+                #inline_c_rs IN_PROCESS: "yes"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int entry(int argc, char** argv) {
+                    printf("%d %s %s", argc, argv[1], getenv("IN_PROCESS"));
+                    fflush(stdout);
+
+                    if (argc > 2) {
+                        *(volatile int*) 0 = 42;
+                    }
+
+                    return 0;
+                }
+            "#,
+            None,
+            &[],
+        )
+        .unwrap();
+
+        assert.arg("foo").assert().success().stdout("2 foo yes");
+
+        // A crash does not take the test process down.
+        assert.arg("bar").assert().failure();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fork_server() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs fork_server: "1"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main(int argc, char** argv) {
+                    char line[32] = {0};
+
+                    if (fgets(line, sizeof(line), stdin) != NULL) {
+                        printf("stdin: %s", line);
+                    }
+
+                    fprintf(stderr, "%s", argv[argc - 1]);
+
+                    if (argc > 3) {
+                        abort();
+                    }
+
+                    return argc;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert.assert().code(1).stdout("");
+
+        assert
+            .arg("foo")
+            .stdin("bar\n")
+            .assert()
+            .code(2)
+            .stdout("stdin: bar\n")
+            .stderr("foo");
+
+        assert.arg("baz").assert().code(3).stderr("baz");
+
+        // A crash does not stop the fork server.
+        assert.arg("qux").assert().failure();
+        assert.assert().failure().stderr("qux");
+
+        // `main` is called with its arguments, in C23 too.
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs fork_server: "1"
+                #inline_c_rs standard: "c23"
+                #include <stdio.h>
+
+                int main(int argc, char** argv) {
+                    printf("%d %s", argc, argv[1]);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .arg("foo")
+        .assert()
+        .success()
+        .stdout("2 foo");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pty() {
+        let program = |pty: &str| {
+            format!(
+                r#"
+                    #inline_c_rs pty: "{}"
+                    #include <stdio.h>
+                    #include <unistd.h>
+
+                    int main() {{
+                        printf("stdout is a tty: %d\n", isatty(STDOUT_FILENO));
+                        fprintf(stderr, "stderr is a tty: %d", isatty(STDERR_FILENO));
+
+                        return 0;
+                    }}
+                "#,
+                pty
+            )
+        };
+
+        run(Language::C, &program("1"))
+            .unwrap()
+            .success()
+            .stdout("stdout is a tty: 1\n")
+            .stderr("stderr is a tty: 0");
+
+        run(Language::C, &program("0"))
+            .unwrap()
+            .success()
+            .stdout("stdout is a tty: 0\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_merge_stderr() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs merge_stderr: "1"
+                #include <stdio.h>
+
+                int main(int argc, char** argv) {
+                    setvbuf(stdout, NULL, _IONBF, 0);
+
+                    printf("result 1\n");
+                    fprintf(stderr, "log\n");
+                    printf("result 2 %s\n", argv[argc - 1]);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .arg("with spaces")
+        .success()
+        .stdout("result 1\nlog\nresult 2 with spaces\n")
+        .stderr("");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_scheduling() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs nice: "5"
+                #inline_c_rs cpu_affinity: "0"
+                #define _GNU_SOURCE
+                #include <sched.h>
+                #include <stdio.h>
+                #include <unistd.h>
+
+                int main() {
+                    cpu_set_t set;
+                    sched_getaffinity(0, sizeof(set), &set);
+
+                    printf("%d %d", nice(0) >= 5, CPU_COUNT(&set));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("1 1");
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        // Cargo gives its variables to the tests, so that the ones of
+        // the process are not changed while other tests run.
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+
+        assert_eq!(
+            expand_variables("${CARGO_MANIFEST_DIR}/bar/${CARGO_MANIFEST_DIR}").unwrap(),
+            format!("{}/bar/{}", manifest_dir, manifest_dir)
+        );
+        assert_eq!(expand_variables("no variable").unwrap(), "no variable");
+        assert!(expand_variables("${INLINE_C_RS_TEST_UNDEFINED}").is_err());
+        assert!(expand_variables("${CARGO_MANIFEST_DIR").is_err());
+        assert_eq!(
+            expand_variables("$${INLINE_C_RS_TEST_UNDEFINED} $${").unwrap(),
+            "${INLINE_C_RS_TEST_UNDEFINED} ${"
+        );
+    }
+
+    #[test]
+    fn test_is_transient_failure() {
+        let output = |stderr: &str| Output {
+            status: Default::default(),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+
+        assert!(is_transient_failure(&output(
+            "fatal error C1090: PDB API call failed, error code '23'"
+        )));
+        assert!(is_transient_failure(&output(
+            "ld: cannot open output file: Text file busy"
+        )));
+        assert!(!is_transient_failure(&output(
+            "error: expected ';' before '}' token"
+        )));
+        // A missing library is an error of the program.
+        assert!(!is_transient_failure(&output(
+            "LINK : fatal error LNK1104: cannot open file 'foo.lib'"
+        )));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("42").unwrap(), 42);
+        assert_eq!(parse_size("64K").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("8m").unwrap(), 8 * 1024 * 1024);
+        assert!(parse_size("eight").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_stack_size() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs stack_size: "2M"
+                #include <stdio.h>
+                #include <sys/resource.h>
+
+                int main() {
+                    struct rlimit limit;
+                    getrlimit(RLIMIT_STACK, &limit);
+                    printf("%lu", (unsigned long) limit.rlim_cur);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("2097152");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_backtrace() {
+        let program = r#"
+            #inline_c_rs backtrace: "1"
+            #include <signal.h>
+
+            int main() {
+                raise(SIGSEGV);
+
+                return 0;
+            }
+        "#;
+
+        run(Language::C, program).unwrap().assert().interrupted();
+
+        // The backtrace is attached to the failure of the assertion.
+        let message = panic_message(|| {
+            run(Language::C, program).unwrap().assert().success();
+        });
+
+        assert!(message.contains("backtrace"), "{}", message);
+
+        if crate::debugger::Debugger::find().is_some() {
+            // The frames, i.e. `#0 …` with `gdb`, `frame #0: …` with `lldb`.
+            assert!(message.contains("#0"), "{}", message);
+        } else {
+            assert!(message.contains("No debugger found"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_run_invalid_debugger() {
+        assert!(run(
+            Language::C,
+            r#"
+                #inline_c_rs debugger: "notepad"
+                int main() { return 0; }
+            "#,
+        )
+        .is_err());
+
+        // `DEBUG` is an ordinary variable of the program.
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs DEBUG: "1"
+
+                #include <stdlib.h>
+                #include <string.h>
+
+                int main() {
+                    return strcmp(getenv("DEBUG"), "1");
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_run_strace() {
+        if !crate::trace::is_available("strace") {
+            return;
+        }
+
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs tracer: "strace"
+                #include <stdio.h>
+
+                int main() {
+                    fclose(fopen("/etc/hostname", "r"));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert.success();
+        assert!(assert.trace_output().contains("/etc/hostname"));
+    }
+
+    #[test]
+    fn test_uses_threads() {
+        assert!(uses_threads("#include <pthread.h>\nint main() {}"));
+        assert!(uses_threads("#include <thread>\nint main() {}"));
+        assert!(!uses_threads("#include <stdio.h>\nint main() {}"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_thread_sanitizer() {
+        let program = |lock: bool| {
+            format!(
+                r#"
+                    #inline_c_rs sanitizer: "thread"
+                    #include <pthread.h>
+
+                    int counter = 0;
+                    pthread_mutex_t mutex = PTHREAD_MUTEX_INITIALIZER;
+
+                    void* increment(void* arg) {{
+                        (void) arg;
+                        {lock}
+                        counter++;
+                        {unlock}
+
+                        return NULL;
+                    }}
+
+                    int main() {{
+                        pthread_t threads[2];
+
+                        for (int i = 0; i < 2; ++i) {{
+                            pthread_create(&threads[i], NULL, increment, NULL);
+                        }}
+
+                        for (int i = 0; i < 2; ++i) {{
+                            pthread_join(threads[i], NULL);
+                        }}
+
+                        return 0;
+                    }}
+                "#,
+                lock = if lock {
+                    "pthread_mutex_lock(&mutex);"
+                } else {
+                    ""
+                },
+                unlock = if lock {
+                    "pthread_mutex_unlock(&mutex);"
+                } else {
+                    ""
+                },
+            )
+        };
+
+        run(Language::C, &program(true))
+            .unwrap()
+            .no_data_races()
+            .success();
+
+        let mut assert = run(Language::C, &program(false)).unwrap();
+        panic_message(|| {
+            assert.no_data_races();
+        });
+    }
+
+    #[test]
+    fn test_auto_link_flags() {
+        assert_eq!(
+            auto_link_flags(
+                "#include <math.h>\n#include <complex.h>\n#include <dlfcn.h>\n",
+                false
+            ),
+            vec!["-lm", "-ldl"]
+        );
+        assert!(auto_link_flags("#include <stdio.h>\n", false).is_empty());
+        assert_eq!(
+            auto_link_flags("#include <math.h>\n#include <stdarg.h>\n", true),
+            vec!["legacy_stdio_definitions.lib"]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_auto_link() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs auto_link: "1"
+                #include <math.h>
+                #include <stdio.h>
+
+                int main() {
+                    volatile double x = 2.0;
+                    printf("%.3f", pow(x, 0.5));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("1.414");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_openmp() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs openmp: "1"
+                #include <omp.h>
+                #include <stdio.h>
+
+                int main() {
+                    int sum = 0;
+
+                    #pragma omp parallel for reduction(+:sum)
+                    for (int i = 1; i <= 100; ++i) {
+                        sum += i;
+                    }
+
+                    printf("%d", sum);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("5050");
+    }
+
+    #[test]
+    fn test_run_expect_stderr() {
+        let program = |expected: &str| {
+            format!(
+                r#"
+                    #inline_c_rs strict_stderr: "1"
+                    #inline_c_rs expect_stderr: "{expected}"
+                    #include <stdio.h>
+
+                    int main() {{
+                        fprintf(stderr, "deprecated function");
+
+                        return 0;
+                    }}
+                "#,
+                expected = expected,
+            )
+        };
+
+        run(Language::C, &program("deprecated function"))
+            .unwrap()
+            .success();
+
+        // ` function` is not expected. It fails like the other
+        // assertions, with the context of the program.
+        let mut assert = run(Language::C, &program("deprecated")).unwrap();
+        assert.context("strict");
+        let message = panic_message(|| {
+            assert.success();
+        });
+
+        assert!(
+            message.starts_with("[strict] Unexpected output on the standard error"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_run_report() {
+        let report_path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+
+        run(
+            Language::C,
+            &format!(
+                r#"
+                    #inline_c_rs report: "{report}"
+                    int main() {{
+                        return 0;
+                    }}
+                "#,
+                report = report_path.display(),
+            ),
+        )
+        .unwrap()
+        .success();
+
+        let report = fs::read_to_string(&report_path).unwrap();
+
+        assert!(report.contains("\"test\": \"run::tests::test_run_report\""));
+        assert!(report.contains("\"result\": \"exited with 0\""));
+    }
+
+    #[test]
+    fn test_run_journal() {
+        let journal_path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let program = |mode: &str| {
+            format!(
+                r#"
+                    #inline_c_rs journal: "{journal}"
+                    #inline_c_rs journal_mode: "{mode}"
+                    #include <stdio.h>
+
+                    int main() {{
+                        printf("Hello, World!\n");
+
+                        return 0;
+                    }}
+                "#,
+                journal = journal_path.display(),
+                mode = mode,
+            )
+        };
+
+        run(Language::C, &program("record"))
+            .unwrap()
+            .success()
+            .stdout(predicate::eq("Hello, World!\n").normalize());
+
+        let mut assert = run(Language::C, &program("replay")).unwrap();
+        assert
+            .success()
+            .stdout(predicate::eq("Hello, World!\n").normalize());
+
+        // Unknown execution.
+        assert.arg("foo");
+        panic_message(|| {
+            assert.assert();
+        });
+    }
+
+    #[test]
+    fn test_run_finishes_within() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #include <unistd.h>
+
+                int main() {
+                    usleep(200000);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert
+            .finishes_within(std::time::Duration::from_secs(30))
+            .success();
+        panic_message(|| {
+            assert.finishes_within(std::time::Duration::from_millis(100));
+        });
+    }
+
+    #[test]
+    fn test_run_min_compiler() {
+        let compiler = match &crate::toolchain_info().c {
+            Some(compiler) => compiler,
+            None => return,
+        };
+        let program = |min_compiler: &str| {
+            format!(
+                r#"
+                    #inline_c_rs min_compiler: "{}"
+
+                    int main() {{
+                        return 0;
+                    }}
+                "#,
+                min_compiler
+            )
+        };
+
+        if let Some(version) = compiler.version {
+            run(
+                Language::C,
+                &program(&format!("{} {}", compiler.kind, version)),
+            )
+            .unwrap()
+            .success();
+        }
+
+        assert!(run(Language::C, &program(&format!("{} 999", compiler.kind))).is_err());
+    }
+
+    #[test]
+    fn test_run_standard() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs standard: "c11"
+                #include <stdio.h>
+
+                int main() {
+                    printf("%ld", __STDC_VERSION__);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("201112");
+
+        assert!(run(
+            Language::C,
+            r#"
+                #inline_c_rs standard: "c++17"
+
+                int main() {
+                    return 0;
+                }
+            "#,
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    fn test_run_cuda() {
+        if !crate::cuda_available() {
+            return;
+        }
+
+        run(
+            Language::Cuda,
+            r#"
+                #include <stdio.h>
+
+                __global__ void add_one(int *x) {
+                    *x += 1;
+                }
+
+                int main() {
+                    int *x;
+                    cudaMallocManaged(&x, sizeof(int));
+                    *x = 41;
+
+                    add_one<<<1, 1>>>(x);
+                    cudaDeviceSynchronize();
+                    printf("%d", *x);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("42");
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("  cc  -O2 ").unwrap(), ["cc", "-O2"]);
+        assert_eq!(
+            split_words("'/opt/my tools/cc' -DA=\"b c\"").unwrap(),
+            ["/opt/my tools/cc", "-DA=b c"]
+        );
+        assert_eq!(
+            split_words(r#"my\ cc "a\"b" ''"#).unwrap(),
+            ["my cc", "a\"b", ""]
+        );
+        assert_eq!(
+            split_words(r"C:\LLVM\bin\clang.exe /O2").unwrap(),
+            [r"C:\LLVM\bin\clang.exe", "/O2"]
+        );
+        assert!(split_words("'cc").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_custom_compiler() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs compiler: "cc -DVALUE=42"
+
+                int main() {
+                    return VALUE;
+                }
+            "#,
+        )
+        .unwrap()
+        .failure()
+        .code(42);
+
+        // The path of the compiler can contain spaces.
+        let compiler_dir = tempfile::Builder::new()
+            .prefix("inline c rs ")
+            .tempdir()
+            .unwrap();
+        let compiler = compiler_dir.path().join("my cc");
+        let cc_path = Command::new("sh")
+            .args(["-c", "command -v cc"])
+            .output()
+            .unwrap()
+            .stdout;
+        std::os::unix::fs::symlink(String::from_utf8(cc_path).unwrap().trim(), &compiler).unwrap();
+
+        run(
+            Language::C,
+            &format!(
+                r#"
+                    #inline_c_rs compiler: "'{}' -DVALUE=7"
+
+                    int main() {{
+                        return VALUE;
+                    }}
+                "#,
+                compiler.display()
+            ),
+        )
+        .unwrap()
+        .failure()
+        .code(7);
+
+        // `.i` files are already preprocessed C.
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs source_extension: "i"
+
+                int main() {
+                    return 3;
+                }
+            "#,
+        )
+        .unwrap()
+        .failure()
+        .code(3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_dynamic_dependencies() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs LDFLAGS: "-lm"
+                #include <math.h>
+                #include <stdio.h>
+
+                int main(int argc, char **argv) {
+                    (void) argv;
+                    printf("%f", sqrt((double) argc));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert
+            .links_against("libc.so")
+            .does_not_link_against("libssl")
+            .success();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_runner() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs runner: "env FOO=bar"
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main() {
+                    printf("%s", getenv("FOO"));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("bar");
+    }
+
+    #[test]
+    fn test_run_with() {
+        run_with(RunConfig {
+            language: Language::C,
+            source: String::from(
+                r#"
+                    #inline_c_rs FOO: "directive"
+                    #include <stdio.h>
+                    #include <stdlib.h>
+
+                    int main() {
+                        printf("%s %d", getenv("FOO"), BAR);
+
+                        return 0;
+                    }
+                "#,
+            ),
+            flags: vec![String::from("-DBAR=42")],
+            env: vec![(String::from("FOO"), String::from("config"))],
+            ..Default::default()
+        })
+        .unwrap()
+        .success()
+        .stdout("config 42");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_compile_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A compiler that hangs on the program only, not when `cc-rs`
+        // detects it.
+        let compiler = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(
+            &compiler,
+            "#!/bin/sh\ncase \"$*\" in *inline-c-rs-*) sleep 30;; esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&compiler, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let start = Instant::now();
+        let error = run(
+            Language::C,
+            &format!(
+                r#"
+                    #inline_c_rs compiler: "{}"
+                    #inline_c_rs compile_timeout: "0.5"
+
+                    int main() {{
+                        return 0;
+                    }}
+                "#,
+                compiler.display()
+            ),
+        )
+        .err()
+        .unwrap();
+
+        assert!(error
+            .to_string()
+            .contains("did not finish within 0.5 seconds"));
+        assert!(start.elapsed() < Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_run_diagnostics_color() {
+        if cfg!(target_env = "msvc") {
+            return;
+        }
+
+        let program = |color: &str| {
+            format!(
+                r#"
+                    #inline_c_rs diagnostics_color: "{}"
+
+                    int main() {{
+                        return undefined;
+                    }}
+                "#,
+                color
+            )
+        };
+
+        run(Language::C, &program("always"))
+            .unwrap()
+            .failure()
+            .stderr(predicate::str::contains("\x1b["));
+        run(Language::C, &program("never"))
+            .unwrap()
+            .failure()
+            .stderr(predicate::str::contains("\x1b[").not());
+    }
+
+    #[test]
+    fn test_run_flags_per_language() {
+        let program = r#"
+            #inline_c_rs CPPFLAGS: "-DPREPROCESSOR=1"
+            #inline_c_rs CFLAGS: "-DC=2"
+            #inline_c_rs CXXFLAGS: "-DCXX=3"
+
+            #include <stdio.h>
+
+            #ifndef C
+            #define C 0
+            #endif
+
+            #ifndef CXX
+            #define CXX 0
+            #endif
+
+            int main() {
+                printf("%d %d %d", PREPROCESSOR, C, CXX);
+
+                return 0;
+            }
+        "#;
+
+        run(Language::C, program).unwrap().success().stdout("1 2 0");
+        run(Language::Cxx, program)
+            .unwrap()
+            .success()
+            .stdout("1 0 3");
+    }
+
+    #[test]
+    fn test_run_compile_and_run_env() {
+        run_with(RunConfig {
+            source: String::from(
+                r#"
+                    #inline_c_rs_compile INLINE_C_RS_TEST_STEP: "compile"
+                    #inline_c_rs_run INLINE_C_RS_TEST_STEP: "run"
+
+                    #include <stdio.h>
+                    #include <stdlib.h>
+
+                    int main() {
+                        printf("%s %s", getenv("INLINE_C_RS_TEST_STEP"), getenv("RUN"));
+
+                        return 0;
+                    }
+                "#,
+            ),
+            run_env: vec![(String::from("RUN"), String::from("yes"))],
+            ..Default::default()
+        })
+        .unwrap()
+        .success()
+        .stdout("run yes");
+
+        let Environment {
+            variables,
+            compile_env,
+            run_env,
+            ..
+        } = collect_environment_variables(
+            "#inline_c_rs FOO: \"1\"\n#inline_c_rs_compile BAR: \"2\"\n#inline_c_rs_run BAZ: \"3\"\n",
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(variables.get("FOO").map(String::as_str), Some("1"));
+        assert_eq!(variables.get("BAR"), None);
+        assert_eq!(compile_env.get("BAR").map(String::as_str), Some("2"));
+        assert_eq!(run_env.get("BAZ").map(String::as_str), Some("3"));
+    }
+
+    #[test]
+    fn test_is_reserved_by_toolchains() {
+        assert!(is_reserved_by_toolchains("INCLUDE"));
+        assert!(is_reserved_by_toolchains("Path"));
+        assert!(!is_reserved_by_toolchains("INCLUDE_DIR"));
+
+        // They are given to the program only if they are defined for it.
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs LIBPATH: "shared"
+                #inline_c_rs_run LIB: "program"
+
+                #include <stdio.h>
+                #include <stdlib.h>
+                #include <string.h>
+
+                int main() {
+                    const char *libpath = getenv("LIBPATH");
+
+                    printf("%d %s", libpath != NULL && strcmp(libpath, "shared") == 0, getenv("LIB"));
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("0 program");
+    }
+
+    #[test]
+    fn test_compiles() {
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            child_test_stderr("run::tests::test_compiles");
+
+            return;
+        }
+
+        // The counters are global to the process, which runs this test
+        // alone.
+        assert!(compiles(Language::C, "int main() { return 0; }"));
+        assert!(!compiles(Language::C, "int main() { return undefined; }"));
+        assert_eq!(crate::footprint().programs, 2);
+
+        // The results are cached, nothing is compiled again.
+        assert!(compiles(Language::C, "int main() { return 0; }"));
+        assert!(!compiles(Language::C, "int main() { return undefined; }"));
+        assert_eq!(crate::footprint().programs, 2);
+
+        // The meta environment variables are part of the cache. They can
+        // be changed, since this test runs alone.
+        let program = "int main() { return ANSWER; }";
+
+        assert!(!compiles(Language::C, program));
+
+        env::set_var("INLINE_C_RS_CFLAGS", "-DANSWER=0");
+
+        assert!(compiles(Language::C, program));
+        assert_eq!(crate::footprint().programs, 4);
+    }
+
+    #[test]
+    fn test_profile() {
+        let location = |debug_assertions| Location {
+            module_path: module_path!(),
+            file: file!(),
+            manifest_dir: env!("CARGO_MANIFEST_DIR"),
+            out_dir: None,
+            debug_assertions,
+        };
+        let mut variables = HashMap::new();
+
+        // The default flags are kept unless asked otherwise.
+        assert_eq!(
+            Profile::of(&variables, Some(&location(true))).unwrap(),
+            None
+        );
+
+        variables.insert("profile".to_string(), "cargo".to_string());
+
+        assert_eq!(Profile::of(&variables, None).unwrap(), None);
+        assert_eq!(
+            Profile::of(&variables, Some(&location(true))).unwrap(),
+            Some(Profile::DEBUG)
+        );
+        assert_eq!(
+            Profile::of(&variables, Some(&location(false))).unwrap(),
+            Some(Profile::RELEASE)
+        );
+
+        variables.insert("profile".to_string(), "release".to_string());
+
+        assert_eq!(
+            Profile::of(&variables, Some(&location(true))).unwrap(),
+            Some(Profile::RELEASE)
+        );
+
+        variables.insert("profile".to_string(), "fast".to_string());
+
+        assert!(Profile::of(&variables, None).is_err());
+    }
+
+    #[test]
+    fn test_run_system_include_dirs() {
+        let include_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            include_dir.path().join("third_party.h"),
+            "static int unused(void) { int x; return 42; }\n#define ANSWER 42\n",
+        )
+        .unwrap();
+        let config = RunConfig {
+            source: String::from(
+                r#"
+                    #include "third_party.h"
+
+                    int main() {
+                        return ANSWER;
+                    }
+                "#,
+            ),
+            flags: vec![format!("-I{}", include_dir.path().display())],
+            ..Default::default()
+        };
+
+        run_with(config.clone()).unwrap().failure().code(1);
+
+        run_with(RunConfig {
+            flags: Vec::new(),
+            ..config.include_system_dir(include_dir.path())
+        })
+        .unwrap()
+        .failure()
+        .code(42);
+    }
+
+    #[test]
+    fn test_run_defines() {
+        run_with(
+            RunConfig {
+                source: String::from(
+                    r#"
+                        #inline_c_rs define ANSWER: "40 + 2"
+
+                        #include <stdio.h>
+                        #include <string.h>
+
+                        int main() {
+                            #ifndef EMPTY
+                            return 1;
+                            #endif
+
+                            printf("%s %d", GREETING, ANSWER);
+
+                            return 0;
+                        }
+                    "#,
+                ),
+                ..Default::default()
+            }
+            .define("GREETING", Some("\"Hello, \\\"World\\\"!\""))
+            .define("EMPTY", None),
+        )
+        .unwrap()
+        .success()
+        .stdout("Hello, \"World\"! 42");
+
+        assert!(run_with(RunConfig::default().define("1ANSWER", Some("42"))).is_err());
+    }
+
+    #[test]
+    fn test_run_record_headers() {
+        let include_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            include_dir.path().join("public.h"),
+            "#include \"private.h\"\n",
+        )
+        .unwrap();
+        fs::write(include_dir.path().join("private.h"), "#define ANSWER 42\n").unwrap();
+
+        let mut assert = run_with(RunConfig {
+            source: String::from(
+                r#"
+                    #inline_c_rs record_headers: "1"
+                    #include <stddef.h>
+                    #include "public.h"
+
+                    int main() {
+                        return ANSWER;
+                    }
+                "#,
+            ),
+            flags: vec![format!("-I{}", include_dir.path().display())],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(assert
+            .included_headers()
+            .contains(&include_dir.path().join("private.h")));
+
+        assert
+            .includes_header("public.h")
+            .includes_header("stddef.h");
+
+        panic_message(|| {
+            assert.does_not_include_header("private.h");
+        });
+    }
+
+    #[test]
+    fn test_artifacts() {
+        let assert = run(Language::C, "int main() { return 0; }").unwrap();
+        let artifacts = assert.artifacts();
+
+        assert_eq!(artifacts.sources().len(), 1);
+        assert!(artifacts.output().unwrap().is_file());
+
+        let persist_dir = tempfile::tempdir().unwrap();
+        let persisted = artifacts.persist_to(persist_dir.path()).unwrap();
+        let output = persisted.output().unwrap().to_path_buf();
+
+        drop(assert);
+
+        assert!(output.is_file());
+    }
+
+    #[test]
+    fn test_context() {
+        let mut assert = run(Language::C, "int main() { return 0; }").unwrap();
+        assert.context("empty program");
+
+        let message = panic_message(|| {
+            assert.file_check();
+        });
+
+        assert!(message.starts_with("[empty program] The program has no `// CHECK:`"));
+
+        let message = panic_message(|| {
+            assert.failure();
+        });
+
+        assert!(message.contains("empty program"), "{}", message);
+    }
+
+    #[test]
+    fn test_compile_failure() {
+        let mut assert = run(Language::C, "int main() { return undefined_symbol; }").unwrap();
+
+        assert!(assert.compilation_failed());
+        assert
+            .compile_failure()
+            .stderr(predicate::str::contains("undefined_symbol"));
+
+        let message = panic_message(|| {
+            assert.success();
+        });
+
+        assert!(message.contains("compilation"), "{}", message);
+        assert!(message.contains("undefined_symbol"), "{}", message);
+        assert!(
+            message.contains("| int main() { return undefined_symbol; }\n"),
+            "{}",
+            message
+        );
+        assert!(message.contains("|                     ^\n"), "{}", message);
+
+        let mut assert = run(Language::C, "int main() { return 0; }").unwrap();
+
+        assert!(!assert.compilation_failed());
+        panic_message(|| {
+            assert.compile_failure();
+        });
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        run(Language::C, "int main() { return undefined_symbol; }")
+            .unwrap()
+            .errors(1)
+            .warnings(0);
+
+        let mut assert = run_with(RunConfig {
+            source: String::from("int main() { int unused; return 0; }"),
+            flags: vec![String::from("-Wno-error")],
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert.errors(0).warnings(1).success();
+        panic_message(|| {
+            assert.warnings(0);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_uncaught_exception() {
+        run(
+            Language::Cxx,
+            r#"
+                #include <vector>
+
+                int main() {
+                    std::vector<int> numbers;
+
+                    return numbers.at(1);
+                }
+            "#,
+        )
+        .unwrap()
+        .uncaught_exception_containing("std::out_of_range");
+
+        let mut assert = run(
+            Language::Cxx,
+            r#"
+                #include <cstdlib>
+
+                int main() {
+                    abort();
+                }
+            "#,
+        )
+        .unwrap();
+        let message = panic_message(|| {
+            assert.uncaught_exception_containing("std::out_of_range");
+        });
+
+        assert!(message.contains("it has aborted"), "{}", message);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_no_leaks_with_suppressions() {
+        let program = r#"
+            #inline_c_rs sanitizer: "address"
+            #include <stdlib.h>
+
+            void* volatile sink;
+
+            __attribute__((noinline)) void* leak_on_purpose(void) {
+                return malloc(16);
+            }
+
+            int main() {
+                sink = leak_on_purpose();
+                sink = NULL;
+
+                return 0;
+            }
+        "#;
+        let suppressions_dir = tempfile::tempdir().unwrap();
+        let suppressions = suppressions_dir.path().join("lsan.supp");
+
+        fs::write(&suppressions, "leak:leak_on_purpose\n").unwrap();
+        run(Language::C, program)
+            .unwrap()
+            .no_leaks_with_suppressions(&suppressions)
+            .success();
+
+        fs::write(&suppressions, "leak:another_function\n").unwrap();
+        let mut assert = run(Language::C, program).unwrap();
+        let message = panic_message(|| {
+            assert.no_leaks_with_suppressions(&suppressions);
+        });
+
+        assert!(message.contains("LeakSanitizer"), "{}", message);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sanitizer_findings() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs sanitizer: "address"
+                #include <stdlib.h>
+
+                int main(int argc, char** argv) {
+                    (void) argv;
+                    int* numbers = calloc(2, sizeof(int));
+                    int number = numbers[argc + 1];
+                    free(numbers);
+
+                    return number;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert
+            .failure()
+            .stderr(predicate::str::contains("heap-buffer-overflow"));
+
+        let findings = assert.sanitizer_findings();
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].sanitizer, "AddressSanitizer");
+        assert_eq!(findings[0].kind, "heap-buffer-overflow");
+    }
+
+    #[test]
+    fn test_provenance() {
+        let assert = run(Language::C, "int main() { return 0; }").unwrap();
+        let provenance = assert.provenance();
+
+        assert_eq!(provenance.host, target_lexicon::HOST.to_string());
+        assert!(!provenance.flags.is_empty());
+        assert_eq!(
+            fs::read_to_string(assert.artifacts().provenance().unwrap()).unwrap(),
+            provenance.to_json()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_sysroot() {
+        let config = RunConfig {
+            source: String::from(
+                r#"
+                    #include <stdio.h>
+
+                    int main() {
+                        return 0;
+                    }
+                "#,
+            ),
+            ..Default::default()
+        }
+        .target(&target_lexicon::HOST.to_string());
+
+        let assert = run_with(config.clone().sysroot("/")).unwrap();
+
+        assert!(assert.has_compiled());
+        assert!(assert
+            .provenance()
+            .flags
+            .contains(&String::from("--sysroot=/")));
+
+        let sysroot = tempfile::tempdir().unwrap();
+
+        assert!(run_with(config.sysroot(sysroot.path()))
+            .unwrap()
+            .compilation_failed());
+    }
+
+    #[test]
+    fn test_run_toolchain_flags() {
+        let assert = run_with(
+            RunConfig {
+                source: String::from("int main() { return 0; }"),
+                env: vec![(String::from("toolchain_prefixes"), String::from("/opt/a"))],
+                ..Default::default()
+            }
+            .toolchain_prefix("/opt/b")
+            .gcc_toolchain("/opt/gcc"),
+        )
+        .unwrap();
+        let flags = &assert.provenance().flags;
+
+        for flag in ["-B/opt/a", "-B/opt/b", "--gcc-toolchain=/opt/gcc"] {
+            assert!(flags.iter().any(|argument| argument == flag), "{:?}", flags);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_static() {
+        let program = |static_linking: &str| {
+            format!(
+                r#"
+                    #inline_c_rs static: "{}"
+                    int main() {{
+                        return 0;
+                    }}
+                "#,
+                static_linking
+            )
+        };
+
+        run(Language::C, &program("1"))
+            .unwrap()
+            .is_statically_linked()
+            .success();
+
+        let mut assert = run(Language::C, &program("0")).unwrap();
+        panic_message(|| {
+            assert.is_statically_linked();
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_exports_exactly() {
+        let mut assert = run_with(RunConfig {
+            source: String::from(
+                r#"
+                    __attribute__((visibility("hidden"))) int foo_count = 0;
+
+                    int foo_new(void) {
+                        return ++foo_count;
+                    }
+
+                    void foo_free(int foo) {
+                        (void) foo;
+                        --foo_count;
+                    }
+                "#,
+            ),
+            output_kind: OutputKind::SharedObject,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert.exports_exactly(["foo_new", "foo_free"]);
+        panic_message(|| {
+            assert.exports_exactly(["foo_new"]);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_version_script() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = RunConfig {
+            source: String::from(
+                r#"
+                    int foo_helper(void) {
+                        return 42;
+                    }
+
+                    int foo_new(void) {
+                        return foo_helper();
+                    }
+                "#,
+            ),
+            output_kind: OutputKind::SharedObject,
+            ..Default::default()
+        };
+
+        let map_path = directory.path().join("foo.map");
+        fs::write(&map_path, "{ global: foo_new; local: *; };\n").unwrap();
+        run_with(config.clone().version_script(&map_path))
+            .unwrap()
+            .exports_exactly(["foo_new"]);
+
+        let def_path = directory.path().join("foo.def");
+        fs::write(&def_path, "EXPORTS\n    foo_helper\n").unwrap();
+        run_with(config.clone().version_script(&def_path))
+            .unwrap()
+            .exports_exactly(["foo_helper"]);
+
+        assert!(run_with(RunConfig {
+            output_kind: OutputKind::Executable,
+            ..config.version_script(&map_path)
+        })
+        .is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loads_cleanly() {
+        let source = |declaration: &str| RunConfig {
+            source: format!(
+                r#"
+                    {}
+
+                    int foo_new(void) {{
+                        return foo_missing();
+                    }}
+                "#,
+                declaration
+            ),
+            output_kind: OutputKind::SharedObject,
+            ..Default::default()
+        };
+
+        run_with(source("static int foo_missing(void) { return 42; }"))
+            .unwrap()
+            .loads_cleanly();
+
+        let mut assert = run_with(source("int foo_missing(void);")).unwrap();
+        let message = panic_message(|| {
+            assert.loads_cleanly();
+        });
+
+        assert!(message.contains("foo_missing"), "{}", message);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_output_name() {
+        let assert = run_with(RunConfig {
+            source: String::from(
+                r#"
+                    #inline_c_rs output_name: "libplugin.so"
+
+                    int plugin_version(void) {
+                        return 1;
+                    }
+                "#,
+            ),
+            output_kind: OutputKind::SharedObject,
+            ..Default::default()
+        })
+        .unwrap();
+        let output = assert.artifacts().output().unwrap().to_path_buf();
+
+        assert_eq!(output.file_name().unwrap(), "libplugin.so");
+        assert!(output.exists());
+
+        drop(assert);
+
+        assert!(!output.exists());
+
+        let error = run(
+            Language::C,
+            r#"
+                #inline_c_rs output_name: "../plugin"
+
+                int main() { return 0; }
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            error.to_string().contains("expected a file name"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_persist_binary_to() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("bin").join("tool");
+
+        run(Language::C, "int main() { return 7; }")
+            .unwrap()
+            .persist_binary_to(&path)
+            .failure()
+            .code(7);
+
+        assert!(path.is_file());
+
+        assert_eq!(Command::new(&path).status().unwrap().code(), Some(7));
+    }
+
+    #[test]
+    fn test_compiler_env() {
+        let compiler_env = |value: &str| {
+            CompilerEnv::from_variables(&HashMap::from([(
+                "compiler_env".to_string(),
+                value.to_string(),
+            )]))
+        };
+
+        assert_eq!(
+            CompilerEnv::from_variables(&HashMap::new()),
+            Ok(CompilerEnv::WithoutFlags)
+        );
+        assert!(!CompilerEnv::WithoutFlags.inherits("CFLAGS"));
+        assert!(CompilerEnv::WithoutFlags.inherits("FOO"));
+        assert!(compiler_env("all").unwrap().inherits("CFLAGS"));
+        assert!(!compiler_env("none").unwrap().inherits("FOO"));
+
+        let only = compiler_env("FOO, BAR").unwrap();
+
+        assert!(only.inherits("BAR"));
+        assert!(!only.inherits("BAZ"));
+        assert!(compiler_env("FOO BAR").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_compiler_env() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A compiler wrapper which would apply `CFLAGS` a second time.
+        let compiler = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        fs::write(
+            &compiler,
+            "#!/bin/sh\n[ -z \"$CFLAGS\" ] || exit 1\nexec cc \"$@\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&compiler, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let program = |compiler_env: &str| {
+            format!(
+                r#"
+                    #inline_c_rs compiler: "{}"
+                    #inline_c_rs CFLAGS: "-DVALUE=2"
+                    {}
+
+                    int main() {{
+                        return VALUE;
+                    }}
+                "#,
+                compiler.display(),
+                compiler_env
+            )
+        };
+
+        run(Language::C, &program("")).unwrap().failure().code(2);
+        assert!(
+            run(Language::C, &program(r#"#inline_c_rs compiler_env: "all""#))
+                .unwrap()
+                .compilation_failed()
+        );
+    }
+
+    #[test]
+    fn test_compiler_flags() {
+        let target = "x86_64-unknown-linux-gnu";
+        let environment = HashMap::from([
+            ("CFLAGS", "-DA=1"),
+            ("HOST_CFLAGS", "-DB=2"),
+            ("CFLAGS_x86_64_unknown_linux_gnu", "-DC=3"),
+            ("CPPFLAGS", "-DD=4"),
+            ("LDFLAGS", "-lm"),
+        ]);
+        let getenv = |name: &str| environment.get(name).map(|value| value.to_string());
+        // `cc-rs` appends the `CFLAGS` of the environment to its flags.
+        let compiler_args = ["-O1", "-DA=1", "-DB=2", "-DC=3"]
+            .iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>();
+        let count = |flags: &[OsString], flag: &str| flags.iter().filter(|f| *f == flag).count();
+
+        let flags = compiler_flags(
+            &compiler_args,
+            false,
+            &Language::C,
+            &HashMap::new(),
+            target,
+            target,
+            getenv,
+        );
+
+        for flag in ["-O1", "-DA=1", "-DB=2", "-DC=3", "-DD=4", "-Wl,-lm"] {
+            assert_eq!(count(&flags, flag), 1, "{} in {:?}", flag, flags);
+        }
+
+        // The `CFLAGS` variable overrides the environment.
+        let flags = compiler_flags(
+            &compiler_args,
+            false,
+            &Language::C,
+            &HashMap::from([("CFLAGS".to_string(), "-DE=5".to_string())]),
+            target,
+            target,
+            getenv,
+        );
+
+        assert_eq!(count(&flags, "-DE=5"), 1);
+        assert_eq!(count(&flags, "-DA=1"), 0);
+
+        // `CXXFLAGS` is not set, the flags of `cc-rs` are kept.
+        let flags = compiler_flags(
+            &compiler_args,
+            false,
+            &Language::Cxx,
+            &HashMap::new(),
+            target,
+            target,
+            getenv,
+        );
+
+        assert_eq!(count(&flags, "-DA=1"), 1);
+
+        // The paths are translated for the MinGW compilers only.
+        let variables = HashMap::from([
+            ("translate_paths".to_string(), "1".to_string()),
+            ("CFLAGS".to_string(), "-I/c/foo".to_string()),
+        ]);
+
+        for (msvc, flag) in [(false, "-IC:/foo"), (true, "-I/c/foo")] {
+            let flags = compiler_flags(
+                &compiler_args,
+                msvc,
+                &Language::C,
+                &variables,
+                target,
+                target,
+                getenv,
+            );
+
+            assert_eq!(count(&flags, flag), 1, "{} in {:?}", flag, flags);
+        }
+    }
+
+    #[test]
+    fn test_program_name() {
+        assert_eq!(
+            program_name(Some("run::tests::test_foo"), 0x1234_84f7_e2a1),
+            "test_foo-84f7e2a1-"
+        );
+        assert_eq!(
+            program_name(Some("weird name:é"), 1),
+            "weird_name__-00000001-"
+        );
+        assert_eq!(program_name(Some("main"), 1), "inline-c-rs-00000001-");
+        assert_eq!(program_name(None, 1), "inline-c-rs-00000001-");
+
+        let assert = run(Language::C, "int main() { return 0; }").unwrap();
+        let output = assert.artifacts().output().unwrap();
+
+        assert!(output
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("test_program_name-"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_kills_the_process_tree() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+                #include <unistd.h>
+
+                int main() {
+                    pid_t pid = fork();
+
+                    if (pid == 0) {
+                        // The output is kept open.
+                        sleep(60);
+
+                        return 0;
+                    }
+
+                    printf("%d\n", pid);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        let pid = assert.capture(r"\d+").remove(0);
+
+        assert!(start.elapsed() < Duration::from_secs(30));
+
+        crate::guard::assert_exits(pid.parse().unwrap());
+    }
+
+    #[test]
+    fn test_run_tee() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs tee: "1"
+
+                #include <stdio.h>
+
+                int main() {
+                    printf("step 1\nstep 2");
+                    fprintf(stderr, "warning\n");
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("step 1\nstep 2")
+        .stderr("warning\n");
+    }
+
+    #[test]
+    fn test_run_redact() {
+        let failure_output = |program: &str| {
+            let mut assert = run(Language::C, program).unwrap();
+
+            panic_message(|| {
+                assert.success();
+            })
+        };
+
+        let output = failure_output(
+            r#"
+                #inline_c_rs API_TOKEN: "s3cr3t"
+                #inline_c_rs DB_HOST: "localhost"
+                #inline_c_rs redact: "DB_*"
+
+                int main() { return 1; }
+            "#,
+        );
+
+        assert!(output.contains("API_TOKEN=<redacted>"));
+        assert!(output.contains("DB_HOST=<redacted>"));
+        assert!(!output.contains("s3cr3t"));
+        assert!(!output.contains("localhost"));
+
+        let output = failure_output(
+            r#"
+                #inline_c_rs quiet: "1"
+
+                int main() { return 1; }
+            "#,
+        );
+
+        assert!(!output.contains("command="));
+    }
+
+    #[test]
+    fn test_run_env_value() {
+        // The variables of the process are changed, so this test runs
+        // alone.
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            child_test_stderr("run::tests::test_run_env_value");
+
+            return;
+        }
+
+        env::set_var("INLINE_C_TEST_ENV_VALUE", "s3cr3t");
+
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs_run API_VALUE: env("INLINE_C_TEST_ENV_VALUE")
+
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main() {
+                    printf("%s", getenv("API_VALUE"));
+
+                    return 1;
+                }
+            "#,
+        )
+        .unwrap();
+
+        assert!(!assert
+            .artifacts()
+            .sources()
+            .iter()
+            .any(|source| fs::read_to_string(source).unwrap().contains("s3cr3t")));
+
+        let output = panic_message(|| {
+            assert.success();
+        });
+
+        // The command is redacted, but not the output of the program.
+        let (command, program_output) = output.split_once("stdout=").unwrap();
+
+        assert!(command.contains("API_VALUE=<redacted>"), "{}", output);
+        assert!(!command.contains("s3cr3t"), "{}", output);
+        assert!(program_output.contains("s3cr3t"), "{}", output);
+
+        assert!(collect_environment_variables(
+            "#inline_c_rs FOO: env(\"INLINE_C_TEST_UNDEFINED\")\n",
+            ""
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_cwd() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs cwd: "src"
+
+                #include <stdio.h>
+
+                int main() {
+                    FILE *file = fopen("run.rs", "r");
+
+                    if (file == NULL) {
+                        return 1;
+                    }
+
+                    fclose(file);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+
+        assert!(run(
+            Language::C,
+            "#inline_c_rs cwd: \"missing\"\nint main() { return 0; }"
+        )
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_umask() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("file.txt");
+
+        run(
+            Language::C,
+            &format!(
+                r#"
+                    #inline_c_rs umask: "027"
+
+                    #include <stdio.h>
+
+                    int main() {{
+                        FILE *file = fopen("{}", "w");
+
+                        return file == NULL || fclose(file) != 0;
+                    }}
+                "#,
+                path.display()
+            ),
+        )
+        .unwrap()
+        .created_file(&path, has_mode(0o640));
+
+        assert!(run(
+            Language::C,
+            "#inline_c_rs umask: \"999\"\nint main() { return 0; }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_stdin_file() {
+        let mut stdin_file = tempfile::NamedTempFile::new().unwrap();
+        stdin_file.write_all(&[0, 1, 2, 0xff]).unwrap();
+
+        run(
+            Language::C,
+            &format!(
+                r#"
+                    #inline_c_rs stdin_file: "{}"
+
+                    #include <stdio.h>
+
+                    int main() {{
+                        int byte;
+                        int sum = 0;
+                        int count = 0;
+
+                        while ((byte = getchar()) != EOF) {{
+                            sum += byte;
+                            count += 1;
+                        }}
+
+                        printf("%d bytes, sum %d", count, sum);
+
+                        return 0;
+                    }}
+                "#,
+                stdin_file.path().display().to_string().replace('\\', "/")
+            ),
+        )
+        .unwrap()
+        .success()
+        .stdout("4 bytes, sum 258");
+    }
 
+    #[cfg(unix)]
     #[test]
-    fn test_run_c() {
+    fn test_run_concurrently() {
+        let program = r#"
+            #include <stdio.h>
+
+            int main() {
+                // Fails if another instance has created the file in
+                // the same directory.
+                FILE *file = fopen("lock", "wx");
+
+                if (file == NULL) {
+                    return 1;
+                }
+
+                fclose(file);
+
+                return 0;
+            }
+        "#;
+
+        run(Language::C, program)
+            .unwrap()
+            .run_concurrently_in_distinct_dirs(4);
+
+        let working_dir = tempfile::tempdir().unwrap();
+        let mut assert = run(
+            Language::C,
+            &format!(
+                "#inline_c_rs cwd: \"{}\"\n{}",
+                working_dir.path().display(),
+                program
+            ),
+        )
+        .unwrap();
+
+        panic_message(|| {
+            assert.run_concurrently(4);
+        });
+    }
+
+    #[test]
+    fn test_run_evaluate() {
+        let report = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    printf("a\nb\n");
+
+                    return 2;
+                }
+            "#,
+        )
+        .unwrap()
+        .evaluate(vec![
+            Check::Failure,
+            Check::Code(3),
+            Check::Stdout(String::from("a\nc\n")),
+            Check::StdoutContains(String::from("b")),
+        ]);
+
+        assert!(!report.is_success());
+        assert_eq!(
+            report
+                .findings()
+                .iter()
+                .map(|finding| finding.check.clone())
+                .collect::<Vec<_>>(),
+            [
+                Some(Check::Code(3)),
+                Some(Check::Stdout(String::from("a\nc\n")))
+            ]
+        );
+        assert!(report.findings()[1]
+            .message
+            .starts_with("The standard output differs at line 2: expected `c`, got `b`"));
+        assert_eq!(report.output().unwrap().status.code(), Some(2));
+
+        let report = run(Language::C, "int main() { return undefined; }")
+            .unwrap()
+            .evaluate(vec![Check::Success]);
+
+        assert!(report.output().is_none());
+        assert_eq!(report.findings()[0].check, None);
+    }
+
+    #[test]
+    fn test_run_program_env() {
+        // The variables of the process are changed, so this test runs
+        // alone.
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            child_test_stderr("run::tests::test_run_program_env");
+
+            return;
+        }
+
+        env::set_var("INLINE_C_RS_TEST_RUN_PROGRAM_ENV", "meta");
+
+        let mut assert = run(
+            Language::C,
+            r#"
+                #inline_c_rs FOO: "bar"
+                #inline_c_rs_run BAZ: "qux"
+
+                int main() { return 0; }
+            "#,
+        )
+        .unwrap();
+
+        env::remove_var("INLINE_C_RS_TEST_RUN_PROGRAM_ENV");
+
+        let env = assert.program_env();
+
+        assert_eq!(env["FOO"], "bar");
+        assert_eq!(env["BAZ"], "qux");
+        assert_eq!(env["TEST_RUN_PROGRAM_ENV"], "meta");
+
+        assert.has_env_var("FOO", predicate::eq("bar"));
+
+        let message = panic_message(|| {
+            assert.has_env_var("MISSING", predicate::always());
+        });
+
+        assert!(message.contains("`MISSING` is not given to the program"));
+        assert!(message.contains("BAZ"));
+    }
+
+    #[test]
+    fn test_run_invalid_directive() {
+        let error = |program| {
+            run(Language::C, program)
+                .err()
+                .unwrap()
+                .downcast::<DirectiveError>()
+                .unwrap()
+        };
+
+        let missing_quote = error(
+            r#"
+                #inline_c_rs FOO: "bar
+
+                int main() { return 0; }
+            "#,
+        );
+
+        assert_eq!(missing_quote.line, 2);
+        assert_eq!(missing_quote.directive, r#"#inline_c_rs FOO: "bar"#);
+
+        let invalid_name = error(
+            r#"
+                #include <stdlib.h>
+                #inline_c_rs_run define ANSWER: "42"
+
+                int main() { return 0; }
+            "#,
+        );
+
+        assert_eq!(invalid_name.line, 3);
+        assert!(invalid_name
+            .reason
+            .contains("`define ANSWER` is not a valid name"));
+
+        // Any other name is a variable, and a typo is only a warning.
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs http_proxy: "http://localhost:3128"
+                #inline_c_rs_run timzone: "UTC"
+                #inline_c_rs allowed_directives: "timzone"
+
+                #include <stdlib.h>
+                #include <string.h>
+
+                int main() {
+                    return strcmp(getenv("http_proxy"), "http://localhost:3128") != 0
+                        || strcmp(getenv("timzone"), "UTC") != 0
+                        || getenv("allowed_directives") != NULL;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_run_custom_directive() {
+        use crate::{register_directive, DirectiveHandler};
+
+        struct Library;
+
+        impl DirectiveHandler for Library {
+            fn handle(
+                &self,
+                value: &str,
+                context: &mut DirectiveContext,
+            ) -> Result<(), Box<dyn Error>> {
+                if value == "missing" {
+                    return Err("no such library".into());
+                }
+
+                context
+                    .compile_command()
+                    .arg(format!("-DLIBRARY=\"{}\"", value));
+                context
+                    .program_command()
+                    .arg("--verbose")
+                    .env("LIBRARY_PATH", "/opt/lib");
+
+                Ok(())
+            }
+        }
+
+        register_directive("test_library", Box::new(Library));
+
         run(
             Language::C,
+            r#"
+                #inline_c_rs test_library: "zlib"
+
+                #include <stdio.h>
+                #include <stdlib.h>
+
+                int main(int argc, char **argv) {
+                    printf("%s %s %s", LIBRARY, argv[1], getenv("LIBRARY_PATH"));
+
+                    return argc != 2 || getenv("test_library") != NULL;
+                }
+            "#,
+        )
+        .unwrap()
+        .success()
+        .stdout("zlib --verbose /opt/lib");
+
+        let error = run(
+            Language::C,
+            r#"
+                #inline_c_rs test_library: "missing"
+
+                int main() { return 0; }
+            "#,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "The `test_library` directive has failed: no such library"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_main_of_a_shared_object() {
+        let source = String::from(
             r#"
                 #include <stdio.h>
 
+                int helper(int x) {
+                    return x + 1;
+                }
+
+                INLINE_C_EXPORT int add_one(int x) {
+                    return helper(x);
+                }
+
                 int main() {
-                    printf("Hello, World!\n");
+                    printf("%d", add_one(41));
+
+                    return 0;
+                }
+            "#,
+        );
+        let config = RunConfig {
+            source,
+            flags: vec![String::from("-fvisibility=hidden")],
+            ..Default::default()
+        };
+
+        run_with(config.clone()).unwrap().success().stdout("42");
+
+        // The name of the exported function is mangled in C++.
+        for (language, symbol) in [(Language::C, "add_one"), (Language::Cxx, "_Z7add_onei")] {
+            run_with(RunConfig {
+                language,
+                output_kind: OutputKind::SharedObject,
+                ..config.clone()
+            })
+            .unwrap()
+            .exports_exactly([symbol])
+            .success()
+            .stdout("42");
+        }
+    }
+
+    #[test]
+    fn test_run_special_directives_are_consumed() {
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs HOST: "example.com"
+                #inline_c_rs TARGET: "production"
+                #inline_c_rs standard: "c11"
+
+                #include <stdlib.h>
+                #include <string.h>
+
+                int main() {
+                    return strcmp(getenv("HOST"), "example.com") != 0
+                        || strcmp(getenv("TARGET"), "production") != 0
+                        || getenv("standard") != NULL;
+                }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_run_invalid_file_check() {
+        let mut assert = run(
+            Language::C,
+            r#"
+                // CHECK-NEXT: foo
+                int main() { return 0; }
+            "#,
+        )
+        .unwrap();
+
+        // The `CHECK` directives are parsed by `file_check` only.
+        assert.success();
+
+        let message = panic_message(|| {
+            assert.file_check();
+        });
+
+        assert!(
+            message.contains("Invalid file check: Found a `CHECK-NEXT` directive"),
+            "{}",
+            message
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_sanitizer_logs() {
+        let program = r#"
+            #inline_c_rs sanitizer: "undefined"
+            #include <limits.h>
+
+            int main(int argc, char** argv) {
+                (void) argv;
+                int x = INT_MAX;
+
+                return (x + argc) == 0;
+            }
+        "#;
+        let overflow = "signed integer overflow";
+
+        // UndefinedBehaviorSanitizer does not halt the program.
+        run(Language::C, program)
+            .unwrap()
+            .success()
+            .stderr(predicate::str::contains(overflow));
+
+        run(Language::C, program)
+            .unwrap()
+            .spawn()
+            .wait()
+            .success()
+            .stderr(predicate::str::contains(overflow));
+
+        let report = run(Language::C, program)
+            .unwrap()
+            .evaluate(vec![Check::Success, Check::NoSanitizerFindings]);
+
+        assert_eq!(report.findings().len(), 1);
+        assert_eq!(report.findings()[0].check, Some(Check::NoSanitizerFindings));
+        assert!(report.findings()[0].message.contains(overflow));
+
+        let mut assert = run(Language::C, program).unwrap();
+        let message = panic_message(|| {
+            assert.run_concurrently(2);
+        });
+
+        assert!(message.contains("2 finding(s)"), "{}", message);
+
+        // Without sanitizer, `NoSanitizerFindings` cannot pass.
+        let report = run(Language::C, "int main() { return 0; }")
+            .unwrap()
+            .evaluate(vec![Check::NoSanitizerFindings]);
+
+        assert!(report.findings()[0].message.contains("No sanitizer"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_process_entry() {
+        // `main` is called with its arguments, in C23 too.
+        run_in_process(
+            Language::C,
+            r#"
+                #inline_c_rs standard: "c23"
+                #include <stdio.h>
+
+                int main(int argc, char** argv) {
+                    printf("%d %s", argc, argv[1]);
 
                     return 0;
                 }
             "#,
+            None,
+            &[],
         )
         .unwrap()
+        .arg("foo")
+        .assert()
         .success()
-        .stdout(predicate::eq("Hello, World!\n").normalize());
+        .stdout("2 foo");
+
+        // A library without `main` loads, on macOS too.
+        run_in_process(Language::C, "int answer(void) { return 42; }", None, &[])
+            .unwrap()
+            .assert()
+            .failure()
+            .stderr("The program has no `main` function\n");
+    }
+
+    /// Run the test `name` alone in a child process, and return its
+    /// standard error, e.g. to read what is printed with `eprintln!`.
+    /// The child process has the `INLINE_C_RS_TEST_CHILD` variable.
+    fn child_test_stderr(name: &str) -> String {
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", name, "--nocapture", "--test-threads=1"])
+            .env("INLINE_C_RS_TEST_CHILD", "1")
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{:?}", output);
+        // A mistyped name would run no test at all.
+        assert!(
+            String::from_utf8_lossy(&output.stdout).contains(" 1 passed;"),
+            "{:?}",
+            output
+        );
+
+        String::from_utf8_lossy(&output.stderr).into_owned()
     }
 
     #[test]
-    fn test_run_cxx() {
+    fn test_run_verbose() {
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            let stderr = child_test_stderr("run::tests::test_run_verbose");
+            let lines = stderr.lines().collect::<Vec<_>>();
+
+            let source = lines
+                .iter()
+                .position(|line| line.starts_with("inline-c: source `") && line.ends_with(".c`"));
+            let compile = lines
+                .iter()
+                .position(|line| line.starts_with("inline-c: compile "));
+            let run = lines
+                .iter()
+                .position(|line| line.starts_with("inline-c: run "));
+
+            assert!(source.is_some(), "{}", stderr);
+            assert!(source < compile, "{}", stderr);
+            assert!(compile < run, "{}", stderr);
+
+            return;
+        }
+
         run(
-            Language::Cxx,
+            Language::C,
+            r#"
+                #inline_c_rs verbose: "1"
+
+                int main() { return 0; }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_run_verbose_redaction() {
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            let stderr = child_test_stderr("run::tests::test_run_verbose_redaction");
+
+            assert!(stderr.contains("inline-c: compile "), "{}", stderr);
+            assert!(stderr.contains("API_TOKEN=<redacted>"), "{}", stderr);
+            assert!(!stderr.contains("s3cr3t"), "{}", stderr);
+
+            return;
+        }
+
+        run(
+            Language::C,
+            r#"
+                #inline_c_rs verbose: "1"
+                #inline_c_rs API_TOKEN: "s3cr3t"
+
+                int main() { return 0; }
+            "#,
+        )
+        .unwrap()
+        .success();
+    }
+
+    #[test]
+    fn test_run_footprint() {
+        if env::var_os("INLINE_C_RS_TEST_CHILD").is_none() {
+            let stderr = child_test_stderr("run::tests::test_run_footprint");
+
+            assert!(
+                stderr.contains("inline-c: 1 program(s) compiled, "),
+                "{}",
+                stderr
+            );
+            assert!(stderr.contains(", 0 file(s) retained (0 B)"), "{}", stderr);
+
+            return;
+        }
+
+        // The counters are global to the process, which runs this test
+        // alone.
+        assert_eq!(crate::footprint(), crate::Footprint::default());
+
+        let assert = run(Language::C, "int main() { return 0; }").unwrap();
+        let (files, bytes) = assert
+            .artifacts()
+            .iter()
+            .map(|artifact| fs::metadata(artifact).unwrap().len())
+            .fold((0, 0), |(files, bytes), size| (files + 1, bytes + size));
+
+        assert!(files >= 2, "{:?}", assert.artifacts());
+        assert_eq!(
+            crate::footprint(),
+            crate::Footprint {
+                programs: 1,
+                files_written: files,
+                bytes_written: bytes,
+                files_retained: files,
+                bytes_retained: bytes,
+            }
+        );
+
+        drop(assert);
+
+        let footprint = crate::report();
+
+        assert_eq!(footprint.files_written, files);
+        assert_eq!(footprint.bytes_written, bytes);
+        assert_eq!(footprint.files_retained, 0);
+        assert_eq!(footprint.bytes_retained, 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_same_as_signal() {
+        let reference = run(
+            Language::C,
+            r#"
+                #include <signal.h>
+
+                int main() {
+                    raise(SIGSEGV);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+
+        let message = panic_message(|| {
+            run(
+                Language::C,
+                r#"
+                    #include <signal.h>
+
+                    int main() {
+                        raise(SIGABRT);
+
+                        return 0;
+                    }
+                "#,
+            )
+            .unwrap()
+            .same_as(&reference);
+        });
+
+        assert!(message.contains("Exit statuses differ"), "{}", message);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_process_concurrently() {
+        // The children do not inherit the pipes of the programs running
+        // at the same time, so that the standard input of the first one
+        // is closed, and the second one has no file descriptor open but
+        // its standard streams.
+        let echo = thread::spawn(|| {
+            let mut assert = run_in_process(
+                Language::C,
+                r#"
+                    #include <stdio.h>
+
+                    int main() {
+                        int c;
+
+                        while ((c = getchar()) != EOF) {
+                            putchar(c);
+                        }
+
+                        return 0;
+                    }
+                "#,
+                None,
+                &[],
+            )
+            .unwrap();
+
+            for _ in 0..20 {
+                assert.stdin("foo\n").assert().success().stdout("foo\n");
+            }
+        });
+
+        let mut assert = run_in_process(
+            Language::C,
             r#"
                 #include <stdio.h>
+                #include <fcntl.h>
+                #include <unistd.h>
 
                 int main() {
-                    printf("Hello, World!\n");
+                    int open = 0;
+
+                    for (int fd = 3; fd < 1024; ++fd) {
+                        open += fcntl(fd, F_GETFD) != -1;
+                    }
+
+                    printf("%d", open);
+                    usleep(10000);
 
                     return 0;
                 }
             "#,
+            None,
+            &[],
         )
-        .unwrap()
-        .success()
-        .stdout(predicate::eq("Hello, World!\n").normalize());
+        .unwrap();
+
+        for _ in 0..20 {
+            assert.assert().success().stdout("0");
+        }
+
+        echo.join().unwrap();
     }
 }
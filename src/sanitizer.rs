@@ -0,0 +1,401 @@
+//! Sanitizers (AddressSanitizer, ThreadSanitizer etc.) support.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::HashMap, env, error::Error, fs, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Sanitizer {
+    Address,
+    Undefined,
+    Thread,
+    Leak,
+    Memory,
+}
+
+impl Sanitizer {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Address => "address",
+            Self::Undefined => "undefined",
+            Self::Thread => "thread",
+            Self::Leak => "leak",
+            Self::Memory => "memory",
+        }
+    }
+
+    /// The environment variable holding the options of the runtime.
+    fn options_variable(&self) -> &'static str {
+        match self {
+            Self::Address => "ASAN_OPTIONS",
+            Self::Undefined => "UBSAN_OPTIONS",
+            Self::Thread => "TSAN_OPTIONS",
+            Self::Leak => "LSAN_OPTIONS",
+            Self::Memory => "MSAN_OPTIONS",
+        }
+    }
+}
+
+/// A finding of a sanitizer, see
+/// [`Assert::sanitizer_findings`](crate::Assert::sanitizer_findings).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanitizerFinding {
+    /// The sanitizer, e.g. `AddressSanitizer`.
+    pub sanitizer: String,
+    /// The kind of the finding, e.g. `heap-buffer-overflow`, `data
+    /// race` or `signed integer overflow`.
+    pub kind: String,
+    /// The whole report, with the stack traces.
+    pub report: String,
+}
+
+/// The sanitizers enabled for a program, from the special
+/// `sanitizer` directive, e.g. `address,undefined`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Sanitizers {
+    sanitizers: Vec<Sanitizer>,
+}
+
+impl Sanitizers {
+    pub(crate) fn parse(sanitizers: &str) -> Result<Self, Box<dyn Error>> {
+        let sanitizers = sanitizers
+            .split(',')
+            .map(str::trim)
+            .filter(|sanitizer| !sanitizer.is_empty())
+            .map(|sanitizer| match sanitizer {
+                "address" => Ok(Sanitizer::Address),
+                "undefined" => Ok(Sanitizer::Undefined),
+                "thread" => Ok(Sanitizer::Thread),
+                "leak" => Ok(Sanitizer::Leak),
+                "memory" => Ok(Sanitizer::Memory),
+                sanitizer => Err(format!(
+                    "Invalid sanitizer `{}`, expected `address`, `undefined`, `thread`, \
+                     `leak` or `memory`",
+                    sanitizer
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { sanitizers })
+    }
+
+    pub(crate) fn contains(&self, sanitizer: Sanitizer) -> bool {
+        self.sanitizers.contains(&sanitizer)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sanitizers.is_empty()
+    }
+
+    /// The options of the runtimes, e.g. `ASAN_OPTIONS`: the reports
+    /// are written in `log_dir`, one file per sanitizer and process.
+    /// The options already in `variables`, or in the environment, come
+    /// last, so that they take precedence.
+    pub(crate) fn runtime_options(
+        &self,
+        log_dir: &Path,
+        variables: &HashMap<String, String>,
+    ) -> Vec<(String, String)> {
+        self.sanitizers
+            .iter()
+            .map(|sanitizer| {
+                let variable = sanitizer.options_variable();
+                // Quoted, for the `:` of the Windows paths.
+                let mut options = vec![format!(
+                    "log_path='{}'",
+                    log_dir.join(sanitizer.name()).display()
+                )];
+
+                if let Sanitizer::Undefined = sanitizer {
+                    options.push("print_stacktrace=1".to_string());
+                }
+
+                if let Some(user_options) = variables
+                    .get(variable)
+                    .cloned()
+                    .or_else(|| env::var(variable).ok())
+                    .filter(|user_options| !user_options.is_empty())
+                {
+                    options.push(user_options);
+                }
+
+                (variable.to_string(), options.join(":"))
+            })
+            .collect()
+    }
+
+    /// The compiler flags enabling the sanitizers.
+    pub(crate) fn compiler_flags(&self, msvc: bool) -> Result<Vec<String>, Box<dyn Error>> {
+        if self.sanitizers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if msvc {
+            return match self.sanitizers.as_slice() {
+                [Sanitizer::Address] => {
+                    Ok(vec!["-fsanitize=address".to_string(), "-Zi".to_string()])
+                }
+                _ => Err("Only the `address` sanitizer is supported with MSVC".into()),
+            };
+        }
+
+        let names = self
+            .sanitizers
+            .iter()
+            .map(Sanitizer::name)
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(vec![
+            format!("-fsanitize={}", names),
+            "-fno-omit-frame-pointer".to_string(),
+            "-g".to_string(),
+        ])
+    }
+}
+
+/// Read, and remove, the reports written in `log_dir` by the runtimes
+/// of the sanitizers, see [`Sanitizers::runtime_options`].
+pub(crate) fn take_logs(log_dir: &Path) -> String {
+    let mut paths = fs::read_dir(log_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    paths
+        .iter()
+        .filter_map(|path| {
+            let log = fs::read_to_string(path).ok();
+            let _ = fs::remove_file(path);
+
+            log
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Extract the findings of the sanitizers from their `output`, i.e.
+/// the standard error of the program and the logs.
+pub(crate) fn findings(output: &str) -> Vec<SanitizerFinding> {
+    lazy_static! {
+        static ref HEADER: Regex = Regex::new(
+            r"(?m)^(?:==\d+==)?(?:ERROR|WARNING): (?P<sanitizer>\w+Sanitizer): (?P<kind>.+?)(?: on .*| \(.*)?\s*$"
+        )
+        .unwrap();
+        static ref RUNTIME_ERROR: Regex =
+            Regex::new(r"(?m)^\S.*?: runtime error: (?P<kind>[^:\r\n]+)").unwrap();
+    }
+
+    let mut headers = HEADER
+        .captures_iter(output)
+        .map(|captures| {
+            (
+                captures.get(0).unwrap().start(),
+                captures["sanitizer"].to_string(),
+                captures["kind"].to_string(),
+            )
+        })
+        .chain(RUNTIME_ERROR.captures_iter(output).map(|captures| {
+            (
+                captures.get(0).unwrap().start(),
+                "UndefinedBehaviorSanitizer".to_string(),
+                captures["kind"].trim().to_string(),
+            )
+        }))
+        .collect::<Vec<_>>();
+    headers.sort_by_key(|(start, _, _)| *start);
+
+    headers
+        .iter()
+        .enumerate()
+        .map(|(nth, (start, sanitizer, kind))| {
+            let end = headers.get(nth + 1).map_or(output.len(), |next| next.0);
+            let report = output[*start..end]
+                .lines()
+                .filter(|line| !line.starts_with("=================="))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            SanitizerFinding {
+                sanitizer: sanitizer.clone(),
+                kind: kind.clone(),
+                report: report.trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Extract the data race reports of ThreadSanitizer from `stderr`.
+pub(crate) fn data_races(stderr: &str) -> Vec<String> {
+    const HEADER: &str = "WARNING: ThreadSanitizer: data race";
+
+    stderr
+        .split("==================")
+        .map(str::trim)
+        .filter(|report| report.starts_with(HEADER))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// The `LSAN_OPTIONS` of the program, i.e. its `options` if any, with
+/// the `suppressions` file.
+pub(crate) fn lsan_options(options: Option<&str>, suppressions: &Path) -> String {
+    let suppressions = format!("suppressions={}", suppressions.display());
+
+    match options {
+        Some(options) if !options.is_empty() => format!("{}:{}", options, suppressions),
+        _ => suppressions,
+    }
+}
+
+/// Extract the leak report of LeakSanitizer, or the leak summary of
+/// Valgrind if definitely or indirectly lost bytes remain, from
+/// `stderr`.
+pub(crate) fn leak_report(stderr: &str) -> Option<String> {
+    const LSAN_HEADER: &str = "ERROR: LeakSanitizer: detected memory leaks";
+    const VALGRIND_HEADER: &str = "LEAK SUMMARY:";
+
+    if let Some(start) = stderr.find(LSAN_HEADER) {
+        let report = &stderr[start..];
+        let end = report
+            .find("SUMMARY: ")
+            .and_then(|summary| report[summary..].find('\n').map(|end| summary + end))
+            .unwrap_or(report.len());
+
+        return Some(report[..end].to_string());
+    }
+
+    // The summary ends with an empty line, once the `==<pid>==`
+    // prefix is removed.
+    let start = stderr.find(VALGRIND_HEADER)?;
+    let summary = stderr[start..]
+        .lines()
+        .take_while(|line| !line.trim_start_matches(|c| c != ' ').trim().is_empty())
+        .collect::<Vec<_>>();
+    let has_leaks = summary.iter().any(|line| {
+        ["definitely lost:", "indirectly lost:"].iter().any(|kind| {
+            line.split(kind)
+                .nth(1)
+                .is_some_and(|bytes| !bytes.trim_start().starts_with("0 bytes"))
+        })
+    });
+
+    has_leaks.then(|| summary.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitizers_parse() {
+        let sanitizers = Sanitizers::parse("address, undefined").unwrap();
+
+        assert!(sanitizers.contains(Sanitizer::Address));
+        assert!(sanitizers.contains(Sanitizer::Undefined));
+        assert!(!sanitizers.contains(Sanitizer::Thread));
+        assert!(Sanitizers::parse("adress").is_err());
+        assert!(sanitizers.compiler_flags(true).is_err());
+    }
+
+    #[test]
+    fn test_data_races() {
+        let stderr = "==================\n\
+                      WARNING: ThreadSanitizer: data race (pid=1)\n  Write of size 4\n\
+                      ==================\n\
+                      ThreadSanitizer: reported 1 warnings\n";
+
+        assert_eq!(
+            data_races(stderr),
+            vec!["WARNING: ThreadSanitizer: data race (pid=1)\n  Write of size 4"]
+        );
+    }
+
+    #[test]
+    fn test_runtime_options() {
+        let sanitizers = Sanitizers::parse("undefined").unwrap();
+        let mut variables = HashMap::new();
+        variables.insert("UBSAN_OPTIONS".to_string(), "halt_on_error=0".to_string());
+
+        assert_eq!(
+            sanitizers.runtime_options(Path::new("/tmp/logs"), &variables),
+            [(
+                "UBSAN_OPTIONS".to_string(),
+                "log_path='/tmp/logs/undefined':print_stacktrace=1:halt_on_error=0".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_findings() {
+        let output = "=================================================================\n\
+                      ==1==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x1 at pc 0x2\n\
+                      READ of size 4 at 0x1 thread T0\n\
+                      ==================\n\
+                      WARNING: ThreadSanitizer: data race (pid=1)\n\
+                      \x20 Write of size 4\n\
+                      ==================\n\
+                      foo.c:2:46: runtime error: signed integer overflow: 1 + 1 cannot be represented\n";
+
+        let findings = findings(output);
+
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.sanitizer.as_str(), finding.kind.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("AddressSanitizer", "heap-buffer-overflow"),
+                ("ThreadSanitizer", "data race"),
+                ("UndefinedBehaviorSanitizer", "signed integer overflow")
+            ]
+        );
+        assert_eq!(
+            findings[0].report,
+            "==1==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x1 at pc 0x2\n\
+             READ of size 4 at 0x1 thread T0"
+        );
+    }
+
+    #[test]
+    fn test_leak_report() {
+        assert_eq!(
+            lsan_options(Some("verbosity=1"), Path::new("/tmp/lsan.supp")),
+            "verbosity=1:suppressions=/tmp/lsan.supp"
+        );
+
+        let stderr = "\n=================================================================\n\
+                      ==1==ERROR: LeakSanitizer: detected memory leaks\n\n\
+                      Direct leak of 10 byte(s) in 1 object(s) allocated from:\n\
+                      \x20   #0 0x1 in malloc\n\n\
+                      SUMMARY: AddressSanitizer: 10 byte(s) leaked in 1 allocation(s).\n";
+
+        assert_eq!(
+            leak_report(stderr).unwrap(),
+            "ERROR: LeakSanitizer: detected memory leaks\n\n\
+             Direct leak of 10 byte(s) in 1 object(s) allocated from:\n\
+             \x20   #0 0x1 in malloc\n\n\
+             SUMMARY: AddressSanitizer: 10 byte(s) leaked in 1 allocation(s)."
+        );
+
+        let valgrind = |definitely_lost: &str| {
+            format!(
+                "==1== LEAK SUMMARY:\n\
+                 ==1==    definitely lost: {} bytes in 1 blocks\n\
+                 ==1==    indirectly lost: 0 bytes in 0 blocks\n\
+                 ==1==         suppressed: 72,704 bytes in 1 blocks\n\
+                 ==1== \n\
+                 ==1== ERROR SUMMARY: 0 errors from 0 contexts\n",
+                definitely_lost
+            )
+        };
+
+        assert!(leak_report(&valgrind("0")).is_none());
+        assert_eq!(leak_report(&valgrind("10")).unwrap().lines().count(), 4);
+    }
+}
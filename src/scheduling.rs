@@ -0,0 +1,175 @@
+//! Scheduling controls of the program (niceness, scheduling policy
+//! and CPU affinity), so that timings are less noisy.
+//!
+//! They are applied by running the program through `nice`, `chrt`
+//! and `taskset`.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{error::Error, ffi::OsString};
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Scheduling {
+    nice: Option<i8>,
+    // The `chrt` flag of the policy, and the priority.
+    policy: Option<(&'static str, u8)>,
+    cpu_affinity: Option<String>,
+}
+
+impl Scheduling {
+    /// Read the `nice`, `scheduler` and `cpu_affinity` directives.
+    pub(crate) fn parse(
+        nice: Option<&str>,
+        scheduler: Option<&str>,
+        cpu_affinity: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        lazy_static! {
+            static ref CPU_LIST: Regex = Regex::new(r"^\d+(-\d+)?(,\d+(-\d+)?)*$").unwrap();
+        }
+
+        let nice = nice
+            .map(|nice| match nice.trim().parse::<i8>() {
+                Ok(nice) if (-20..=19).contains(&nice) => Ok(nice),
+                _ => Err(format!(
+                    "Invalid `nice` value `{}`, expected an integer between -20 and 19",
+                    nice
+                )),
+            })
+            .transpose()?;
+
+        let policy = scheduler
+            .map(|scheduler| {
+                let (policy, priority) = match scheduler.split_once(':') {
+                    Some((policy, priority)) => (policy, Some(priority)),
+                    None => (scheduler, None),
+                };
+
+                let (flag, realtime) = match policy.trim() {
+                    "other" => ("--other", false),
+                    "batch" => ("--batch", false),
+                    "idle" => ("--idle", false),
+                    "fifo" => ("--fifo", true),
+                    "rr" => ("--rr", true),
+                    _ => {
+                        return Err(format!(
+                            "Invalid `scheduler` value `{}`, expected `other`, `batch`, \
+                             `idle`, `fifo[:priority]` or `rr[:priority]`",
+                            scheduler
+                        ))
+                    }
+                };
+
+                let priority = match (priority, realtime) {
+                    (None, true) => 1,
+                    (None, false) => 0,
+                    (Some(priority), true) => match priority.trim().parse() {
+                        Ok(priority) if (1..=99).contains(&priority) => priority,
+                        _ => {
+                            return Err(format!(
+                                "Invalid `scheduler` priority `{}`, expected an integer \
+                                 between 1 and 99",
+                                priority
+                            ))
+                        }
+                    },
+                    (Some(_), false) => {
+                        return Err(format!(
+                            "The `{}` scheduling policy has no priority",
+                            policy
+                        ))
+                    }
+                };
+
+                Ok((flag, priority))
+            })
+            .transpose()?;
+
+        let cpu_affinity = cpu_affinity
+            .map(|cpu_affinity| {
+                let cpu_affinity = cpu_affinity.replace(' ', "");
+
+                if CPU_LIST.is_match(&cpu_affinity) {
+                    Ok(cpu_affinity)
+                } else {
+                    Err(format!(
+                        "Invalid `cpu_affinity` value `{}`, expected a list of CPUs, \
+                         e.g. `0,2-3`",
+                        cpu_affinity
+                    ))
+                }
+            })
+            .transpose()?;
+
+        if (policy.is_some() || cpu_affinity.is_some()) && !cfg!(target_os = "linux") {
+            return Err("`scheduler` and `cpu_affinity` are only supported on Linux".into());
+        }
+
+        if nice.is_some() && !cfg!(unix) {
+            return Err("`nice` is only supported on Unix".into());
+        }
+
+        Ok(Self {
+            nice,
+            policy,
+            cpu_affinity,
+        })
+    }
+
+    /// The programs to run the program through.
+    pub(crate) fn launcher(&self) -> Vec<OsString> {
+        let mut launcher = Vec::new();
+
+        if let Some(nice) = self.nice {
+            launcher.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+
+        if let Some((flag, priority)) = self.policy {
+            launcher.extend(["chrt".to_string(), flag.to_string(), priority.to_string()]);
+        }
+
+        if let Some(cpu_affinity) = &self.cpu_affinity {
+            launcher.extend([
+                "taskset".to_string(),
+                "-c".to_string(),
+                cpu_affinity.clone(),
+            ]);
+        }
+
+        launcher.into_iter().map(OsString::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Scheduling::parse(None, None, None).unwrap(),
+            Scheduling::default()
+        );
+        assert!(Scheduling::parse(Some("20"), None, None).is_err());
+        assert!(Scheduling::parse(None, Some("fifo:100"), None).is_err());
+        assert!(Scheduling::parse(None, Some("batch:1"), None).is_err());
+        assert!(Scheduling::parse(None, Some("deadline"), None).is_err());
+        assert!(Scheduling::parse(None, None, Some("0,a")).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_launcher() {
+        assert_eq!(
+            Scheduling::parse(Some("10"), Some("rr:5"), Some("0, 2-3"))
+                .unwrap()
+                .launcher(),
+            ["nice", "-n", "10", "chrt", "--rr", "5", "taskset", "-c", "0,2-3"]
+        );
+        assert_eq!(
+            Scheduling::parse(None, Some("batch"), None)
+                .unwrap()
+                .launcher(),
+            ["chrt", "--batch", "0"]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Assert the output of a running program incrementally, e.g. for
+//! programs that print some progress, then block.
+
+use crate::{guard::ProcessGuard, sanitizer};
+use predicates::Predicate;
+use std::{
+    io::{self, prelude::*, BufReader},
+    path::PathBuf,
+    process::{ChildStdin, Command, Output, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// A running program, created by [`Assert::spawn`](crate::Assert::spawn).
+/// The program, and the processes it has spawned, are killed when
+/// `Spawned` is dropped, unless [`Assert::kill_on_drop`] is off.
+///
+/// [`Assert::kill_on_drop`]: crate::Assert::kill_on_drop
+pub struct Spawned {
+    guard: ProcessGuard,
+    stdin: Option<ChildStdin>,
+    // Write the initial standard input, then give the pipe back.
+    stdin_writer: Option<JoinHandle<Option<ChildStdin>>>,
+    lines: Receiver<Vec<u8>>,
+    stderr: Option<JoinHandle<io::Result<Vec<u8>>>>,
+    // All the lines received so far.
+    stdout: Vec<u8>,
+    // The directory of the reports of the sanitizers.
+    sanitizer_logs: Option<PathBuf>,
+}
+
+impl Spawned {
+    pub(crate) fn new(
+        mut command: Command,
+        stdin: Option<&[u8]>,
+        kill_on_drop: bool,
+        sanitizer_logs: Option<PathBuf>,
+    ) -> io::Result<Self> {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut guard = ProcessGuard::spawn(&mut command, kill_on_drop)?;
+        let child = guard.child();
+
+        let mut child_stdin = child.stdin.take();
+        let stdin = stdin.unwrap_or_default().to_vec();
+        // The input is written in a thread, like by
+        // `guard::output`, so that a program writing its output before
+        // reading its input cannot block.
+        let stdin_writer = thread::spawn(move || {
+            if let Some(child_stdin) = child_stdin.as_mut() {
+                // The program may exit without reading its input.
+                let _ = child_stdin.write_all(&stdin);
+            }
+
+            child_stdin
+        });
+
+        let (sender, lines) = mpsc::channel();
+        let mut child_stdout = BufReader::new(child.stdout.take().expect("No stdout"));
+
+        thread::spawn(move || loop {
+            let mut line = Vec::new();
+
+            match child_stdout.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut child_stderr = child.stderr.take().expect("No stderr");
+        let stderr = thread::spawn(move || {
+            let mut stderr = Vec::new();
+            child_stderr.read_to_end(&mut stderr).map(|_| stderr)
+        });
+
+        Ok(Self {
+            guard,
+            stdin: None,
+            stdin_writer: Some(stdin_writer),
+            lines,
+            stderr: Some(stderr),
+            stdout: Vec::new(),
+            sanitizer_logs,
+        })
+    }
+
+    /// Wait for the next line of the standard output matching
+    /// `predicate`, and return it (without the new line). Previous
+    /// lines not matching `predicate` are skipped. It panics if no
+    /// line matches within `timeout`, or if the standard output is
+    /// closed.
+    ///
+    /// ```rust
+    /// use inline_c::{assert_c, predicates::*};
+    /// use std::time::Duration;
+    ///
+    /// fn test_expect_line_within() {
+    ///     let mut program = (assert_c! {
+    ///         #include <stdio.h>
+    ///
+    ///         int main() {
+    ///             printf("ready\n");
+    ///             fflush(stdout);
+    ///
+    ///             // Wait for the standard input to be closed.
+    ///             while (getchar() != EOF);
+    ///
+    ///             return 0;
+    ///         }
+    ///     })
+    ///     .spawn();
+    ///
+    ///     program.expect_line_within(predicate::eq("ready"), Duration::from_secs(10));
+    ///     program.wait().success();
+    /// }
+    ///
+    /// # fn main() { test_expect_line_within() }
+    /// ```
+    pub fn expect_line_within<P>(&mut self, predicate: P, timeout: Duration) -> String
+    where
+        P: Predicate<str>,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut skipped = Vec::new();
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) => {
+                    self.stdout.extend_from_slice(&line);
+
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches(&['\r', '\n'][..]);
+
+                    if predicate.eval(line) {
+                        return line.to_string();
+                    }
+
+                    skipped.push(line.to_string());
+                }
+
+                Err(error) => panic!(
+                    "No line matching `{}` {}\n\nskipped lines:\n{}",
+                    predicate,
+                    match error {
+                        RecvTimeoutError::Timeout => format!("within {:?}", timeout),
+                        RecvTimeoutError::Disconnected => String::from("before the end of stdout"),
+                    },
+                    skipped.join("\n")
+                ),
+            }
+        }
+    }
+
+    /// Write `buffer` to the standard input of the program.
+    pub fn write_stdin<B: AsRef<[u8]>>(&mut self, buffer: B) -> &mut Self {
+        self.stdin()
+            .expect("The standard input is closed")
+            .write_all(buffer.as_ref())
+            .unwrap_or_else(|error| panic!("Failed to write to stdin: {}", error));
+
+        self
+    }
+
+    /// Kill the program, and the processes it has spawned, and wait
+    /// for it.
+    pub fn kill(mut self) -> assert_cmd::assert::Assert {
+        self.guard.kill();
+
+        self.wait()
+    }
+
+    /// Close the standard input, wait for the program to exit, and
+    /// assert the whole output with [`assert_cmd`].
+    pub fn wait(mut self) -> assert_cmd::assert::Assert {
+        self.stdin();
+        drop(self.stdin.take());
+
+        let status = self
+            .guard
+            .wait()
+            .unwrap_or_else(|error| panic!("Failed to wait for the program: {}", error));
+
+        // The reader thread stops at the end of the standard output.
+        for line in self.lines.iter() {
+            self.stdout.extend_from_slice(&line);
+        }
+
+        let mut stderr = self
+            .stderr
+            .take()
+            .unwrap()
+            .join()
+            .expect("The stderr reader has panicked")
+            .unwrap_or_else(|error| panic!("Failed to read stderr: {}", error));
+
+        // The reports of the sanitizers are appended to the standard
+        // error, like by `Assert::assert`.
+        if let Some(sanitizer_logs) = &self.sanitizer_logs {
+            stderr.extend(sanitizer::take_logs(sanitizer_logs).into_bytes());
+        }
+
+        assert_cmd::assert::Assert::new(Output {
+            status,
+            stdout: std::mem::take(&mut self.stdout),
+            stderr,
+        })
+    }
+
+    /// The standard input, once the initial input is written.
+    fn stdin(&mut self) -> Option<&mut ChildStdin> {
+        if let Some(stdin_writer) = self.stdin_writer.take() {
+            self.stdin = stdin_writer.join().expect("The stdin writer has panicked");
+        }
+
+        self.stdin.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        predicates::*,
+        run::{run, Language},
+    };
+    use std::{panic, time::Duration};
+
+    fn blocking_program() -> crate::Assert {
+        run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    char line[32];
+
+                    printf("step 1\n");
+                    printf("ready\n");
+                    fflush(stdout);
+
+                    while (fgets(line, sizeof(line), stdin) != NULL) {
+                        printf("echo %s", line);
+                        fflush(stdout);
+                    }
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_expect_line_within() {
+        let mut program = blocking_program().spawn();
+
+        assert_eq!(
+            program.expect_line_within(predicate::eq("ready"), Duration::from_secs(10)),
+            "ready"
+        );
+
+        program.write_stdin("foo\n");
+        program.expect_line_within(predicate::eq("echo foo"), Duration::from_secs(10));
+
+        program.wait().success().stdout("step 1\nready\necho foo\n");
+    }
+
+    #[test]
+    fn test_expect_line_within_timeout() {
+        let mut program = blocking_program().spawn();
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            program.expect_line_within(predicate::eq("never"), Duration::from_millis(200));
+        }));
+
+        assert!(result.is_err());
+        program.kill().failure();
+    }
+
+    #[test]
+    fn test_spawn_large_stdin() {
+        // The program writes more than a pipe can hold before reading
+        // its input.
+        let mut program = run(
+            Language::C,
+            r#"
+                #include <stdio.h>
+
+                int main() {
+                    long count = 0;
+
+                    for (int i = 0; i < 256 * 1024; ++i) {
+                        putchar('.');
+                    }
+
+                    printf("\n");
+
+                    while (count < 1024 * 1024 && getchar() != EOF) {
+                        ++count;
+                    }
+
+                    printf("%ld\n", count);
+
+                    return 0;
+                }
+            "#,
+        )
+        .unwrap();
+        program.stdin(vec![b'x'; 1024 * 1024]);
+
+        let mut program = program.spawn();
+
+        program.expect_line_within(predicate::eq("1048576"), Duration::from_secs(30));
+        program.wait().success();
+    }
+}
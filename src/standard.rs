@@ -0,0 +1,216 @@
+//! The language standard of the program, set with the special
+//! `standard` directive, e.g. `c11`, `gnu11`, `c23` or `c++20`.
+//!
+//! The compiler is probed before the program is compiled, so that a
+//! standard unknown to an old toolchain fails with a clear error
+//! instead of a cryptic compilation error.
+
+use crate::{run::Language, temp};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, error::Error, fmt, io::prelude::*, path::PathBuf, sync::Mutex};
+
+lazy_static! {
+    // Whether a compiler, identified by its path, supports a flag.
+    static ref PROBES: Mutex<HashMap<(PathBuf, String), bool>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Standard {
+    cxx: bool,
+    /// Whether the GNU extensions are enabled.
+    gnu: bool,
+    /// The last 2 digits of the year of the standard.
+    year: u8,
+}
+
+impl Standard {
+    pub(crate) fn parse(standard: &str) -> Result<Self, Box<dyn Error>> {
+        let (gnu, rest) = match standard.trim() {
+            standard if standard.starts_with("gnu") => (true, &standard[3..]),
+            standard if standard.starts_with('c') => (false, &standard[1..]),
+            _ => return Err(invalid(standard)),
+        };
+
+        let (cxx, year) = match rest.strip_prefix("++") {
+            Some(year) => (true, year),
+            None => (false, rest),
+        };
+
+        let year = match (cxx, year) {
+            (false, "89") | (false, "90") => 89,
+            (false, "99") => 99,
+            (false, "11") => 11,
+            (false, "17") | (false, "18") => 17,
+            (false, "23") | (false, "2x") => 23,
+            (true, "11") => 11,
+            (true, "14") => 14,
+            (true, "17") => 17,
+            (true, "20") => 20,
+            (true, "23") | (true, "2b") => 23,
+            _ => return Err(invalid(standard)),
+        };
+
+        Ok(Self { cxx, gnu, year })
+    }
+
+    /// The compiler flag selecting the standard.
+    pub(crate) fn flag(&self, language: &Language, msvc: bool) -> Result<String, Box<dyn Error>> {
+        match (language, self.cxx) {
+            (Language::C, true) => {
+                return Err(format!("`{}` is a C++ standard, the program is in C", self).into())
+            }
+            (Language::Cxx, false) => {
+                return Err(format!("`{}` is a C standard, the program is in C++", self).into())
+            }
+            #[cfg(feature = "cuda")]
+            (Language::Cuda, false) => {
+                return Err(format!("`{}` is a C standard, the program is in CUDA", self).into())
+            }
+            _ => (),
+        }
+
+        if !msvc {
+            // The provisional names are understood by more compilers.
+            return Ok(match (self.cxx, self.year) {
+                (false, 23) => format!("-std={}2x", if self.gnu { "gnu" } else { "c" }),
+                (true, 23) => format!("-std={}++2b", if self.gnu { "gnu" } else { "c" }),
+                _ => format!("-std={}", self),
+            });
+        }
+
+        if self.gnu {
+            return Err("The GNU extensions are not supported by MSVC".into());
+        }
+
+        Ok(match (self.cxx, self.year) {
+            (false, 11) | (false, 17) | (true, 14) | (true, 17) | (true, 20) => {
+                format!("/std:{}", self)
+            }
+            (false, 23) => String::from("/std:clatest"),
+            (true, 23) => String::from("/std:c++latest"),
+            _ => return Err(format!("The `{}` standard is not supported by MSVC", self).into()),
+        })
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{}{}{:02}",
+            if self.gnu { "gnu" } else { "c" },
+            if self.cxx { "++" } else { "" },
+            self.year
+        )
+    }
+}
+
+fn invalid(standard: &str) -> Box<dyn Error> {
+    format!(
+        "Invalid `standard` value `{}`, expected e.g. `c11`, `gnu17`, `c23` or `c++20`",
+        standard
+    )
+    .into()
+}
+
+/// Check that `compiler` supports `flag`, by compiling an empty
+/// program. The result is cached per compiler.
+pub(crate) fn probe(
+    compiler: &cc::Tool,
+    language: &Language,
+    flag: &str,
+) -> Result<(), Box<dyn Error>> {
+    let key = (compiler.path().to_path_buf(), flag.to_string());
+
+    if let Some(supported) = PROBES
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .get(&key)
+    {
+        return if *supported {
+            Ok(())
+        } else {
+            Err(unsupported(compiler, flag))
+        };
+    }
+
+    let mut probe_file = tempfile::Builder::new()
+        .prefix("inline-c-rs-probe-")
+        .suffix(&format!(".{}", language.to_string()))
+        .tempfile_in(temp::temp_dir())?;
+    probe_file.write_all(b"int main(void) { return 0; }\n")?;
+
+    let mut command = compiler.to_command();
+    command.arg(flag);
+
+    if compiler.is_like_msvc() {
+        command.arg("/Zs");
+    } else {
+        command.arg("-fsyntax-only");
+    }
+
+    let output = command.arg(probe_file.path()).output()?;
+    // MSVC only warns about unknown options (`D9002`).
+    let supported = output.status.success()
+        && !String::from_utf8_lossy(&output.stderr).contains("D9002")
+        && !String::from_utf8_lossy(&output.stdout).contains("D9002");
+
+    PROBES
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .insert(key, supported);
+
+    if supported {
+        Ok(())
+    } else {
+        Err(unsupported(compiler, flag))
+    }
+}
+
+fn unsupported(compiler: &cc::Tool, flag: &str) -> Box<dyn Error> {
+    format!(
+        "The compiler `{}` does not support `{}`, it is probably too old for the `standard` \
+         variable",
+        compiler.path().display(),
+        flag
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_flag() {
+        let flag = |standard: &str, language, msvc| {
+            Standard::parse(standard)
+                .and_then(|standard| standard.flag(&language, msvc))
+                .ok()
+        };
+
+        assert_eq!(
+            flag("gnu11", Language::C, false).as_deref(),
+            Some("-std=gnu11")
+        );
+        assert_eq!(flag("c23", Language::C, false).as_deref(), Some("-std=c2x"));
+        assert_eq!(flag("c90", Language::C, false).as_deref(), Some("-std=c89"));
+        assert_eq!(
+            flag("c++23", Language::Cxx, false).as_deref(),
+            Some("-std=c++2b")
+        );
+        assert_eq!(
+            flag("c23", Language::C, true).as_deref(),
+            Some("/std:clatest")
+        );
+        assert_eq!(
+            flag("c++17", Language::Cxx, true).as_deref(),
+            Some("/std:c++17")
+        );
+        assert_eq!(flag("gnu11", Language::C, true), None);
+        assert_eq!(flag("c99", Language::C, true), None);
+        assert_eq!(flag("c++17", Language::C, false), None);
+        assert_eq!(flag("c12", Language::C, false), None);
+        assert_eq!(flag("ansi", Language::C, false), None);
+    }
+}
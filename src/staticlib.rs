@@ -0,0 +1,178 @@
+//! Link the program against a Rust crate built as a `staticlib`.
+//!
+//! The static library is linked as a whole archive, so that all the
+//! exported symbols are kept, and with the system libraries required
+//! by the Rust standard library on the target.
+
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone)]
+pub(crate) struct StaticLib {
+    path: PathBuf,
+}
+
+impl StaticLib {
+    /// Find the static library from the special `staticlib` directive:
+    /// either a path to the library, or the name of a crate. The
+    /// library of a crate is looked up in the target directory of the
+    /// running test, e.g. `target/debug/lib<crate>.a`.
+    pub(crate) fn find(staticlib: &str, msvc: bool) -> Result<Self, Box<dyn Error>> {
+        let path = Path::new(staticlib);
+
+        if path.components().count() > 1 || path.extension().is_some() {
+            return if path.is_file() {
+                Ok(Self {
+                    path: path.to_path_buf(),
+                })
+            } else {
+                Err(format!("The static library `{}` does not exist", path.display()).into())
+            };
+        }
+
+        let file_name = library_file_name(staticlib, msvc);
+        let current_exe = env::current_exe()?;
+
+        // Tests live in `target/<profile>/deps/`, and libraries in
+        // `target/<profile>/`.
+        current_exe
+            .ancestors()
+            .skip(1)
+            .take(2)
+            .map(|directory| directory.join(&file_name))
+            .find(|path| path.is_file())
+            .map(|path| Self { path })
+            .ok_or_else(|| {
+                format!(
+                    "The static library `{}` of the crate `{}` is not found next to `{}`; \
+                     is the crate built with `crate-type = [\"staticlib\"]`?",
+                    file_name,
+                    staticlib,
+                    current_exe.display()
+                )
+                .into()
+            })
+    }
+
+    /// The flags linking the static library and the system libraries
+    /// it needs on `target`. They must come after the input file.
+    pub(crate) fn linker_flags(&self, target: &str) -> Vec<OsString> {
+        let mut flags = Vec::new();
+
+        if target.contains("msvc") {
+            flags.push(self.path.clone().into_os_string());
+            flags.extend(system_libraries(target).iter().map(OsString::from));
+
+            // Everything after `/link` is given to the linker.
+            let mut whole_archive = OsString::from("/WHOLEARCHIVE:");
+            whole_archive.push(&self.path);
+            flags.push("/link".into());
+            flags.push(whole_archive);
+        } else if target.contains("apple") {
+            let mut force_load = OsString::from("-Wl,-force_load,");
+            force_load.push(&self.path);
+            flags.push(force_load);
+            flags.extend(system_libraries(target).iter().map(OsString::from));
+        } else {
+            flags.push("-Wl,--whole-archive".into());
+            flags.push(self.path.clone().into_os_string());
+            flags.push("-Wl,--no-whole-archive".into());
+            flags.extend(system_libraries(target).iter().map(OsString::from));
+        }
+
+        flags
+    }
+}
+
+fn library_file_name(crate_name: &str, msvc: bool) -> String {
+    let crate_name = crate_name.replace('-', "_");
+
+    if msvc {
+        format!("{}.lib", crate_name)
+    } else {
+        format!("lib{}.a", crate_name)
+    }
+}
+
+/// The system libraries needed by the Rust standard library, as
+/// printed by `rustc --print native-static-libs`.
+fn system_libraries(target: &str) -> &'static [&'static str] {
+    if target.contains("msvc") {
+        &[
+            "kernel32.lib",
+            "advapi32.lib",
+            "bcrypt.lib",
+            "ntdll.lib",
+            "userenv.lib",
+            "ws2_32.lib",
+        ]
+    } else if target.contains("windows") {
+        &[
+            "-lkernel32",
+            "-ladvapi32",
+            "-lbcrypt",
+            "-lntdll",
+            "-luserenv",
+            "-lws2_32",
+        ]
+    } else if target.contains("apple") {
+        &["-liconv", "-lSystem", "-lc", "-lm"]
+    } else if target.contains("android") {
+        &["-ldl", "-llog", "-lc", "-lm"]
+    } else {
+        &[
+            "-lgcc_s",
+            "-lutil",
+            "-lrt",
+            "-lpthread",
+            "-lm",
+            "-ldl",
+            "-lc",
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linker_flags() {
+        let staticlib = StaticLib {
+            path: PathBuf::from("libfoo.a"),
+        };
+
+        let flags = staticlib.linker_flags("x86_64-unknown-linux-gnu");
+        assert_eq!(
+            &flags[..3],
+            ["-Wl,--whole-archive", "libfoo.a", "-Wl,--no-whole-archive"]
+        );
+        assert!(flags.iter().any(|flag| flag == "-lpthread"));
+
+        let flags = staticlib.linker_flags("aarch64-apple-darwin");
+        assert_eq!(flags[0], "-Wl,-force_load,libfoo.a");
+
+        let staticlib = StaticLib {
+            path: PathBuf::from("foo.lib"),
+        };
+        let flags = staticlib.linker_flags("x86_64-pc-windows-msvc");
+        assert_eq!(flags[0], "foo.lib");
+        assert!(flags.iter().any(|flag| flag == "ws2_32.lib"));
+        assert_eq!(
+            &flags[flags.len() - 2..],
+            ["/link", "/WHOLEARCHIVE:foo.lib"]
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(library_file_name("foo-bar", false), "libfoo_bar.a");
+        assert_eq!(library_file_name("foo-bar", true), "foo_bar.lib");
+        assert!(StaticLib::find("inline-c-rs-missing-crate", false).is_err());
+        assert!(StaticLib::find("missing/libfoo.a", false).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+//! Expectations on the standard error of programs.
+
+/// What the standard error of a program is expected to be.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StderrExpectation {
+    /// In strict mode, the standard error must contain nothing but
+    /// the expected text.
+    pub(crate) strict: bool,
+    /// Text expected in the standard error, from the `expect_stderr`
+    /// directive.
+    pub(crate) expected: Option<String>,
+}
+
+impl StderrExpectation {
+    /// Check `stderr` against the expectation.
+    pub(crate) fn check(&self, stderr: &str) -> Result<(), String> {
+        let stderr = stderr.replace("\r\n", "\n");
+        let mut unexpected = stderr.as_str();
+        let remainder;
+
+        if let Some(expected) = &self.expected {
+            match stderr.find(expected.as_str()) {
+                Some(index) => {
+                    remainder =
+                        format!("{}{}", &stderr[..index], &stderr[index + expected.len()..]);
+                    unexpected = &remainder;
+                }
+
+                None => {
+                    return Err(format!(
+                        "Expected `{}` on the standard error, got:\n{}",
+                        expected, stderr
+                    ))
+                }
+            }
+        }
+
+        if self.strict && !unexpected.trim().is_empty() {
+            return Err(format!(
+                "Unexpected output on the standard error (strict mode):\n{}",
+                stderr
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stderr_expectation() {
+        let lax = StderrExpectation::default();
+        let strict = StderrExpectation {
+            strict: true,
+            expected: None,
+        };
+        let expected = StderrExpectation {
+            strict: true,
+            expected: Some("warning: foo".to_string()),
+        };
+
+        assert!(lax.check("anything").is_ok());
+        assert!(strict.check("").is_ok());
+        assert!(strict.check("oops\n").is_err());
+        assert!(expected.check("warning: foo\n").is_ok());
+        assert!(expected.check("warning: foo\nwarning: bar\n").is_err());
+        assert!(expected.check("").is_err());
+    }
+}
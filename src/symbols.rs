@@ -0,0 +1,121 @@
+//! List the symbols exported by the compiled program, with `nm` on
+//! Linux and macOS, and `dumpbin /EXPORTS` on Windows.
+
+use std::{error::Error, path::Path, process::Command};
+
+/// The symbols defined by the linker, and exported by older toolchains
+/// from any shared object.
+const LINKER_SYMBOLS: &[&str] = &["_init", "_fini", "_edata", "_end", "__bss_start"];
+
+/// The symbols exported by `path`, from its dynamic symbol table,
+/// without the ones of the linker and of `inline-c` (e.g. the function
+/// pointers of the callbacks), sorted.
+pub(crate) fn exported_symbols(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let (tool, output) = if cfg!(target_os = "windows") {
+        (
+            "dumpbin",
+            Command::new("dumpbin").arg("/EXPORTS").arg(path).output(),
+        )
+    } else if cfg!(target_os = "macos") {
+        (
+            "nm",
+            Command::new("nm")
+                .args(["-g", "-U", "-P"])
+                .arg(path)
+                .output(),
+        )
+    } else {
+        (
+            "nm",
+            Command::new("nm")
+                .args(["-D", "-P", "--defined-only"])
+                .arg(path)
+                .output(),
+        )
+    };
+
+    let output = output.map_err(|error| format!("Failed to run `{}`: {}", tool, error))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "`{}` has failed: {}",
+            tool,
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut symbols = if cfg!(target_os = "windows") {
+        parse_dumpbin(&stdout)
+    } else {
+        // The C symbols are prefixed by an underscore on macOS.
+        parse_nm(&stdout)
+            .into_iter()
+            .map(|symbol| {
+                if cfg!(target_os = "macos") {
+                    symbol.strip_prefix('_').unwrap_or(&symbol).to_string()
+                } else {
+                    symbol
+                }
+            })
+            .collect()
+    };
+
+    symbols.retain(|symbol| {
+        !LINKER_SYMBOLS.contains(&symbol.as_str()) && !symbol.starts_with("inline_c_rs_")
+    });
+    symbols.sort();
+    symbols.dedup();
+
+    Ok(symbols)
+}
+
+/// Parse the POSIX format of `nm`, i.e. `name type value size` lines.
+fn parse_nm(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Parse the `ordinal hint RVA name` lines of `dumpbin /EXPORTS`.
+fn parse_dumpbin(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("ordinal"))
+        .skip(1)
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_whitespace().nth(3))
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_nm("foo_free T 110f 7\nfoo_new T 10f9 b\n"),
+            ["foo_free", "foo_new"]
+        );
+        assert_eq!(
+            parse_dumpbin(
+                "Dump of file inline-c-rs.dll\n\
+                 \n\
+                 \x20   ordinal hint RVA      name\n\
+                 \n\
+                 \x20         1    0 00001000 foo_free\n\
+                 \x20         2    1 00001010 foo_new\n\
+                 \n\
+                 \x20 Summary\n"
+            ),
+            ["foo_free", "foo_new"]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! The directory of the temporary files given to the compiler.
+//!
+//! MSVC fails on paths with non-ASCII characters, e.g. in a localized
+//! user name, or longer than `MAX_PATH`. On Windows, such a temporary
+//! directory is replaced by a short build directory at the root of
+//! the system drive, or else by its extended-length (`\\?\`) form.
+
+use lazy_static::lazy_static;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The maximum length of a path on Windows, without the extended-length
+/// prefix.
+const MAX_PATH: usize = 260;
+
+/// The room left for the names of the temporary files.
+const FILE_NAME_LENGTH: usize = 64;
+
+lazy_static! {
+    static ref TEMP_DIR: PathBuf = {
+        let temp_dir = env::temp_dir();
+
+        if cfg!(windows) && !is_supported_by_compilers(&temp_dir) {
+            short_temp_dir().unwrap_or_else(|| extended_path(&temp_dir))
+        } else {
+            temp_dir
+        }
+    };
+}
+
+/// The directory where the temporary files given to the compiler are
+/// created.
+pub(crate) fn temp_dir() -> &'static Path {
+    &TEMP_DIR
+}
+
+/// Whether the files of `directory` can be given to all the compilers.
+fn is_supported_by_compilers(directory: &Path) -> bool {
+    let directory = directory.to_string_lossy();
+
+    directory.is_ascii() && directory.len() + FILE_NAME_LENGTH < MAX_PATH
+}
+
+/// `%SystemDrive%\inline-c-rs`, if it can be created.
+fn short_temp_dir() -> Option<PathBuf> {
+    let mut directory = PathBuf::from(env::var_os("SystemDrive").unwrap_or_else(|| "C:".into()));
+    directory.push("\\inline-c-rs");
+
+    fs::create_dir_all(&directory).ok()?;
+
+    Some(directory).filter(|directory| is_supported_by_compilers(directory))
+}
+
+/// The extended-length form of the absolute `path`, i.e. `\\?\C:\…`
+/// or `\\?\UNC\server\share\…`, which is not limited to `MAX_PATH`.
+fn extended_path(path: &Path) -> PathBuf {
+    let path = path.to_string_lossy();
+
+    if path.starts_with(r"\\?\") {
+        path.into_owned().into()
+    } else if let Some(unc_path) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", unc_path).into()
+    } else {
+        format!(r"\\?\{}", path).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_supported_by_compilers() {
+        assert!(is_supported_by_compilers(Path::new(
+            r"C:\Users\ivan\AppData\Local\Temp"
+        )));
+        assert!(!is_supported_by_compilers(Path::new(
+            r"C:\Users\Иван\AppData\Local\Temp"
+        )));
+        assert!(!is_supported_by_compilers(
+            &Path::new(r"C:\").join("a".repeat(200))
+        ));
+    }
+
+    #[test]
+    fn test_extended_path() {
+        assert_eq!(
+            extended_path(Path::new(r"C:\Temp")),
+            Path::new(r"\\?\C:\Temp")
+        );
+        assert_eq!(
+            extended_path(Path::new(r"\\server\share\Temp")),
+            Path::new(r"\\?\UNC\server\share\Temp")
+        );
+        assert_eq!(
+            extended_path(Path::new(r"\\?\C:\Temp")),
+            Path::new(r"\\?\C:\Temp")
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Detect the C and C++ compilers, their kind and their version, e.g.
+//! to skip a test when the compiler is too old.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::HashMap, error::Error, fmt, path::PathBuf, process::Command, sync::Mutex};
+
+/// The kind of a compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompilerKind {
+    Gcc,
+    Clang,
+    /// The Clang shipped by Apple, whose versions differ from the
+    /// upstream ones.
+    AppleClang,
+    Msvc,
+    Unknown,
+}
+
+impl fmt::Display for CompilerKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::Gcc => "gcc",
+            Self::Clang => "clang",
+            Self::AppleClang => "apple-clang",
+            Self::Msvc => "msvc",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+impl CompilerKind {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "gcc" => Self::Gcc,
+            "clang" => Self::Clang,
+            "apple-clang" => Self::AppleClang,
+            "msvc" => Self::Msvc,
+            _ => return None,
+        })
+    }
+}
+
+/// The version of a compiler, e.g. `12.2.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompilerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CompilerVersion {
+    /// Find the first version in `string`, e.g. in the output of
+    /// `gcc --version`.
+    fn find(string: &str) -> Option<Self> {
+        lazy_static! {
+            static ref VERSION: Regex = Regex::new(r"\b(\d+)(?:\.(\d+))?(?:\.(\d+))?\b").unwrap();
+        }
+
+        let captures = VERSION.captures(string)?;
+        let number = |index| {
+            captures
+                .get(index)
+                .map_or(Some(0), |number: regex::Match| number.as_str().parse().ok())
+        };
+
+        Some(Self {
+            major: number(1)?,
+            minor: number(2)?,
+            patch: number(3)?,
+        })
+    }
+}
+
+impl fmt::Display for CompilerVersion {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A detected compiler.
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    pub kind: CompilerKind,
+    /// The version, if it has been found.
+    pub version: Option<CompilerVersion>,
+    pub path: PathBuf,
+}
+
+impl Compiler {
+    pub(crate) fn detect(tool: &cc::Tool) -> Self {
+        let output = if tool.is_like_msvc() {
+            // `cl.exe` prints its version when run without argument.
+            tool.to_command().output()
+        } else {
+            Command::new(tool.path()).arg("--version").output()
+        };
+        let output = output
+            .map(|output| {
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+                text
+            })
+            .unwrap_or_default();
+
+        let kind = if tool.is_like_msvc() {
+            CompilerKind::Msvc
+        } else if tool.is_like_clang() && output.contains("Apple") {
+            CompilerKind::AppleClang
+        } else if tool.is_like_clang() {
+            CompilerKind::Clang
+        } else if tool.is_like_gnu() {
+            CompilerKind::Gcc
+        } else {
+            CompilerKind::Unknown
+        };
+
+        // Skip the name of the compiler, e.g. `g++-12 (…) 12.2.0`.
+        let version = output
+            .lines()
+            .find_map(|line| {
+                line.find(|character: char| character.is_whitespace())
+                    .and_then(|index| CompilerVersion::find(&line[index..]))
+            })
+            .filter(|_| kind != CompilerKind::Unknown);
+
+        Self {
+            kind,
+            version,
+            path: tool.path().to_path_buf(),
+        }
+    }
+}
+
+/// Detect the compiler of `tool`, once per path.
+pub(crate) fn detect_once(tool: &cc::Tool) -> Compiler {
+    lazy_static! {
+        static ref COMPILERS: Mutex<HashMap<PathBuf, Compiler>> = Mutex::new(HashMap::new());
+    }
+
+    COMPILERS
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .entry(tool.path().to_path_buf())
+        .or_insert_with(|| Compiler::detect(tool))
+        .clone()
+}
+
+impl fmt::Display for Compiler {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(formatter, "{} {}", self.kind, version),
+            None => write!(formatter, "{} (unknown version)", self.kind),
+        }
+    }
+}
+
+/// The toolchain used to compile the programs, see
+/// [`toolchain_info`].
+#[derive(Debug, Clone)]
+pub struct ToolchainInfo {
+    /// The C compiler, if it has been found.
+    pub c: Option<Compiler>,
+    /// The C++ compiler, if it has been found.
+    pub cxx: Option<Compiler>,
+    /// The target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub target: String,
+}
+
+/// Detect the toolchain used to compile the programs. The detection
+/// runs once per process.
+///
+/// ```rust
+/// use inline_c::{assert_c, toolchain_info, CompilerKind};
+///
+/// fn test_toolchain_info() {
+///     let toolchain = toolchain_info();
+///
+///     if let Some(compiler) = &toolchain.c {
+///         if compiler.kind == CompilerKind::Gcc
+///             && compiler.version.map_or(true, |version| version.major < 5)
+///         {
+///             // Too old, skip the test.
+///             return;
+///         }
+///     }
+///
+///     (assert_c! {
+///         int main() {
+///             return 0;
+///         }
+///     })
+///     .success();
+/// }
+///
+/// # fn main() { test_toolchain_info() }
+/// ```
+pub fn toolchain_info() -> &'static ToolchainInfo {
+    lazy_static! {
+        static ref TOOLCHAIN_INFO: ToolchainInfo = {
+            let target = target_lexicon::HOST.to_string();
+            let compiler = |cpp| {
+                cc::Build::new()
+                    .cargo_metadata(false)
+                    .host(&target)
+                    .target(&target)
+                    .opt_level(1)
+                    .debug(false)
+                    .cpp(cpp)
+                    .try_get_compiler()
+                    .ok()
+                    .map(|tool| Compiler::detect(&tool))
+            };
+
+            ToolchainInfo {
+                c: compiler(false),
+                cxx: compiler(true),
+                target: target.clone(),
+            }
+        };
+    }
+
+    &TOOLCHAIN_INFO
+}
+
+/// The `min_compiler` directive: a comma-separated list of minimum
+/// versions per compiler kind, e.g. `gcc 12, clang 15`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct MinCompiler(Vec<(CompilerKind, CompilerVersion)>);
+
+impl MinCompiler {
+    pub(crate) fn parse(min_compiler: &str) -> Result<Self, Box<dyn Error>> {
+        min_compiler
+            .split(',')
+            .map(|requirement| {
+                let invalid = || {
+                    format!(
+                        "Invalid `min_compiler` value `{}`, expected e.g. `gcc 12, clang 15`",
+                        min_compiler
+                    )
+                };
+
+                let (kind, version) = requirement.trim().split_once(' ').ok_or_else(invalid)?;
+                let kind = CompilerKind::from_name(kind).ok_or_else(invalid)?;
+                let version = version.trim();
+                let version = CompilerVersion::find(version)
+                    .filter(|_| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+                    .ok_or_else(invalid)?;
+
+                Ok((kind, version))
+            })
+            .collect::<Result<_, String>>()
+            .map(Self)
+            .map_err(Into::into)
+    }
+
+    /// Check that `compiler` is listed, and is recent enough.
+    pub(crate) fn check(&self, compiler: &Compiler) -> Result<(), Box<dyn Error>> {
+        let satisfied = self.0.iter().any(|(kind, minimum)| {
+            *kind == compiler.kind && compiler.version.is_some_and(|version| version >= *minimum)
+        });
+
+        if satisfied {
+            return Ok(());
+        }
+
+        Err(format!(
+            "The program requires {}, but the compiler is {}",
+            self.0
+                .iter()
+                .map(|(kind, version)| format!("{} >= {}", kind, version))
+                .collect::<Vec<_>>()
+                .join(", "),
+            compiler
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiler_version_find() {
+        assert_eq!(
+            CompilerVersion::find("(Ubuntu 11.4.0-1ubuntu1~22.04) 11.4.0"),
+            Some(CompilerVersion {
+                major: 11,
+                minor: 4,
+                patch: 0
+            })
+        );
+        assert_eq!(
+            CompilerVersion::find("version 19.29"),
+            Some(CompilerVersion {
+                major: 19,
+                minor: 29,
+                patch: 0
+            })
+        );
+        assert_eq!(CompilerVersion::find("none"), None);
+    }
+
+    #[test]
+    fn test_min_compiler() {
+        let min_compiler = MinCompiler::parse("gcc 12, clang 15.0.1").unwrap();
+        let compiler = |kind, major| Compiler {
+            kind,
+            version: Some(CompilerVersion {
+                major,
+                minor: 0,
+                patch: 0,
+            }),
+            path: PathBuf::new(),
+        };
+
+        assert!(min_compiler.check(&compiler(CompilerKind::Gcc, 12)).is_ok());
+        assert!(min_compiler
+            .check(&compiler(CompilerKind::Gcc, 11))
+            .is_err());
+        assert!(min_compiler
+            .check(&compiler(CompilerKind::Clang, 15))
+            .is_err());
+        assert!(min_compiler
+            .check(&compiler(CompilerKind::Msvc, 20))
+            .is_err());
+
+        assert!(MinCompiler::parse("gcc").is_err());
+        assert!(MinCompiler::parse("tcc 1").is_err());
+        assert!(MinCompiler::parse("gcc twelve").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! Trace the system calls (`strace`) or the library calls (`ltrace`)
+//! of programs.
+
+use std::{
+    error::Error,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A trace, written by a tracer when the program runs.
+#[derive(Debug, Clone)]
+pub(crate) struct Trace {
+    tracer: &'static str,
+    path: PathBuf,
+}
+
+impl Trace {
+    /// Create a trace for the program `program_path`. The trace is
+    /// written next to the program.
+    pub(crate) fn new(tracer: &str, program_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let tracer = match tracer {
+            "strace" => "strace",
+            "ltrace" => "ltrace",
+            tracer => {
+                return Err(
+                    format!("Invalid tracer `{}`, expected `strace` or `ltrace`", tracer).into(),
+                )
+            }
+        };
+
+        if !is_available(tracer) {
+            return Err(format!("The tracer requires `{}` to be installed", tracer).into());
+        }
+
+        let mut path = program_path.as_os_str().to_os_string();
+        path.push(".");
+        path.push(tracer);
+
+        Ok(Self {
+            tracer,
+            path: PathBuf::from(path),
+        })
+    }
+
+    /// The command and arguments that run the program under the
+    /// tracer, following forks.
+    pub(crate) fn launcher(&self) -> Vec<OsString> {
+        vec![
+            OsString::from(self.tracer),
+            OsString::from("-f"),
+            OsString::from("-o"),
+            self.path.clone().into_os_string(),
+        ]
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Whether `tracer` is installed.
+pub(crate) fn is_available(tracer: &str) -> bool {
+    Command::new(tracer)
+        .arg("-V")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
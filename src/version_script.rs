@@ -0,0 +1,265 @@
+//! Translate the list of the symbols exported by a shared object, a
+//! GNU version script (`.map`) or a module-definition file (`.def`),
+//! for the linker: GNU ld and lld, Apple ld, or MSVC.
+
+use crate::temp;
+use std::{
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The family of the linker, by the format of its list of exported
+/// symbols.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Linker {
+    /// GNU ld and lld, with version scripts.
+    Gnu,
+    /// Apple ld, with lists of exported symbols.
+    Apple,
+    /// MSVC, with module-definition files.
+    Msvc,
+}
+
+/// The flags applying the list of exported symbols at `path` to the
+/// linker, and the path of the list generated for the linker, if the
+/// format of `path` is not its own.
+pub(crate) fn linker_flags(
+    path: &Path,
+    linker: Linker,
+) -> Result<(Vec<String>, Option<PathBuf>), Box<dyn Error>> {
+    let is_def = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("def"));
+
+    match (linker, is_def) {
+        (Linker::Gnu, false) => {
+            return Ok((
+                vec![format!("-Wl,--version-script={}", path.display())],
+                None,
+            ))
+        }
+        // `cl` gives the `.def` files to the linker.
+        (Linker::Msvc, true) => return Ok((vec![path.display().to_string()], None)),
+        _ => (),
+    }
+
+    let content = fs::read_to_string(path).map_err(|error| {
+        format!(
+            "Failed to read the version script `{}`: {}",
+            path.display(),
+            error
+        )
+    })?;
+    let symbols = if is_def {
+        parse_def(&content)
+    } else {
+        parse_version_script(&content)
+    };
+
+    let (generated, suffix) = match linker {
+        Linker::Gnu => (
+            format!(
+                "{{\n  global:\n{}  local:\n    *;\n}};\n",
+                symbols
+                    .iter()
+                    .map(|symbol| format!("    {};\n", symbol))
+                    .collect::<String>()
+            ),
+            ".map",
+        ),
+        Linker::Apple => (
+            symbols
+                .iter()
+                .map(|symbol| format!("_{}\n", symbol))
+                .collect(),
+            ".exp",
+        ),
+        Linker::Msvc => {
+            if let Some(pattern) = symbols.iter().find(|symbol| symbol.contains(['*', '?'])) {
+                return Err(format!(
+                    "The pattern `{}` of the version script is not supported with MSVC",
+                    pattern
+                )
+                .into());
+            }
+
+            (
+                format!(
+                    "EXPORTS\n{}",
+                    symbols
+                        .iter()
+                        .map(|symbol| format!("    {}\n", symbol))
+                        .collect::<String>()
+                ),
+                ".def",
+            )
+        }
+    };
+
+    let mut generated_file = tempfile::Builder::new()
+        .prefix("inline-c-rs-exports-")
+        .suffix(suffix)
+        .tempfile_in(temp::temp_dir())?;
+    generated_file.write_all(generated.as_bytes())?;
+    let (_, generated_path) = generated_file.keep()?;
+
+    let flags = match linker {
+        Linker::Apple => vec![format!(
+            "-Wl,-exported_symbols_list,{}",
+            generated_path.display()
+        )],
+        Linker::Gnu => vec![format!("-Wl,--version-script={}", generated_path.display())],
+        Linker::Msvc => vec![generated_path.display().to_string()],
+    };
+
+    Ok((flags, Some(generated_path)))
+}
+
+/// The global symbols (or patterns) of a version script, e.g.
+/// `VERS_1 { global: foo_new; foo_free; local: *; };`. The symbols
+/// before any `global:` or `local:` label are global.
+fn parse_version_script(content: &str) -> Vec<String> {
+    let mut without_comments = String::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("/*") {
+        without_comments.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 2..]);
+    }
+
+    without_comments.push_str(rest);
+
+    let mut symbols = Vec::new();
+    let mut depth = 0;
+    let mut global = true;
+    let mut token = String::new();
+
+    for line in without_comments.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+
+        for character in line.chars().chain(Some(' ')) {
+            match character {
+                '{' => {
+                    depth += 1;
+                    global = true;
+                    token.clear();
+                }
+                '}' => {
+                    depth -= 1;
+                    token.clear();
+                }
+                ';' => {
+                    let symbol = token.trim();
+
+                    if depth > 0 && global && !symbol.is_empty() {
+                        symbols.push(symbol.to_string());
+                    }
+
+                    token.clear();
+                }
+                ':' if depth > 0 => {
+                    match token.trim() {
+                        "global" => global = true,
+                        "local" => global = false,
+                        _ => (),
+                    }
+
+                    token.clear();
+                }
+                character => token.push(character),
+            }
+        }
+    }
+
+    symbols
+}
+
+/// The symbols of the `EXPORTS` section of a module-definition file,
+/// without their ordinals and aliases, e.g. `foo_new @1`.
+fn parse_def(content: &str) -> Vec<String> {
+    const KEYWORDS: &[&str] = &[
+        "LIBRARY",
+        "NAME",
+        "HEAPSIZE",
+        "STACKSIZE",
+        "SECTIONS",
+        "VERSION",
+        "STUB",
+    ];
+
+    content
+        .lines()
+        .map(|line| line.split(';').next().unwrap_or_default().trim())
+        .skip_while(|line| !line.starts_with("EXPORTS"))
+        .skip(1)
+        .take_while(|line| {
+            !KEYWORDS
+                .iter()
+                .any(|keyword| line.split_whitespace().next() == Some(keyword))
+        })
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|symbol| symbol.split('=').next().unwrap_or(symbol).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_version_script(
+                "/* The public API. */\n\
+                 VERS_1 {\n\
+                 \x20 global:\n\
+                 \x20   foo_new; # Create a foo.\n\
+                 \x20   foo_free;\n\
+                 \x20 local:\n\
+                 \x20   *;\n\
+                 };\n"
+            ),
+            ["foo_new", "foo_free"]
+        );
+        assert_eq!(parse_version_script("{ foo_*; };"), ["foo_*"]);
+        assert_eq!(
+            parse_def(
+                "LIBRARY foo\n\
+                 EXPORTS\n\
+                 \x20   foo_new @1 ; Create a foo.\n\
+                 \x20   foo_free=internal_free\n"
+            ),
+            ["foo_new", "foo_free"]
+        );
+    }
+
+    #[test]
+    fn test_linker_flags() {
+        let directory = tempfile::tempdir().unwrap();
+        let def_path = directory.path().join("foo.def");
+        fs::write(&def_path, "EXPORTS\n    foo_new\n").unwrap();
+
+        let (flags, generated_path) = linker_flags(&def_path, Linker::Apple).unwrap();
+        let generated_path = generated_path.unwrap();
+
+        assert_eq!(
+            flags,
+            [format!(
+                "-Wl,-exported_symbols_list,{}",
+                generated_path.display()
+            )]
+        );
+        assert_eq!(fs::read_to_string(&generated_path).unwrap(), "_foo_new\n");
+
+        fs::remove_file(generated_path).unwrap();
+
+        assert_eq!(
+            linker_flags(&def_path, Linker::Msvc).unwrap(),
+            (vec![def_path.display().to_string()], None)
+        );
+    }
+}